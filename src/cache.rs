@@ -0,0 +1,249 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::*;
+use crate::rule::{Fix, FixOrigin, Fixes, Rule};
+
+const HEADER: &str = "rust-covfix-cache 2";
+
+/// counter to make the temporary file names unique within the process
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// On-disk cache of the fixes recorded by the rules
+///
+/// Entries are keyed by the hash of the source code, the rule configuration and the
+/// version of rust-covfix, so that unchanged files can be fixed without parsing. Only
+/// the fixes are cached because they do not depend on the coverage.
+#[derive(Clone, Debug)]
+pub struct FixCache {
+    dir: PathBuf,
+}
+
+/// Cached result of a source file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct CacheEntry {
+    pub parse_error: Option<String>,
+    /// fixes for each rule. `None` if the rule was not applied through the visitor.
+    pub fixes: Vec<Option<Fixes>>,
+}
+
+impl FixCache {
+    /// use the directory to store the cache. The directory is created on first write.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// remove all cache entries
+    pub fn clear(&self) -> Result<(), Error> {
+        if self.dir.is_dir() {
+            fs::remove_dir_all(&self.dir)
+                .chain_err(|| format!("Failed to remove cache directory {:?}", self.dir))?;
+        }
+        Ok(())
+    }
+
+    /// cache key of the source code. `None` if any rule cannot be cached.
+    pub(crate) fn key(rules: &[Box<dyn Rule>], content: &str) -> Option<String> {
+        let mut context = md5::Context::new();
+        context.consume(env!("CARGO_PKG_VERSION"));
+
+        for rule in rules {
+            context.consume(b"\0");
+            context.consume(rule.cache_key()?);
+        }
+
+        context.consume(b"\0\0");
+        context.consume(content);
+        Some(format!("{:x}", context.compute()))
+    }
+
+    pub(crate) fn load(&self, key: &str, num_rules: usize) -> Option<CacheEntry> {
+        let content = fs::read_to_string(self.dir.join(key)).ok()?;
+        parse_entry(&content, num_rules)
+    }
+
+    pub(crate) fn store(&self, key: &str, entry: &CacheEntry) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir)
+            .chain_err(|| format!("Failed to create cache directory {:?}", self.dir))?;
+
+        // write into the temporary file at first so that other processes (or threads)
+        // never read the incomplete entry
+        let path = self.dir.join(key);
+        let tmp_path = self.dir.join(format!(
+            "{}.{}-{}.tmp",
+            key,
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let res =
+            fs::write(&tmp_path, format_entry(entry)).and_then(|_| fs::rename(&tmp_path, &path));
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+
+        res.chain_err(|| format!("Failed to write cache entry {:?}", path))
+    }
+}
+
+fn format_entry(entry: &CacheEntry) -> String {
+    let mut s = String::new();
+    s.push_str(HEADER);
+    s.push('\n');
+    let _ = writeln!(s, "rules {}", entry.fixes.len());
+
+    if let Some(ref e) = entry.parse_error {
+        let _ = writeln!(s, "parse_error {}", e.replace('\n', " "));
+    }
+
+    for (i, fixes) in entry.fixes.iter().enumerate() {
+        let fixes = match fixes {
+            Some(fixes) => fixes,
+            None => continue,
+        };
+
+        let _ = writeln!(s, "rule {}", i);
+        let mut origins = fixes.origins().peekable();
+        for fix in fixes {
+            let _ = match *fix {
+                Fix::IgnoreRange { start, end } => writeln!(s, "range {} {}", start, end),
+                Fix::IgnoreLine(n) => writeln!(s, "line {}", n),
+                Fix::IgnoreBranches(n) => writeln!(s, "branches {}", n),
+                Fix::IgnoreUntakenBranch {
+                    line_number,
+                    executed_only,
+                } => writeln!(s, "untaken {} {}", line_number, executed_only as u8),
+            };
+
+            // the origin follows the fix
+            if let Some(&(_, origin)) = origins.peek().filter(|&&(f, _)| std::ptr::eq(f, fix)) {
+                let _ = writeln!(
+                    s,
+                    "origin {} {} {}",
+                    origin.span.0,
+                    origin.span.1,
+                    origin.reason.replace('\n', " ")
+                );
+                origins.next();
+            }
+        }
+    }
+
+    s.push_str("end\n");
+    s
+}
+
+/// parse the cache entry. `None` if the entry is broken or has different number of rules.
+fn parse_entry(content: &str, num_rules: usize) -> Option<CacheEntry> {
+    let mut lines = content.lines();
+    if lines.next()? != HEADER {
+        return None;
+    }
+
+    let mut entry = CacheEntry::default();
+    let mut current: Option<usize> = None;
+
+    for line in lines {
+        let (kind, args) = match line.find(' ') {
+            Some(pos) => (&line[..pos], &line[pos + 1..]),
+            None => (line, ""),
+        };
+
+        if kind == "end" {
+            return Some(entry).filter(|e| e.fixes.len() == num_rules);
+        }
+        if kind == "parse_error" {
+            entry.parse_error = Some(args.to_owned());
+            continue;
+        }
+        if kind == "rules" {
+            if args.parse::<usize>().ok()? != num_rules {
+                return None;
+            }
+            entry.fixes = vec![None; num_rules];
+            continue;
+        }
+        if kind == "origin" {
+            let mut args = args.splitn(3, ' ');
+            let start = args.next()?.parse().ok()?;
+            let end = args.next()?.parse().ok()?;
+            let origin = FixOrigin {
+                reason: args.next()?.to_owned(),
+                span: (start, end),
+            };
+
+            if !entry.fixes[current?].as_mut()?.set_last_origin(origin) {
+                return None;
+            }
+            continue;
+        }
+        if kind == "rule" {
+            let idx: usize = args.parse().ok()?;
+            *entry.fixes.get_mut(idx)? = Some(Fixes::new());
+            current = Some(idx);
+            continue;
+        }
+
+        let nums = args
+            .split(' ')
+            .map(|v| v.parse::<usize>().ok())
+            .collect::<Option<Vec<usize>>>()?;
+        let fix = match (kind, nums.as_slice()) {
+            ("range", &[start, end]) => Fix::IgnoreRange { start, end },
+            ("line", &[n]) => Fix::IgnoreLine(n),
+            ("branches", &[n]) => Fix::IgnoreBranches(n),
+            ("untaken", &[n, executed_only]) => Fix::IgnoreUntakenBranch {
+                line_number: n,
+                executed_only: executed_only != 0,
+            },
+            _ => return None,
+        };
+        entry.fixes[current?].as_mut()?.push(fix);
+    }
+
+    // truncated entry
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let mut fixes = Fixes::new();
+        fixes.ignore_range(3, 5);
+        fixes.push(Fix::IgnoreLine(7));
+        fixes.push_with_origin(
+            Fix::IgnoreBranches(8),
+            FixOrigin {
+                reason: "marked by `cov:ignore-branch`".to_owned(),
+                span: (8, 8),
+            },
+        );
+        fixes.push(Fix::IgnoreUntakenBranch {
+            line_number: 9,
+            executed_only: true,
+        });
+
+        let entry = CacheEntry {
+            parse_error: Some("unexpected token".to_owned()),
+            fixes: vec![Some(fixes), None, Some(Fixes::new())],
+        };
+
+        let content = format_entry(&entry);
+        assert_eq!(parse_entry(&content, 3), Some(entry));
+        assert_eq!(parse_entry(&content, 2), None);
+        assert_eq!(parse_entry(content.trim_end_matches("end\n"), 3), None);
+        assert_eq!(parse_entry("rust-covfix-cache 0\nend\n", 3), None);
+        assert_eq!(
+            parse_entry(&format!("{}\nrules 99999999999\nend\n", HEADER), 3),
+            None
+        );
+    }
+}
@@ -0,0 +1,556 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+use crate::error::*;
+use crate::fix::RuleScope;
+use crate::rule::{Rule, RuleRegistry};
+
+#[cfg(feature = "pattern")]
+use crate::pattern::PatternRule;
+#[cfg(feature = "plugin")]
+use crate::plugin::PluginRule;
+
+/// name of the configuration file searched in the project root
+pub const CONFIG_FILE_NAME: &str = "covfix.toml";
+
+/// Minimum coverage required after fixing (in percent)
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Thresholds {
+    pub line: Option<f64>,
+    pub branch: Option<f64>,
+}
+
+/// Rules for the files matching the path patterns
+///
+/// If `rules` starts with `+` or `-`, the rules are added to or removed from the rule set
+/// of the whole project.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RuleOverride {
+    /// glob patterns relative to the project root
+    pub paths: Vec<String>,
+    pub rules: Option<String>,
+    /// rule options in `RULE.KEY=VALUE` format, applied after the project options
+    pub rule_options: Vec<String>,
+}
+
+impl RuleOverride {
+    /// rule specification combined with the one of the whole project
+    pub fn rules_spec(&self, base: Option<&str>) -> Option<String> {
+        match (&self.rules, base) {
+            (Some(spec), Some(base)) if spec.starts_with(['+', '-']) => {
+                Some(format!("{},{}", base, spec))
+            }
+            (Some(spec), _) => Some(spec.clone()),
+            (None, base) => base.map(|v| v.to_owned()),
+        }
+    }
+
+    /// create the scope with the rules configured by the project options and then by
+    /// the options of this override
+    ///
+    /// Options for the rules which are not selected are ignored.
+    pub fn create_scope(
+        &self,
+        registry: &RuleRegistry,
+        base_rules: Option<&str>,
+        base_options: &[String],
+    ) -> Result<RuleScope, Error> {
+        let spec = self.rules_spec(base_rules);
+        let rules = registry.build(
+            spec.as_deref(),
+            base_options.iter().chain(&self.rule_options),
+        )?;
+
+        let mut scope = RuleScope::new(rules);
+        for pattern in &self.paths {
+            scope.add_pattern(pattern.as_str());
+        }
+
+        Ok(scope)
+    }
+
+    fn from_table(table: &Table) -> Result<Self, Error> {
+        let mut rule_override = Self::default();
+
+        for (key, value) in table {
+            match key.as_str() {
+                "paths" => rule_override.paths = string_list(key, value)?,
+                "rules" => rule_override.rules = Some(string_list(key, value)?.join(",")),
+                "rule" => rule_override.rule_options = rule_options(key, value)?,
+                _ => warnln!("Warning: Unknown configuration key \"override.{}\"", key),
+            }
+        }
+
+        if rule_override.paths.is_empty() {
+            return Err(invalid_value(
+                "override.paths",
+                "non-empty array of strings",
+            ));
+        }
+
+        Ok(rule_override)
+    }
+}
+
+/// External plugin rule defined in the configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct PluginConfig {
+    pub name: String,
+    /// executable. Relative paths which contain directories are resolved from the
+    /// directory of the configuration file.
+    pub command: PathBuf,
+    pub args: Vec<String>,
+    /// run the executable only once for all files
+    pub batch: bool,
+    pub reason: Option<String>,
+    /// whether the plugin is enabled by default (default: `true`)
+    pub default: bool,
+}
+
+impl PluginConfig {
+    /// create the plugin rule
+    #[cfg(feature = "plugin")]
+    pub fn to_rule(&self) -> PluginRule {
+        let mut rule = PluginRule::new(self.name.as_str(), self.command.as_path());
+        for arg in &self.args {
+            rule.add_arg(arg.as_str());
+        }
+        rule.set_batch(self.batch);
+        if let Some(ref reason) = self.reason {
+            rule.set_reason(reason.as_str());
+        }
+        rule
+    }
+
+    /// register the plugin rule into the registry
+    #[cfg(feature = "plugin")]
+    pub fn register(&self, registry: &mut RuleRegistry) -> Result<(), Error> {
+        self.to_rule().register(registry, self.default)
+    }
+
+    fn from_table(name: &str, table: &Table, base_dir: &Path) -> Result<Self, Error> {
+        let mut plugin = Self {
+            name: name.to_owned(),
+            command: PathBuf::new(),
+            args: Vec::new(),
+            batch: false,
+            reason: None,
+            default: true,
+        };
+
+        for (key, value) in table {
+            match key.as_str() {
+                "command" => {
+                    let command = as_str(key, value)?;
+                    plugin.command = if command.contains(['/', '\\']) {
+                        base_dir.join(command)
+                    } else {
+                        PathBuf::from(command)
+                    };
+                }
+                "args" => plugin.args = string_list(key, value)?,
+                "batch" => plugin.batch = as_bool(key, value)?,
+                "reason" => plugin.reason = Some(as_str(key, value)?.to_owned()),
+                "default" => plugin.default = as_bool(key, value)?,
+                _ => warnln!(
+                    "Warning: Unknown configuration key \"plugin.{}.{}\"",
+                    name,
+                    key
+                ),
+            }
+        }
+
+        if plugin.command.as_os_str().is_empty() {
+            return Err(invalid_value(&format!("plugin.{}.command", name), "string"));
+        }
+
+        Ok(plugin)
+    }
+}
+
+/// Pattern rule defined in the configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternConfig {
+    pub name: String,
+    /// `line`, `branch` or `both` (default: `both`)
+    pub scope: String,
+    pub regexes: Vec<String>,
+    pub macros: Vec<String>,
+    pub attributes: Vec<String>,
+    /// glob patterns of the function names
+    pub functions: Vec<String>,
+    pub reason: Option<String>,
+    /// whether the rule is enabled by default (default: `true`)
+    pub default: bool,
+}
+
+impl PatternConfig {
+    /// create the pattern rule. Fails if the regular expressions are invalid.
+    #[cfg(feature = "pattern")]
+    pub fn to_rule(&self) -> Result<PatternRule, Error> {
+        let mut rule = PatternRule::new(self.name.as_str());
+        rule.set_scope(self.scope.parse()?);
+        for regex in &self.regexes {
+            rule.add_regex(regex)?;
+        }
+        for name in &self.macros {
+            rule.add_macro(name.as_str());
+        }
+        for name in &self.attributes {
+            rule.add_attribute(name.as_str());
+        }
+        for pattern in &self.functions {
+            rule.add_function(pattern.as_str());
+        }
+        if let Some(ref reason) = self.reason {
+            rule.set_reason(reason.as_str());
+        }
+        Ok(rule)
+    }
+
+    /// register the pattern rule into the registry
+    #[cfg(feature = "pattern")]
+    pub fn register(&self, registry: &mut RuleRegistry) -> Result<(), Error> {
+        self.to_rule()?.register(registry, self.default)
+    }
+
+    fn from_table(name: &str, table: &Table) -> Result<Self, Error> {
+        let mut pattern = Self {
+            name: name.to_owned(),
+            scope: "both".to_owned(),
+            regexes: Vec::new(),
+            macros: Vec::new(),
+            attributes: Vec::new(),
+            functions: Vec::new(),
+            reason: None,
+            default: true,
+        };
+
+        for (key, value) in table {
+            match key.as_str() {
+                "scope" => {
+                    pattern.scope = as_str(key, value)?.to_owned();
+                    if !["line", "branch", "both"].contains(&pattern.scope.as_str()) {
+                        return Err(invalid_value(key, "\"line\", \"branch\" or \"both\""));
+                    }
+                }
+                "regex" => pattern.regexes = string_list(key, value)?,
+                "macros" => pattern.macros = string_list(key, value)?,
+                "attributes" => pattern.attributes = string_list(key, value)?,
+                "functions" => pattern.functions = string_list(key, value)?,
+                "reason" => pattern.reason = Some(as_str(key, value)?.to_owned()),
+                "default" => pattern.default = as_bool(key, value)?,
+                _ => warnln!(
+                    "Warning: Unknown configuration key \"pattern.{}.{}\"",
+                    name,
+                    key
+                ),
+            }
+        }
+
+        if pattern.regexes.is_empty()
+            && pattern.macros.is_empty()
+            && pattern.attributes.is_empty()
+            && pattern.functions.is_empty()
+        {
+            return Err(format!(
+                "No patterns are given for \"pattern.{}\" (expected regex, macros, attributes \
+                 or functions)",
+                name
+            )
+            .into());
+        }
+
+        Ok(pattern)
+    }
+}
+
+/// Project configuration loaded from `covfix.toml` or the metadata of `Cargo.toml`
+///
+/// `covfix.toml` has the same keys as `[package.metadata.covfix]` and
+/// `[workspace.metadata.covfix]` tables. Relative paths are resolved from the directory
+/// which contains the configuration file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    /// rule specification (see `RuleRegistry::select()`)
+    pub rules: Option<String>,
+    /// rule options in `RULE.KEY=VALUE` format
+    pub rule_options: Vec<String>,
+    pub includes: Vec<String>,
+    pub excludes: Vec<String>,
+    /// path remaps as `(FROM, TO)` pairs, in the order written in the file
+    pub remaps: Vec<(String, String)>,
+    pub relative_outside_root: Option<bool>,
+    pub repair: Option<bool>,
+    pub on_error: Option<String>,
+    pub diff_format: Option<String>,
+    pub cache_dir: Option<PathBuf>,
+    pub thresholds: Thresholds,
+    /// rule sets for the specific paths (`[[override]]` tables)
+    pub overrides: Vec<RuleOverride>,
+    /// external plugin rules (`[plugin.NAME]` tables)
+    pub plugins: Vec<PluginConfig>,
+    /// pattern rules (`[pattern.NAME]` tables)
+    pub patterns: Vec<PatternConfig>,
+    /// path of the loaded file
+    path: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// find the configuration in the project root directory
+    ///
+    /// `covfix.toml` is preferred, and then `[workspace.metadata.covfix]` and
+    /// `[package.metadata.covfix]` in `Cargo.toml` are searched. Returns `None` if no
+    /// configuration is found.
+    pub fn discover(root: &Path) -> Result<Option<Self>, Error> {
+        let path = root.join(CONFIG_FILE_NAME);
+        if path.is_file() {
+            return Self::from_file(&path).map(Some);
+        }
+
+        let path = root.join("Cargo.toml");
+        if path.is_file() {
+            let table = read_toml(&path)?;
+            if let Some(metadata) = cargo_metadata(&table) {
+                return Self::from_table(metadata, root)
+                    .map(|config| Some(config.with_path(path.clone())))
+                    .chain_err(|| format!("Invalid configuration in {:?}", path));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// load the configuration file
+    ///
+    /// If the file name is `Cargo.toml`, the configuration is read from the metadata
+    /// table (an empty configuration is returned if the table does not exist).
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let table = read_toml(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let config = if path.file_name() == Some("Cargo.toml".as_ref()) {
+            match cargo_metadata(&table) {
+                Some(metadata) => Self::from_table(metadata, base_dir),
+                None => Ok(Self::new()),
+            }
+        } else {
+            Self::from_table(&table, base_dir)
+        };
+
+        config
+            .map(|config| config.with_path(path.to_owned()))
+            .chain_err(|| format!("Invalid configuration in {:?}", path))
+    }
+
+    /// parse the content of `covfix.toml`. Relative paths are resolved from `base_dir`.
+    pub fn from_toml(content: &str, base_dir: &Path) -> Result<Self, Error> {
+        let table: Table = toml::from_str(content)?;
+        Self::from_table(&table, base_dir)
+    }
+
+    /// path of the file from which the configuration was loaded
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// create the rules selected and configured by this configuration
+    ///
+    /// Options for the rules which are not selected are ignored.
+    pub fn create_rules(&self, registry: &RuleRegistry) -> Result<Vec<Box<dyn Rule>>, Error> {
+        registry.build(self.rules.as_deref(), &self.rule_options)
+    }
+
+    /// create the rule scopes of the overrides in the order of the configuration
+    pub fn create_scopes(&self, registry: &RuleRegistry) -> Result<Vec<RuleScope>, Error> {
+        self.overrides
+            .iter()
+            .map(|v| v.create_scope(registry, self.rules.as_deref(), &self.rule_options))
+            .collect()
+    }
+
+    fn with_path(mut self, path: PathBuf) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    fn from_table(table: &Table, base_dir: &Path) -> Result<Self, Error> {
+        let mut config = Self::new();
+
+        for (key, value) in table {
+            match key.as_str() {
+                "rules" => config.rules = Some(string_list(key, value)?.join(",")),
+                "rule" => config.rule_options = rule_options(key, value)?,
+                "override" => {
+                    let tables = value
+                        .as_array()
+                        .ok_or_else(|| invalid_value(key, "array of tables"))?;
+                    for table in tables {
+                        let table = as_table(key, table)?;
+                        config.overrides.push(RuleOverride::from_table(table)?);
+                    }
+                }
+                "plugin" => {
+                    for (name, table) in as_table(key, value)? {
+                        let table = as_table(name, table)?;
+                        config
+                            .plugins
+                            .push(PluginConfig::from_table(name, table, base_dir)?);
+                    }
+                }
+                "pattern" => {
+                    for (name, table) in as_table(key, value)? {
+                        let table = as_table(name, table)?;
+                        config
+                            .patterns
+                            .push(PatternConfig::from_table(name, table)?);
+                    }
+                }
+                "include" => config.includes = string_list(key, value)?,
+                "exclude" => config.excludes = string_list(key, value)?,
+                "remap" => config.remaps = remaps(key, value)?,
+                "relative_outside_root" => {
+                    config.relative_outside_root = Some(as_bool(key, value)?);
+                }
+                "repair" => config.repair = Some(as_bool(key, value)?),
+                "on_error" => config.on_error = Some(as_str(key, value)?.to_owned()),
+                "diff_format" => config.diff_format = Some(as_str(key, value)?.to_owned()),
+                "cache_dir" => config.cache_dir = Some(base_dir.join(as_str(key, value)?)),
+                "threshold" => {
+                    for (name, value) in as_table(key, value)? {
+                        let percent = as_percent(name, value)?;
+                        match name.as_str() {
+                            "line" => config.thresholds.line = Some(percent),
+                            "branch" => config.thresholds.branch = Some(percent),
+                            _ => {
+                                warnln!("Warning: Unknown configuration key \"threshold.{}\"", name)
+                            }
+                        }
+                    }
+                }
+                _ => warnln!("Warning: Unknown configuration key {:?}", key),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn read_toml(path: &Path) -> Result<Table, Error> {
+    let content =
+        fs::read_to_string(path).chain_err(|| format!("Failed to read file {:?}", path))?;
+    toml::from_str(&content).chain_err(|| format!("Failed to parse {:?}", path))
+}
+
+/// `[workspace.metadata.covfix]` or `[package.metadata.covfix]` table
+fn cargo_metadata(table: &Table) -> Option<&Table> {
+    ["workspace", "package"].iter().find_map(|section| {
+        table
+            .get(*section)
+            .and_then(|v| v.get("metadata"))
+            .and_then(|v| v.get("covfix"))
+            .and_then(|v| v.as_table())
+    })
+}
+
+fn invalid_value(key: &str, expected: &str) -> Error {
+    format!("Invalid value for {:?} (expected {})", key, expected).into()
+}
+
+fn as_table<'a>(key: &str, value: &'a Value) -> Result<&'a Table, Error> {
+    value.as_table().ok_or_else(|| invalid_value(key, "table"))
+}
+
+fn as_str<'a>(key: &str, value: &'a Value) -> Result<&'a str, Error> {
+    value.as_str().ok_or_else(|| invalid_value(key, "string"))
+}
+
+fn as_bool(key: &str, value: &Value) -> Result<bool, Error> {
+    value.as_bool().ok_or_else(|| invalid_value(key, "boolean"))
+}
+
+fn as_percent(key: &str, value: &Value) -> Result<f64, Error> {
+    let percent = match *value {
+        Value::Integer(v) => v as f64,
+        Value::Float(v) => v,
+        _ => return Err(invalid_value(key, "number")),
+    };
+
+    if (0.0..=100.0).contains(&percent) {
+        Ok(percent)
+    } else {
+        Err(invalid_value(key, "percentage between 0 and 100"))
+    }
+}
+
+/// string or array of strings
+fn string_list(key: &str, value: &Value) -> Result<Vec<String>, Error> {
+    match *value {
+        Value::String(ref s) => Ok(vec![s.clone()]),
+        Value::Array(ref array) => array
+            .iter()
+            .map(|v| as_str(key, v).map(|s| s.to_owned()))
+            .collect(),
+        _ => Err(invalid_value(key, "string or array of strings")),
+    }
+}
+
+/// `{ FROM = TO }` table or array of `{ from = FROM, to = TO }` tables
+///
+/// Keys of the table are not kept in the written order, which does not matter because
+/// the longest prefix is used when remapping. Use the array to keep the order.
+fn remaps(key: &str, value: &Value) -> Result<Vec<(String, String)>, Error> {
+    match *value {
+        Value::Table(ref table) => table
+            .iter()
+            .map(|(from, to)| Ok((from.clone(), as_str(from, to)?.to_owned())))
+            .collect(),
+        Value::Array(ref array) => array
+            .iter()
+            .map(|entry| {
+                let entry = as_table(key, entry)?;
+                let field = |name: &str| {
+                    let key = format!("{}.{}", key, name);
+                    match entry.get(name) {
+                        Some(v) => as_str(&key, v).map(|s| s.to_owned()),
+                        None => Err(invalid_value(&key, "string")),
+                    }
+                };
+                Ok((field("from")?, field("to")?))
+            })
+            .collect(),
+        _ => Err(invalid_value(key, "table or array of tables")),
+    }
+}
+
+/// `[rule.NAME]` tables into `NAME.KEY=VALUE` options
+fn rule_options(key: &str, value: &Value) -> Result<Vec<String>, Error> {
+    let mut options = Vec::new();
+
+    for (name, table) in as_table(key, value)? {
+        for (option, value) in as_table(name, table)? {
+            let value = option_value(option, value)?;
+            options.push(format!("{}.{}={}", name, option, value));
+        }
+    }
+
+    Ok(options)
+}
+
+/// rule option value in the string format accepted by `Rule::set_option()`
+fn option_value(key: &str, value: &Value) -> Result<String, Error> {
+    match *value {
+        Value::String(ref s) => Ok(s.clone()),
+        Value::Integer(v) => Ok(v.to_string()),
+        Value::Float(v) => Ok(v.to_string()),
+        Value::Boolean(v) => Ok(v.to_string()),
+        Value::Array(_) => string_list(key, value).map(|v| v.join(",")),
+        _ => Err(invalid_value(key, "string, number, boolean or array")),
+    }
+}
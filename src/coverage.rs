@@ -1,11 +1,16 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::path::{Path, PathBuf};
+use std::ops::{Bound, Range, RangeBounds};
+use std::path::{Component, Path, PathBuf};
 
 use crate::error::*;
+use crate::filter::PathFilter;
 
 /// Coverage information for a single line
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LineCoverage {
     /// 1-indexed line in the source file
     pub line_number: usize,
@@ -16,6 +21,7 @@ pub struct LineCoverage {
 
 /// Coverage information for a single branch
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BranchCoverage {
     /// 1-indexed line in the source file
     pub line_number: usize,
@@ -29,13 +35,18 @@ pub struct BranchCoverage {
 /// Coverage information for a single file
 ///
 /// `FileCoverage` holds coverage information for lines and branches in the source file.
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileCoverage {
     path: PathBuf,
     #[doc(hidden)]
     pub line_coverages: Vec<LineCoverage>,
     #[doc(hidden)]
     pub branch_coverages: Vec<BranchCoverage>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    declared: DeclaredTotals,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) checksums: BTreeMap<usize, String>,
 }
 
 impl FileCoverage {
@@ -48,6 +59,8 @@ impl FileCoverage {
             path: path.into(),
             line_coverages,
             branch_coverages,
+            declared: DeclaredTotals::default(),
+            checksums: BTreeMap::new(),
         }
     }
 
@@ -55,6 +68,30 @@ impl FileCoverage {
         &self.path
     }
 
+    /// totals declared in the coverage file (e.g. `LF`/`LH` records in lcov)
+    pub fn declared_totals(&self) -> &DeclaredTotals {
+        &self.declared
+    }
+
+    pub fn set_declared_totals(&mut self, declared: DeclaredTotals) {
+        self.declared = declared;
+    }
+
+    /// checksum of the source line recorded in the coverage file (e.g. the third field
+    /// of `DA` records in lcov)
+    pub fn line_checksum(&self, line_number: usize) -> Option<&str> {
+        self.checksums.get(&line_number).map(|v| v.as_str())
+    }
+
+    pub fn set_line_checksum<S: Into<String>>(&mut self, line_number: usize, checksum: S) {
+        self.checksums.insert(line_number, checksum.into());
+    }
+
+    /// recorded checksums as `(line number, checksum)` pairs in the order of line numbers
+    pub fn line_checksums(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.checksums.iter().map(|(&k, v)| (k, v.as_str()))
+    }
+
     pub fn line_coverages(&self) -> &[LineCoverage] {
         &self.line_coverages
     }
@@ -62,10 +99,194 @@ impl FileCoverage {
     pub fn branch_coverages(&self) -> &[BranchCoverage] {
         &self.branch_coverages
     }
+
+    /// sort the line and branch coverages by line number
+    ///
+    /// Query methods below require the coverages to be sorted. `CoverageFixer` sorts
+    /// them before applying the rules.
+    pub fn sort(&mut self) {
+        self.line_coverages.sort_by_key(|v| v.line_number);
+        self.branch_coverages.sort_by_key(|v| v.line_number);
+    }
+
+    /// line coverage at the line
+    pub fn line_coverage(&self, line_number: usize) -> Option<&LineCoverage> {
+        self.line_coverages
+            .binary_search_by_key(&line_number, |v| v.line_number)
+            .ok()
+            .map(|idx| &self.line_coverages[idx])
+    }
+
+    /// execution count of the line. `None` if the line is not executable.
+    pub fn line_count(&self, line_number: usize) -> Option<u32> {
+        self.line_coverage(line_number).and_then(|v| v.count)
+    }
+
+    /// branch coverages at the line
+    pub fn branches_at(&self, line_number: usize) -> &[BranchCoverage] {
+        self.branch_range(line_number..=line_number)
+    }
+
+    /// line coverages within the range of line numbers
+    pub fn line_range<R: RangeBounds<usize>>(&self, range: R) -> &[LineCoverage] {
+        let range = sorted_range(&self.line_coverages, |v| v.line_number, range);
+        &self.line_coverages[range]
+    }
+
+    /// mutable line coverages within the range of line numbers
+    pub fn line_range_mut<R: RangeBounds<usize>>(&mut self, range: R) -> &mut [LineCoverage] {
+        let range = sorted_range(&self.line_coverages, |v| v.line_number, range);
+        &mut self.line_coverages[range]
+    }
+
+    /// branch coverages within the range of line numbers
+    pub fn branch_range<R: RangeBounds<usize>>(&self, range: R) -> &[BranchCoverage] {
+        let range = sorted_range(&self.branch_coverages, |v| v.line_number, range);
+        &self.branch_coverages[range]
+    }
+
+    /// mutable branch coverages within the range of line numbers
+    pub fn branch_range_mut<R: RangeBounds<usize>>(&mut self, range: R) -> &mut [BranchCoverage] {
+        let range = sorted_range(&self.branch_coverages, |v| v.line_number, range);
+        &mut self.branch_coverages[range]
+    }
+
+    /// ranges of unexecuted lines as `(start, end)` pairs (both inclusive)
+    ///
+    /// Non-executable lines between unexecuted lines do not split the range.
+    pub fn uncovered_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        let mut in_range = false;
+
+        for line_cov in &self.line_coverages {
+            match line_cov.count {
+                Some(0) => {
+                    match ranges.last_mut() {
+                        Some(range) if in_range => range.1 = line_cov.line_number,
+                        _ => ranges.push((line_cov.line_number, line_cov.line_number)),
+                    }
+                    in_range = true;
+                }
+                Some(_) => in_range = false,
+                None => {}
+            }
+        }
+
+        ranges
+    }
+
+    /// merge the coverage information of the same source file into `self`
+    ///
+    /// Execution counts of the same line are summed up. Branches on the same line and
+    /// block are matched in the order of appearance, and the branch is regarded as taken
+    /// if it was taken in either of the coverages. Duplicate entries in `other` are
+    /// combined first.
+    pub fn merge(&mut self, mut other: FileCoverage) {
+        other.combine_duplicates();
+        self.line_coverages.sort_by_key(|v| v.line_number);
+
+        let mut new_line_coverages = Vec::new();
+        for line_cov in other.line_coverages {
+            match self
+                .line_coverages
+                .binary_search_by_key(&line_cov.line_number, |v| v.line_number)
+            {
+                Ok(idx) => {
+                    let v = &mut self.line_coverages[idx];
+                    v.count = merge_count(v.count, line_cov.count);
+                }
+                Err(_) => new_line_coverages.push(line_cov),
+            }
+        }
+        self.line_coverages.append(&mut new_line_coverages);
+
+        let mut matched = vec![false; self.branch_coverages.len()];
+        for branch_cov in other.branch_coverages {
+            let found = self
+                .branch_coverages
+                .iter()
+                .zip(matched.iter())
+                .position(|(v, &m)| {
+                    !m && v.line_number == branch_cov.line_number
+                        && v.block_number == branch_cov.block_number
+                });
+
+            match found {
+                Some(idx) => {
+                    matched[idx] = true;
+                    let v = &mut self.branch_coverages[idx];
+                    v.taken = merge_taken(v.taken, branch_cov.taken);
+                }
+                None => {
+                    matched.push(true);
+                    self.branch_coverages.push(branch_cov);
+                }
+            }
+        }
+
+        for (line_number, checksum) in other.checksums {
+            self.checksums.entry(line_number).or_insert(checksum);
+        }
+
+        self.sort();
+        self.declared = DeclaredTotals::default();
+    }
+
+    /// combine the entries of the same line, and the branches duplicated along with them
+    ///
+    /// Returns the duplicate lines with their number of entries, and the duplicate
+    /// blocks with their line number and number of entries.
+    pub(crate) fn combine_duplicates(&mut self) -> (Vec<(usize, usize)>, Vec<DuplicateBlock>) {
+        self.sort();
+
+        let duplicates = duplicate_lines(&line_entries(self));
+        let duplicate_branches = duplicate_branches(self, &duplicates);
+        for &(line_number, block_number, entries) in &duplicate_branches {
+            // merge the n-th branch of each copy into the branch of the first copy
+            let indices: Vec<usize> = self
+                .branch_coverages
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| v.line_number == line_number && v.block_number == block_number)
+                .map(|(i, _)| i)
+                .collect();
+            let branches = indices.len() / entries;
+            for (n, &idx) in indices.iter().enumerate().skip(branches) {
+                let taken = self.branch_coverages[idx].taken.take();
+                let first = &mut self.branch_coverages[indices[n % branches]];
+                first.taken = merge_taken(first.taken, taken);
+            }
+            for &idx in indices[branches..].iter().rev() {
+                self.branch_coverages.remove(idx);
+            }
+        }
+
+        self.line_coverages.dedup_by(|a, b| {
+            if a.line_number == b.line_number {
+                b.count = merge_count(b.count, a.count);
+                true
+            } else {
+                false
+            }
+        });
+
+        (duplicates, duplicate_branches)
+    }
+}
+
+/// Declared totals and checksums are not compared because they are just a copy of the
+/// input file.
+impl PartialEq for FileCoverage {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.line_coverages == other.line_coverages
+            && self.branch_coverages == other.branch_coverages
+    }
 }
 
 /// Coverage information for package
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PackageCoverage {
     name: String,
     #[doc(hidden)]
@@ -91,17 +312,173 @@ impl PackageCoverage {
     pub fn file_coverages(&self) -> &[FileCoverage] {
         &self.file_coverages
     }
+
+    /// remove the coverages of the files which do not match the filter
+    pub fn retain_paths(&mut self, filter: &PathFilter) {
+        self.file_coverages.retain(|v| {
+            let keep = filter.is_match(v.path());
+            if !keep {
+                debugln!("Removing coverage for {:?}", v.path());
+            }
+            keep
+        });
+    }
+
+    /// merge another coverage information (e.g. from another test run) into `self`
+    ///
+    /// Files are identified by their normalized paths. Coverages of the files which
+    /// appear in both packages are merged by `FileCoverage::merge`, and the others are
+    /// appended.
+    pub fn merge(&mut self, other: PackageCoverage) {
+        if self.name.is_empty() {
+            self.name = other.name;
+        }
+
+        let mut indices: HashMap<PathBuf, usize> = self
+            .file_coverages
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (normalize_path(&v.path), i))
+            .collect();
+
+        for file_cov in other.file_coverages {
+            match indices.entry(normalize_path(&file_cov.path)) {
+                Entry::Occupied(e) => self.file_coverages[*e.get()].merge(file_cov),
+                Entry::Vacant(e) => {
+                    e.insert(self.file_coverages.len());
+                    self.file_coverages.push(file_cov);
+                }
+            }
+        }
+    }
+}
+
+/// index range of the elements whose keys are within the range
+fn sorted_range<T, F: Fn(&T) -> usize, R: RangeBounds<usize>>(
+    slice: &[T],
+    key: F,
+    range: R,
+) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&s) => slice.partition_point(|v| key(v) < s),
+        Bound::Excluded(&s) => slice.partition_point(|v| key(v) <= s),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&e) => slice.partition_point(|v| key(v) <= e),
+        Bound::Excluded(&e) => slice.partition_point(|v| key(v) < e),
+        Bound::Unbounded => slice.len(),
+    };
+
+    start..end.max(start)
+}
+
+/// line number, block number and number of entries of a duplicated block
+pub(crate) type DuplicateBlock = (usize, Option<usize>, usize);
+
+/// number of entries of each line
+pub(crate) fn line_entries(file_cov: &FileCoverage) -> HashMap<usize, usize> {
+    let mut line_entries = HashMap::new();
+    for line_cov in file_cov.line_coverages() {
+        *line_entries.entry(line_cov.line_number).or_insert(0) += 1;
+    }
+    line_entries
+}
+
+/// lines which appear multiple times, and the number of entries
+pub(crate) fn duplicate_lines(line_entries: &HashMap<usize, usize>) -> Vec<(usize, usize)> {
+    let mut duplicates: Vec<_> = line_entries
+        .iter()
+        .filter(|&(_, &entries)| entries > 1)
+        .map(|(&line_number, &entries)| (line_number, entries))
+        .collect();
+    duplicates.sort_unstable();
+    duplicates
+}
+
+/// blocks whose branches are duplicated along with the duplicate lines
+///
+/// Branches of the same block are distinguished only by their order, so the branches
+/// are regarded as duplicated only if their number is a multiple of the entries of the
+/// line. Returns the line number, the block number and the number of entries.
+pub(crate) fn duplicate_branches(
+    file_cov: &FileCoverage,
+    duplicate_lines: &[(usize, usize)],
+) -> Vec<DuplicateBlock> {
+    let mut duplicates = Vec::new();
+
+    for &(line_number, entries) in duplicate_lines {
+        let mut blocks: Vec<(Option<usize>, usize)> = Vec::new();
+        for branch_cov in file_cov.branch_coverages() {
+            if branch_cov.line_number != line_number {
+                continue;
+            }
+            match blocks.iter_mut().find(|b| b.0 == branch_cov.block_number) {
+                Some(block) => block.1 += 1,
+                None => blocks.push((branch_cov.block_number, 1)),
+            }
+        }
+
+        duplicates.extend(
+            blocks
+                .into_iter()
+                .filter(|&(_, branches)| branches % entries == 0)
+                .map(|(block_number, _)| (line_number, block_number, entries)),
+        );
+    }
+
+    duplicates
 }
 
-#[doc(hidden)]
+pub(crate) fn merge_count(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.saturating_add(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+pub(crate) fn merge_taken(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a || b),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Remove `.` components and resolve `..` components lexically
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            c => normalized.push(c.as_os_str()),
+        }
+    }
+
+    normalized
+}
+
+/// Total number of executed/executable lines and branches
 pub trait TotalCoverage {
+    /// number of lines executed at least once
     fn line_executed(&self) -> usize;
+    /// number of executable lines
     fn line_total(&self) -> usize;
+    /// number of taken branches
     fn branch_executed(&self) -> usize;
+    /// number of executable branches
     fn branch_total(&self) -> usize;
 }
 
-#[doc(hidden)]
 impl TotalCoverage for FileCoverage {
     fn line_executed(&self) -> usize {
         self.line_coverages
@@ -132,7 +509,6 @@ impl TotalCoverage for FileCoverage {
     }
 }
 
-#[doc(hidden)]
 impl TotalCoverage for PackageCoverage {
     fn line_executed(&self) -> usize {
         self.file_coverages
@@ -159,6 +535,101 @@ impl TotalCoverage for PackageCoverage {
     }
 }
 
+/// Compute the relative path from `base` to `path`
+///
+/// Both paths must be normalized absolute paths. `None` is returned if the relative path
+/// cannot be computed (e.g. paths are on different drives).
+pub(crate) fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() || !base.is_absolute() {
+        return None;
+    }
+
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+
+    if path_components.peek() != base_components.peek() {
+        return None;
+    }
+
+    while let (Some(a), Some(b)) = (path_components.peek(), base_components.peek()) {
+        if a != b {
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in base_components {
+        relative.push("..");
+    }
+    for c in path_components {
+        relative.push(c.as_os_str());
+    }
+
+    Some(relative)
+}
+
+/// Summary of line and branch coverage
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CoverageSummary {
+    pub line_executed: usize,
+    pub line_total: usize,
+    pub branch_executed: usize,
+    pub branch_total: usize,
+}
+
+impl CoverageSummary {
+    pub fn new<T: TotalCoverage>(data: &T) -> Self {
+        Self {
+            line_executed: data.line_executed(),
+            line_total: data.line_total(),
+            branch_executed: data.branch_executed(),
+            branch_total: data.branch_total(),
+        }
+    }
+
+    /// percentage of executed lines. `None` if there are no executable lines.
+    pub fn line_percent(&self) -> Option<f64> {
+        percent(self.line_executed, self.line_total)
+    }
+
+    /// percentage of executed branches. `None` if there are no branches.
+    pub fn branch_percent(&self) -> Option<f64> {
+        percent(self.branch_executed, self.branch_total)
+    }
+}
+
+/// `count` out of `total` in percent, or `None` if `total` is zero
+pub(crate) fn percent(count: usize, total: usize) -> Option<f64> {
+    if total == 0 {
+        None
+    } else {
+        Some((count as f64) / (total as f64) * 100.0)
+    }
+}
+
+/// format the percentage as `12.34%`, or `n/a` if it is not defined
+pub fn format_percent(percent: Option<f64>) -> String {
+    match percent {
+        Some(percent) => format!("{:.2}%", percent),
+        None => "n/a".to_owned(),
+    }
+}
+
+/// Totals declared in the coverage file
+///
+/// These values are not computed from the line and branch coverages, and `None` means
+/// that the value was not declared. They are cleared once the coverage is modified.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeclaredTotals {
+    pub line_executed: Option<usize>,
+    pub line_total: Option<usize>,
+    pub branch_executed: Option<usize>,
+    pub branch_total: Option<usize>,
+}
+
 pub trait CoverageReader {
     /// fetch the coverage information from the reader
     fn read<R: BufRead>(&self, reader: &mut R) -> Result<PackageCoverage, Error>;
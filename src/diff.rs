@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::coverage::{
+    format_percent, normalize_path, CoverageSummary, FileCoverage, PackageCoverage,
+};
+use crate::error::*;
+
+/// Difference of coverage information for a single file
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileCoverageDiff {
+    pub path: PathBuf,
+    /// coverage summary in the old coverage. `None` means the file is newly added.
+    pub old: Option<CoverageSummary>,
+    /// coverage summary in the new coverage. `None` means the file is removed.
+    pub new: Option<CoverageSummary>,
+    /// lines which are executed in the new coverage, but were not in the old one
+    pub newly_covered_lines: Vec<usize>,
+    /// lines which are not executed in the new coverage, but were in the old one. Lines
+    /// missing from the old coverage are not included.
+    pub newly_uncovered_lines: Vec<usize>,
+}
+
+impl FileCoverageDiff {
+    fn new(old: Option<&FileCoverage>, new: Option<&FileCoverage>) -> Self {
+        let path = new.or(old).map(|v| v.path().to_owned()).unwrap_or_default();
+
+        let mut old_counts = HashMap::new();
+        if let Some(old) = old {
+            for line_cov in old.line_coverages() {
+                if let Some(count) = line_cov.count {
+                    old_counts.insert(line_cov.line_number, count);
+                }
+            }
+        }
+
+        let mut newly_covered_lines = Vec::new();
+        let mut newly_uncovered_lines = Vec::new();
+
+        if let Some(new) = new {
+            for line_cov in new.line_coverages() {
+                let count = match line_cov.count {
+                    Some(count) => count,
+                    None => continue,
+                };
+
+                let old_count = old_counts.get(&line_cov.line_number).cloned();
+                if count > 0 && old_count.unwrap_or(0) == 0 {
+                    newly_covered_lines.push(line_cov.line_number);
+                } else if count == 0 && matches!(old_count, Some(c) if c > 0) {
+                    newly_uncovered_lines.push(line_cov.line_number);
+                }
+            }
+        }
+
+        newly_covered_lines.sort_unstable();
+        newly_uncovered_lines.sort_unstable();
+
+        Self {
+            path,
+            old: old.map(CoverageSummary::new),
+            new: new.map(CoverageSummary::new),
+            newly_covered_lines,
+            newly_uncovered_lines,
+        }
+    }
+
+    /// difference of the number of executed lines
+    pub fn line_executed_delta(&self) -> isize {
+        delta(self.old, self.new, |s| s.line_executed)
+    }
+
+    /// difference of the number of executable lines
+    pub fn line_total_delta(&self) -> isize {
+        delta(self.old, self.new, |s| s.line_total)
+    }
+
+    /// difference of the number of taken branches
+    pub fn branch_executed_delta(&self) -> isize {
+        delta(self.old, self.new, |s| s.branch_executed)
+    }
+
+    /// difference of the number of executable branches
+    pub fn branch_total_delta(&self) -> isize {
+        delta(self.old, self.new, |s| s.branch_total)
+    }
+
+    /// returns `true` if nothing is changed in this file
+    pub fn is_unchanged(&self) -> bool {
+        self.old == self.new
+            && self.newly_covered_lines.is_empty()
+            && self.newly_uncovered_lines.is_empty()
+    }
+}
+
+/// Difference between two package coverages
+///
+/// Files are matched by their normalized paths. Both coverages should be fixed by
+/// `CoverageFixer` beforehand, otherwise the non-executable lines are also compared.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CoverageDiff {
+    pub old: CoverageSummary,
+    pub new: CoverageSummary,
+    #[cfg_attr(feature = "serde", serde(rename = "files"))]
+    pub file_diffs: Vec<FileCoverageDiff>,
+}
+
+impl CoverageDiff {
+    pub fn new(old: &PackageCoverage, new: &PackageCoverage) -> Self {
+        let mut old_files: HashMap<PathBuf, &FileCoverage> = old
+            .file_coverages()
+            .iter()
+            .map(|v| (normalize_path(v.path()), v))
+            .collect();
+
+        let mut file_diffs = Vec::new();
+        for new_file in new.file_coverages() {
+            let old_file = old_files.remove(&normalize_path(new_file.path()));
+            file_diffs.push(FileCoverageDiff::new(old_file, Some(new_file)));
+        }
+
+        // removed files
+        for old_file in old.file_coverages() {
+            if old_files.contains_key(&normalize_path(old_file.path())) {
+                file_diffs.push(FileCoverageDiff::new(Some(old_file), None));
+            }
+        }
+
+        Self {
+            old: CoverageSummary::new(old),
+            new: CoverageSummary::new(new),
+            file_diffs,
+        }
+    }
+
+    /// write the difference in human-readable format
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        for file_diff in self.file_diffs.iter().filter(|v| !v.is_unchanged()) {
+            writeln!(writer, "{}", file_diff.path.display())?;
+            write_summary_diff(writer, file_diff.old, file_diff.new)?;
+
+            if !file_diff.newly_covered_lines.is_empty() {
+                writeln!(
+                    writer,
+                    "  newly covered lines:   {}",
+                    format_lines(&file_diff.newly_covered_lines)
+                )?;
+            }
+
+            if !file_diff.newly_uncovered_lines.is_empty() {
+                writeln!(
+                    writer,
+                    "  newly uncovered lines: {}",
+                    format_lines(&file_diff.newly_uncovered_lines)
+                )?;
+            }
+
+            writeln!(writer)?;
+        }
+
+        writeln!(writer, "Total")?;
+        write_summary_diff(writer, Some(self.old), Some(self.new))?;
+
+        Ok(())
+    }
+
+    /// write the difference in JSON format
+    #[cfg(feature = "json")]
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        serde_json::to_writer(&mut *writer, self)
+            .chain_err(|| "Failed to write the coverage difference")?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+fn delta<F: Fn(&CoverageSummary) -> usize>(
+    old: Option<CoverageSummary>,
+    new: Option<CoverageSummary>,
+    f: F,
+) -> isize {
+    let old = old.as_ref().map_or(0, &f) as isize;
+    let new = new.as_ref().map_or(0, &f) as isize;
+    new - old
+}
+
+fn write_summary_diff<W: Write>(
+    writer: &mut W,
+    old: Option<CoverageSummary>,
+    new: Option<CoverageSummary>,
+) -> Result<(), Error> {
+    let old = old.unwrap_or_default();
+    let new = new.unwrap_or_default();
+
+    writeln!(
+        writer,
+        "  line:   {} ({} of {} lines)    => {} ({} of {} lines)",
+        format_percent(old.line_percent()),
+        old.line_executed,
+        old.line_total,
+        format_percent(new.line_percent()),
+        new.line_executed,
+        new.line_total,
+    )?;
+
+    writeln!(
+        writer,
+        "  branch: {} ({} of {} branches) => {} ({} of {} branches)",
+        format_percent(old.branch_percent()),
+        old.branch_executed,
+        old.branch_total,
+        format_percent(new.branch_percent()),
+        new.branch_executed,
+        new.branch_total,
+    )?;
+
+    Ok(())
+}
+
+/// format line numbers into compact ranges (e.g. "1-3, 5")
+fn format_lines(lines: &[usize]) -> String {
+    let mut ranges: Vec<String> = Vec::new();
+    let mut it = lines.iter().cloned().peekable();
+
+    while let Some(start) = it.next() {
+        let mut end = start;
+        while it.peek() == Some(&(end + 1)) {
+            end += 1;
+            it.next();
+        }
+
+        if start == end {
+            ranges.push(start.to_string());
+        } else {
+            ranges.push(format!("{}-{}", start, end));
+        }
+    }
+
+    ranges.join(", ")
+}
@@ -0,0 +1,136 @@
+use syn::visit::{self, Visit};
+use syn::{
+    ExprForLoop, ExprMacro, ImplItemMethod, ItemEnum, ItemFn, ItemMacro, ItemMod, ItemStruct,
+    ItemUnion,
+};
+
+use crate::rule::{Fixes, Node, NodeKind, RuleVisitor, SourceCode};
+
+/// walk the source code once and dispatch the subscribed nodes and lines to the visitors
+///
+/// Returns the fixes recorded by each visitor in the same order. `None` entries are
+/// skipped, and result in `None`.
+pub(crate) fn collect_fixes(
+    visitors: &mut [Option<Box<dyn RuleVisitor + '_>>],
+    source: &SourceCode,
+) -> Vec<Option<Fixes>> {
+    let mut dispatcher = Dispatcher {
+        visitors,
+        fixes: Vec::new(),
+        node_subscribers: vec![Vec::new(); NodeKind::ALL.len()],
+        line_subscribers: Vec::new(),
+    };
+
+    for (i, visitor) in dispatcher.visitors.iter().enumerate() {
+        let visitor = match visitor {
+            Some(visitor) => visitor,
+            None => {
+                dispatcher.fixes.push(None);
+                continue;
+            }
+        };
+
+        let subscription = visitor.subscription();
+        for kind in subscription.nodes {
+            let subscribers = &mut dispatcher.node_subscribers[kind as usize];
+            if !subscribers.contains(&i) {
+                subscribers.push(i);
+            }
+        }
+        if subscription.lines {
+            dispatcher.line_subscribers.push(i);
+        }
+
+        dispatcher.fixes.push(Some(Fixes::new()));
+    }
+
+    if !dispatcher.line_subscribers.is_empty() {
+        for (i, line) in source.content.lines().enumerate() {
+            for &idx in &dispatcher.line_subscribers {
+                if let (Some(visitor), Some(fixes)) =
+                    (&mut dispatcher.visitors[idx], &mut dispatcher.fixes[idx])
+                {
+                    visitor.visit_line(i + 1, line, fixes);
+                }
+            }
+        }
+    }
+
+    if dispatcher.node_subscribers.iter().any(|v| !v.is_empty()) {
+        dispatcher.visit_file(&source.ast);
+    }
+
+    for (visitor, fixes) in dispatcher.visitors.iter_mut().zip(&mut dispatcher.fixes) {
+        if let (Some(visitor), Some(fixes)) = (visitor, fixes) {
+            visitor.finish(fixes);
+        }
+    }
+
+    dispatcher.fixes
+}
+
+struct Dispatcher<'v, 'r> {
+    visitors: &'v mut [Option<Box<dyn RuleVisitor + 'r>>],
+    fixes: Vec<Option<Fixes>>,
+    /// indices of the visitors subscribing to each node kind
+    node_subscribers: Vec<Vec<usize>>,
+    /// indices of the visitors subscribing to the lines
+    line_subscribers: Vec<usize>,
+}
+
+impl<'v, 'r> Dispatcher<'v, 'r> {
+    fn dispatch(&mut self, node: Node) {
+        for &idx in &self.node_subscribers[node.kind() as usize] {
+            if let (Some(visitor), Some(fixes)) = (&mut self.visitors[idx], &mut self.fixes[idx]) {
+                visitor.visit_node(node, fixes);
+            }
+        }
+    }
+}
+
+impl<'ast, 'v, 'r> Visit<'ast> for Dispatcher<'v, 'r> {
+    fn visit_item_fn(&mut self, item: &'ast ItemFn) {
+        self.dispatch(Node::ItemFn(item));
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_item_mod(&mut self, item: &'ast ItemMod) {
+        self.dispatch(Node::ItemMod(item));
+        visit::visit_item_mod(self, item);
+    }
+
+    fn visit_item_struct(&mut self, item: &'ast ItemStruct) {
+        self.dispatch(Node::ItemStruct(item));
+        visit::visit_item_struct(self, item);
+    }
+
+    fn visit_item_enum(&mut self, item: &'ast ItemEnum) {
+        self.dispatch(Node::ItemEnum(item));
+        visit::visit_item_enum(self, item);
+    }
+
+    fn visit_item_union(&mut self, item: &'ast ItemUnion) {
+        self.dispatch(Node::ItemUnion(item));
+        visit::visit_item_union(self, item);
+    }
+
+    fn visit_item_macro(&mut self, item: &'ast ItemMacro) {
+        self.dispatch(Node::ItemMacro(item));
+        visit::visit_item_macro(self, item);
+    }
+
+    fn visit_impl_item_method(&mut self, item: &'ast ImplItemMethod) {
+        self.dispatch(Node::ImplItemMethod(item));
+        visit::visit_impl_item_method(self, item);
+    }
+
+    fn visit_expr_for_loop(&mut self, expr: &'ast ExprForLoop) {
+        self.dispatch(Node::ExprForLoop(expr));
+        visit::visit_expr_for_loop(self, expr);
+    }
+
+    fn visit_expr_macro(&mut self, expr: &'ast ExprMacro) {
+        self.dispatch(Node::ExprMacro(expr));
+        visit::visit_expr_macro(self, expr);
+    }
+}
@@ -14,6 +14,8 @@ error_chain! {
         FmtError(fmt::Error);
         IoError(io::Error);
         ParseError(syn::Error);
+        ZipError(zip::result::ZipError) #[cfg(feature = "archive")];
+        TomlError(toml::de::Error) #[cfg(feature = "config")];
     }
 
     // Custom errors
@@ -26,5 +28,13 @@ error_chain! {
             description("Invalid Rule name"),
             display("Invalid Rule name: {:?}", name)
         }
+        InvalidRuleOption(option: String) {
+            description("Invalid Rule option"),
+            display("Invalid Rule option: {:?}", option)
+        }
+        DuplicateRuleName(name: String) {
+            description("Duplicate Rule name"),
+            display("Rule {:?} is already registered", name)
+        }
     }
 }
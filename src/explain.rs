@@ -0,0 +1,249 @@
+use std::io::Write;
+
+use crate::error::*;
+use crate::fix::FixReport;
+
+/// Lines and branches removed by a rule from a span of the source code
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Adjustment {
+    /// name of the rule (`Rule::name()`)
+    pub rule: String,
+    /// reason of the removal (`Rule::reason()` unless the rule gives a specific one)
+    pub reason: String,
+    /// first and last line of the code which caused the removal (e.g. the region of
+    /// `cov:begin-ignore` marker), if the rule records it
+    pub origin: Option<(usize, usize)>,
+    /// first line of the span (1-indexed)
+    pub start_line: usize,
+    /// last line of the span (inclusive)
+    pub end_line: usize,
+    /// lines which became non-executable
+    pub lines: Vec<usize>,
+    /// line numbers of the branches which became non-executable (one entry per branch)
+    pub branches: Vec<usize>,
+}
+
+/// group the lines and branches removed by a rule into spans
+///
+/// `reason_of` returns the reason and the origin of the removal for each line, and lines
+/// removed for different reasons are grouped separately. Removed lines are split into
+/// different spans if an executable line or branch, or a line removed for another reason
+/// remains between them. All line numbers must be sorted.
+pub(crate) fn group_adjustments<'a, F>(
+    rule: &str,
+    lines: &[usize],
+    branches: &[usize],
+    kept: &[usize],
+    reason_of: F,
+) -> Vec<Adjustment>
+where
+    F: Fn(usize) -> (&'a str, Option<(usize, usize)>),
+{
+    let removed: Vec<usize> = lines.iter().chain(branches).cloned().collect();
+    let mut reasons: Vec<(&str, Option<(usize, usize)>)> = Vec::new();
+    for &line in &removed {
+        let reason = reason_of(line);
+        if !reasons.contains(&reason) {
+            reasons.push(reason);
+        }
+    }
+
+    let mut adjustments = Vec::new();
+    for reason in reasons {
+        let lines: Vec<usize> = lines
+            .iter()
+            .cloned()
+            .filter(|&l| reason_of(l) == reason)
+            .collect();
+        let branches: Vec<usize> = branches
+            .iter()
+            .cloned()
+            .filter(|&l| reason_of(l) == reason)
+            .collect();
+
+        let mut boundaries = kept.to_vec();
+        boundaries.extend(removed.iter().cloned().filter(|&l| reason_of(l) != reason));
+        boundaries.sort_unstable();
+
+        adjustments.extend(group_spans(rule, reason, &lines, &branches, &boundaries));
+    }
+    adjustments.sort_by_key(|a| a.start_line);
+
+    adjustments
+}
+
+/// group the lines and branches removed for the same reason into spans separated by
+/// the boundaries
+fn group_spans(
+    rule: &str,
+    (reason, origin): (&str, Option<(usize, usize)>),
+    lines: &[usize],
+    branches: &[usize],
+    boundaries: &[usize],
+) -> Vec<Adjustment> {
+    let mut removed: Vec<usize> = lines.iter().chain(branches.iter()).cloned().collect();
+    removed.sort_unstable();
+    removed.dedup();
+
+    let mut adjustments: Vec<Adjustment> = Vec::new();
+    for line in removed {
+        let split = match adjustments.last() {
+            Some(last) => {
+                let idx = boundaries.partition_point(|&k| k <= last.end_line);
+                matches!(boundaries.get(idx), Some(&k) if k < line)
+            }
+            None => true,
+        };
+
+        if split {
+            adjustments.push(Adjustment {
+                rule: rule.to_owned(),
+                reason: reason.to_owned(),
+                origin,
+                start_line: line,
+                end_line: line,
+                lines: Vec::new(),
+                branches: Vec::new(),
+            });
+        }
+
+        let adjustment = adjustments.last_mut().unwrap();
+        adjustment.end_line = line;
+        if lines.binary_search(&line).is_ok() {
+            adjustment.lines.push(line);
+        }
+        adjustment
+            .branches
+            .extend(branches.iter().filter(|&&b| b == line));
+    }
+
+    adjustments
+}
+
+impl FixReport {
+    /// write the annotated listing of the removed lines and branches for each file
+    ///
+    /// Contents of the lines are taken from the source code read while fixing.
+    pub fn write_explanation<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        for file_report in &self.files {
+            if file_report.adjustments.is_empty() {
+                continue;
+            }
+
+            writeln!(writer, "{}", file_report.path.display())?;
+
+            for adjustment in &file_report.adjustments {
+                if adjustment.start_line == adjustment.end_line {
+                    write!(
+                        writer,
+                        "  {}: line {}",
+                        adjustment.rule, adjustment.start_line
+                    )?;
+                } else {
+                    write!(
+                        writer,
+                        "  {}: lines {}-{}",
+                        adjustment.rule, adjustment.start_line, adjustment.end_line
+                    )?;
+                }
+                match adjustment.origin {
+                    Some((start, end)) if start == end => {
+                        write!(writer, " ({} at line {})", adjustment.reason, start)?
+                    }
+                    Some((start, end)) => write!(
+                        writer,
+                        " ({} at lines {}-{})",
+                        adjustment.reason, start, end
+                    )?,
+                    None if !adjustment.reason.is_empty() => {
+                        write!(writer, " ({})", adjustment.reason)?
+                    }
+                    None => {}
+                }
+                writeln!(writer)?;
+
+                let mut lines: Vec<usize> = adjustment
+                    .lines
+                    .iter()
+                    .chain(adjustment.branches.iter())
+                    .cloned()
+                    .collect();
+                lines.sort_unstable();
+                lines.dedup();
+
+                for line in lines {
+                    let text = file_report.removed_line(line).unwrap_or("");
+                    let branches = adjustment.branches.iter().filter(|&&b| b == line).count();
+
+                    let mut removed = Vec::new();
+                    if adjustment.lines.contains(&line) {
+                        removed.push("line".to_owned());
+                    }
+                    match branches {
+                        0 => {}
+                        1 => removed.push("1 branch".to_owned()),
+                        n => removed.push(format!("{} branches", n)),
+                    }
+
+                    writeln!(
+                        writer,
+                        "    {:>5} | {:<60} [{}]",
+                        line,
+                        text.trim_end(),
+                        removed.join(", ")
+                    )?;
+                }
+            }
+
+            writeln!(writer)?;
+        }
+
+        writeln!(
+            writer,
+            "Total: {} lines and {} branches removed",
+            self.lines_removed(),
+            self.branches_removed()
+        )?;
+
+        Ok(())
+    }
+
+    /// write the provenance of all adjustments in JSON format
+    #[cfg(feature = "json")]
+    pub fn write_explanation_json<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        use crate::coverage::CoverageSummary;
+        use std::path::Path;
+
+        #[derive(serde::Serialize)]
+        struct Explanation<'a> {
+            before: &'a CoverageSummary,
+            after: &'a CoverageSummary,
+            files: Vec<FileExplanation<'a>>,
+        }
+
+        #[derive(serde::Serialize)]
+        struct FileExplanation<'a> {
+            path: &'a Path,
+            adjustments: &'a [Adjustment],
+        }
+
+        let explanation = Explanation {
+            before: &self.before,
+            after: &self.after,
+            files: self
+                .files
+                .iter()
+                .map(|f| FileExplanation {
+                    path: &f.path,
+                    adjustments: &f.adjustments,
+                })
+                .collect(),
+        };
+        serde_json::to_writer(&mut *writer, &explanation)
+            .chain_err(|| "Failed to write the explanation")?;
+        writeln!(writer)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Glob pattern to match the file paths
+///
+/// Supported syntax:
+///
+/// - `?` matches any single character except `/`
+/// - `*` matches any sequence of characters except `/`
+/// - `**` matches any sequence of characters including `/`
+///
+/// The pattern also matches the files under the matched directory, so `tests/*`
+/// matches `tests/common/mod.rs`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlobPattern {
+    pattern: String,
+}
+
+impl GlobPattern {
+    pub fn new<S: Into<String>>(pattern: S) -> Self {
+        let pattern = pattern.into().replace('\\', "/");
+        let pattern = pattern.trim_end_matches('/').to_owned();
+        Self { pattern }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// returns `true` if the path (or one of its ancestors) matches the pattern
+    pub fn is_match(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy().replace('\\', "/");
+        let bytes = path.as_bytes();
+
+        if glob_match(self.pattern.as_bytes(), bytes) {
+            return true;
+        }
+
+        bytes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &b)| b == b'/')
+            .any(|(i, _)| {
+                let prefix = if i == 0 { &bytes[..1] } else { &bytes[..i] };
+                glob_match(self.pattern.as_bytes(), prefix)
+            })
+    }
+
+    /// returns `true` if the whole text (e.g. a function name) matches the pattern
+    pub fn is_match_str(&self, text: &str) -> bool {
+        glob_match(self.pattern.as_bytes(), text.as_bytes())
+    }
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let mut rest = &pattern[2..];
+            // `**/` also matches empty directories
+            if rest.first() == Some(&b'/') && glob_match(&rest[1..], text) {
+                return true;
+            }
+            while rest.first() == Some(&b'*') {
+                rest = &rest[1..];
+            }
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if glob_match(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => match text.first() {
+            Some(&c) if c != b'/' => glob_match(&pattern[1..], &text[1..]),
+            _ => false,
+        },
+        Some(&c) => text.first() == Some(&c) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Filter for the coverage records based on their file paths
+///
+/// Paths inside the project root are matched relative to the root directory,
+/// and the other paths are matched as they are.
+#[derive(Clone, Debug)]
+pub struct PathFilter {
+    root: PathBuf,
+    includes: Vec<GlobPattern>,
+    excludes: Vec<GlobPattern>,
+    exclude_external: bool,
+}
+
+impl PathFilter {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            includes: Vec::new(),
+            excludes: Vec::new(),
+            exclude_external: true,
+        }
+    }
+
+    /// only keep the files which match one of the include patterns
+    pub fn add_include<S: Into<String>>(&mut self, pattern: S) {
+        self.includes.push(GlobPattern::new(pattern));
+    }
+
+    /// remove the files which match the pattern
+    pub fn add_exclude<S: Into<String>>(&mut self, pattern: S) {
+        self.excludes.push(GlobPattern::new(pattern));
+    }
+
+    /// whether to remove the external sources (enabled by default)
+    ///
+    /// See `is_external_path` for which paths are regarded as external.
+    pub fn set_exclude_external(&mut self, exclude_external: bool) {
+        self.exclude_external = exclude_external;
+    }
+
+    /// returns `true` if the coverage for this path should be kept
+    pub fn is_match(&self, path: &Path) -> bool {
+        if self.exclude_external && is_external_path(path) {
+            return false;
+        }
+
+        let path = path.strip_prefix(&self.root).unwrap_or(path);
+
+        if !self.includes.is_empty() && !self.includes.iter().any(|p| p.is_match(path)) {
+            return false;
+        }
+
+        !self.excludes.iter().any(|p| p.is_match(path))
+    }
+}
+
+/// returns `true` if the path is a third-party or standard library source
+///
+/// The following paths are regarded as external.
+///
+/// - crates downloaded by cargo (`~/.cargo/registry`, `~/.cargo/git`)
+/// - sources of the standard library (`/rustc/<hash>/...`, `lib/rustlib/src`)
+pub fn is_external_path(path: &Path) -> bool {
+    let s = path.to_string_lossy().replace('\\', "/");
+
+    if s.contains("/.cargo/registry/")
+        || s.contains("/.cargo/git/")
+        || s.contains("/lib/rustlib/src/")
+        || s.starts_with("/rustc/")
+    {
+        return true;
+    }
+
+    if let Some(cargo_home) = env::var_os("CARGO_HOME") {
+        let cargo_home = Path::new(&cargo_home);
+        if path.starts_with(cargo_home.join("registry")) || path.starts_with(cargo_home.join("git"))
+        {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GlobPattern;
+    use std::path::Path;
+
+    #[test]
+    fn glob_pattern() {
+        let is_match =
+            |pattern: &str, path: &str| GlobPattern::new(pattern).is_match(Path::new(path));
+
+        assert!(is_match("src/lib.rs", "src/lib.rs"));
+        assert!(!is_match("src/lib.rs", "src/lib.rss"));
+        assert!(is_match("src/*.rs", "src/lib.rs"));
+        assert!(!is_match("src/*.rs", "src/sub/mod.rs"));
+        assert!(is_match("src/**/*.rs", "src/sub/mod.rs"));
+        assert!(is_match("src/**/*.rs", "src/lib.rs"));
+        assert!(is_match("**/generated/*", "src/generated/a.rs"));
+        assert!(is_match("src/?.rs", "src/a.rs"));
+        assert!(!is_match("src/?.rs", "src/ab.rs"));
+        assert!(is_match("tests/*", "tests/common/mod.rs"));
+        assert!(is_match("tests", "tests/common/mod.rs"));
+        assert!(!is_match("tests", "tests2/mod.rs"));
+        assert!(is_match("/*", "/usr/lib/foo.rs"));
+        assert!(!is_match("/*", "src/lib.rs"));
+    }
+
+    #[test]
+    fn is_external_path() {
+        assert!(super::is_external_path(Path::new(
+            "/home/user/.cargo/registry/src/github.com-1ecc6299db9ec823/syn-1.0.17/src/lib.rs"
+        )));
+        assert!(super::is_external_path(Path::new(
+            "/rustc/b8cedc00407a4c56a3bda1ed605c6fc166655447/library/core/src/option.rs"
+        )));
+        assert!(super::is_external_path(Path::new(
+            "/usr/lib/rustlib/lib/rustlib/src/rust/library/std/src/io/mod.rs"
+        )));
+        assert!(!super::is_external_path(Path::new(
+            "/home/user/project/src/lib.rs"
+        )));
+    }
+}
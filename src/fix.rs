@@ -1,8 +1,19 @@
-use std::sync::mpsc::channel;
+use std::mem;
+use std::path::{Path, PathBuf};
 
-use crate::coverage::{PackageCoverage, TotalCoverage};
+use crate::cache::{CacheEntry, FixCache};
+
+use crate::coverage::{
+    format_percent, percent, CoverageSummary, DeclaredTotals, FileCoverage, PackageCoverage,
+};
+use crate::engine::collect_fixes;
 use crate::error::*;
-use crate::rule::{default_rules, Rule, SourceCode};
+use crate::explain::{group_adjustments, Adjustment};
+use crate::filter::{is_external_path, GlobPattern};
+use crate::rule::{default_rules, Fixes, Rule, SourceCode};
+use crate::source::{FsSourceProvider, SourceProvider};
+use crate::stale::{detect_stale, StaleReason};
+use crate::validate::ValidationFinding;
 
 /// Fix coverage information based on source code
 ///
@@ -11,141 +22,594 @@ use crate::rule::{default_rules, Rule, SourceCode};
 /// This struct checks the information format and modify it if it is invalid.
 pub struct CoverageFixer {
     rules: Vec<Box<dyn Rule>>,
+    scopes: Vec<RuleScope>,
+    root: Option<PathBuf>,
     num_threads: usize,
+    repair: bool,
+    explain: bool,
+    error_policy: ErrorPolicy,
+    provider: Box<dyn SourceProvider>,
+    cache: Option<FixCache>,
 }
 
 impl CoverageFixer {
     pub fn new() -> Self {
         Self {
             rules: default_rules(),
+            scopes: Vec::new(),
+            root: None,
             num_threads: 1,
+            repair: false,
+            explain: false,
+            error_policy: ErrorPolicy::Fallback,
+            provider: Box::new(FsSourceProvider::new()),
+            cache: None,
         }
     }
 
     pub fn with_rules<I: Into<Vec<Box<dyn Rule>>>>(rules: I) -> Self {
         Self {
             rules: rules.into(),
+            scopes: Vec::new(),
+            root: None,
             num_threads: 1,
+            repair: false,
+            explain: false,
+            error_policy: ErrorPolicy::Fallback,
+            provider: Box::new(FsSourceProvider::new()),
+            cache: None,
         }
     }
 
+    /// apply the rules of the scope to the files matching its patterns
+    ///
+    /// If multiple scopes match the file, the one added last is used. Files which do not
+    /// match any scope are fixed by the rules given to the constructor.
+    pub fn add_scope(&mut self, scope: RuleScope) {
+        self.scopes.push(scope);
+    }
+
+    /// directory against which the scope patterns are matched (default: none)
+    ///
+    /// Paths outside the directory are matched as they are.
+    pub fn set_root<P: Into<PathBuf>>(&mut self, root: P) {
+        self.root = Some(root.into());
+    }
+
+    /// rules applied to the file
+    pub fn rules_for(&self, path: &Path) -> &[Box<dyn Rule>] {
+        self.scope_index(path)
+            .map_or(&self.rules, |idx| &self.scopes[idx].rules)
+    }
+
+    /// index of the scope matching the file, or `None` if no scope matches
+    fn scope_index(&self, path: &Path) -> Option<usize> {
+        let relative = match self.root {
+            Some(ref root) => path.strip_prefix(root).unwrap_or(path),
+            None => path,
+        };
+
+        self.scopes
+            .iter()
+            .rposition(|scope| scope.is_match(relative))
+    }
+
+    /// returns `true` if no rules are applied to any file
+    fn has_no_rules(&self) -> bool {
+        self.rules.is_empty() && self.scopes.iter().all(|scope| scope.rules.is_empty())
+    }
+
     #[cfg(feature = "parallel")]
     pub fn set_num_threads(&mut self, num_threads: usize) {
         self.num_threads = num_threads;
     }
 
+    /// set how to handle the files which cannot be fixed (e.g. source file is missing)
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+    }
+
+    /// set where to read the source files from (default: filesystem)
+    pub fn set_source_provider<P: SourceProvider + 'static>(&mut self, provider: P) {
+        self.provider = Box::new(provider);
+    }
+
+    /// cache the fixes of each source file on the disk
+    ///
+    /// Unchanged files are fixed without parsing if all rules support caching (see
+    /// `Rule::cache_key()`).
+    pub fn set_cache(&mut self, cache: FixCache) {
+        self.cache = Some(cache);
+    }
+
+    /// record the provenance of the removed lines and branches (default: `false`)
+    ///
+    /// `FileFixReport::adjustments` and `FileFixReport::removed_source` are left empty
+    /// unless this is enabled.
+    pub fn set_explain(&mut self, explain: bool) {
+        self.explain = explain;
+    }
+
+    /// repair inconsistent coverage information before applying the rules
+    ///
+    /// See `PackageCoverage::repair()` for details.
+    pub fn set_repair(&mut self, repair: bool) {
+        self.repair = repair;
+    }
+
+    /// call `Rule::prepare()` of the rules applied to at least one file, with the files
+    /// to which each of them is applied
+    fn prepare(&self, data: &PackageCoverage) -> Result<(), Error> {
+        // files of the default rules, followed by the files of each scope
+        let mut files: Vec<Vec<&FileCoverage>> = vec![Vec::new(); self.scopes.len() + 1];
+        for file_cov in data.file_coverages() {
+            if !is_external_path(file_cov.path()) {
+                let idx = self.scope_index(file_cov.path()).map_or(0, |idx| idx + 1);
+                files[idx].push(file_cov);
+            }
+        }
+
+        let scoped = self.scopes.iter().map(|scope| &scope.rules);
+        for (rules, files) in std::iter::once(&self.rules).chain(scoped).zip(files) {
+            if files.is_empty() {
+                continue;
+            }
+
+            for rule in rules {
+                rule.prepare(&files, &*self.provider)
+                    .chain_err(|| format!("Failed to prepare rule {:?}", rule.name()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// repair inconsistent coverage information, reading the sources from the provider
+    ///
+    /// `fix()` calls this before applying the rules if the repair is enabled by
+    /// `set_repair()`. Returns the changes made.
+    pub fn repair(&self, data: &mut PackageCoverage) -> Vec<ValidationFinding> {
+        debugln!("Repairing package coverage");
+        let repaired = data.repair_with(&*self.provider);
+        for finding in &repaired {
+            warnln!("Repaired {}", finding);
+        }
+
+        repaired
+    }
+
     /// fix coverage information
     #[cfg(not(feature = "parallel"))]
-    pub fn fix(&self, data: &mut PackageCoverage) -> Result<(), Error> {
-        if self.rules.is_empty() {
+    pub fn fix(&self, data: &mut PackageCoverage) -> Result<FixReport, Error> {
+        let repaired = if self.repair {
+            self.repair(data)
+        } else {
+            Vec::new()
+        };
+        let mut report = FixReport::new(data, repaired);
+
+        if self.has_no_rules() {
             debugln!("Skipping fix because rules are empty");
-            return Ok(());
+            return Ok(report);
         }
 
-        let old = CoverageSummary::new(data);
+        self.prepare(data)?;
 
         debugln!("Fixing package coverage");
-        for file_cov in &mut data.file_coverages {
-            file_cov.line_coverages.sort_by_key(|v| v.line_number);
-            file_cov.branch_coverages.sort_by_key(|v| v.line_number);
-
-            let path = file_cov.path();
-            debugln!("Processing file {:?}", path);
-
-            let source = SourceCode::new(path)?;
-
-            for rule in self.rules.iter() {
-                rule.fix_file_coverage(&source, file_cov);
+        let mut dropped = Vec::new();
+        for (idx, file_cov) in data.file_coverages.iter_mut().enumerate() {
+            let res = self.fix_file(file_cov);
+            if !self.handle_result(file_cov.path(), res, &mut report)? {
+                dropped.push(idx);
             }
-
-            file_cov.line_coverages.retain(|v| v.count.is_some());
-            file_cov.branch_coverages.retain(|v| v.taken.is_some());
         }
+        remove_indices(&mut data.file_coverages, &dropped);
 
-        let new = CoverageSummary::new(data);
+        report.after = CoverageSummary::new(data);
 
         infoln!("Coverages are fixed successfully!");
-        report_diff(&old, &new);
+        report_diff(&report.before, &report.after);
 
-        Ok(())
+        Ok(report)
     }
 
     /// fix coverage information
     #[cfg(feature = "parallel")]
-    pub fn fix(&self, data: &mut PackageCoverage) -> Result<(), Error> {
+    pub fn fix(&self, data: &mut PackageCoverage) -> Result<FixReport, Error> {
         use scoped_threadpool::Pool;
+        use std::sync::mpsc::channel;
+
+        let repaired = if self.repair {
+            self.repair(data)
+        } else {
+            Vec::new()
+        };
+        let mut report = FixReport::new(data, repaired);
 
-        if self.rules.is_empty() {
+        if self.has_no_rules() {
             debugln!("Skipping fix because rules are empty");
-            return Ok(());
+            return Ok(report);
         }
 
-        let old = CoverageSummary::new(data);
+        self.prepare(data)?;
 
         debugln!("Fixing package coverage");
 
         let num_files = data.file_coverages.len();
         let mut pool = Pool::new(self.num_threads as u32);
-        let (tx, rx) = channel::<Result<(), Error>>();
+        let (tx, rx) = channel::<(usize, Result<Option<FileFixReport>, Error>)>();
 
         pool.scoped(|scoped| {
-            for file_cov in &mut data.file_coverages {
-                let mut task = move || {
-                    let path = file_cov.path();
-                    debugln!("Processing file {:?}", path);
+            for (idx, file_cov) in data.file_coverages.iter_mut().enumerate() {
+                // propagate error
+                let tx = tx.clone();
+                scoped.execute(move || tx.send((idx, self.fix_file(file_cov))).unwrap());
+            }
+        });
+
+        let mut results: Vec<_> = rx.iter().take(num_files).collect();
+        results.sort_by_key(|&(idx, _)| idx);
+
+        let mut dropped = Vec::new();
+        for (idx, res) in results {
+            if !self.handle_result(data.file_coverages[idx].path(), res, &mut report)? {
+                dropped.push(idx);
+            }
+        }
+        remove_indices(&mut data.file_coverages, &dropped);
+
+        report.after = CoverageSummary::new(data);
+
+        infoln!("Coverages are fixed successfully!");
+        report_diff(&report.before, &report.after);
+
+        Ok(report)
+    }
+
+    /// handle the result of `fix_file` according to the error policy
+    ///
+    /// Returns `false` if the file should be removed from the coverage.
+    fn handle_result(
+        &self,
+        path: &Path,
+        res: Result<Option<FileFixReport>, Error>,
+        report: &mut FixReport,
+    ) -> Result<bool, Error> {
+        let e = match res {
+            Ok(Some(file_report)) => {
+                report.files.push(file_report);
+                return Ok(true);
+            }
+            Ok(None) => return Ok(true),
+            Err(e) => e,
+        };
+
+        let message = e
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(": ");
+
+        match self.error_policy {
+            ErrorPolicy::Fallback | ErrorPolicy::Abort => Err(e),
+            ErrorPolicy::SkipFile => {
+                warnln!("Warning: {} (coverage is kept unchanged)", message);
+                report.warnings.push(FileWarning {
+                    path: path.to_owned(),
+                    message,
+                });
+                Ok(true)
+            }
+            ErrorPolicy::DropFile => {
+                warnln!("Warning: {} (coverage is removed)", message);
+                report.warnings.push(FileWarning {
+                    path: path.to_owned(),
+                    message,
+                });
+                Ok(false)
+            }
+        }
+    }
+
+    /// walk the source code only once for all applicable rules which provide the visitor
+    fn record_fixes(
+        rules: &[Box<dyn Rule>],
+        source: &SourceCode,
+        parsed: bool,
+    ) -> Vec<Option<Fixes>> {
+        let mut visitors: Vec<_> = rules
+            .iter()
+            .map(|rule| {
+                if parsed || !rule.requires_ast() {
+                    rule.visitor()
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        collect_fixes(&mut visitors, source)
+    }
+
+    /// apply the rules to the file. `None` is returned if the file is skipped.
+    fn fix_file(&self, file_cov: &mut FileCoverage) -> Result<Option<FileFixReport>, Error> {
+        let path = file_cov.path();
+        if is_external_path(path) {
+            debugln!("Skipping external file {:?}", path);
+            return Ok(None);
+        }
+
+        debugln!("Processing file {:?}", path);
 
-                    let source = SourceCode::new(path)?;
+        let rules = self.rules_for(path);
 
-                    file_cov.line_coverages.sort_by_key(|v| v.line_number);
-                    file_cov.branch_coverages.sort_by_key(|v| v.line_number);
+        let content = self.provider.read_source(path)?;
+        let stale = detect_stale(file_cov, &content);
+        for reason in &stale {
+            warnln!("Warning: {:?} may be stale: {}", path, reason);
+        }
 
-                    for rule in self.rules.iter() {
-                        rule.fix_file_coverage(&source, file_cov);
+        let cache_key = match self.cache {
+            Some(_) => FixCache::key(rules, &content),
+            None => None,
+        };
+        let cached = match (&self.cache, &cache_key) {
+            (Some(cache), Some(key)) => cache.load(key, rules.len()),
+            _ => None,
+        };
+        let is_cached = cached.is_some();
+
+        // if the fixes are found in the cache, the source code is not parsed unless some
+        // rules have to be applied without the visitor
+        let (mut source, parse_error, mut fixes) = match cached {
+            Some(entry) => {
+                debugln!("Using cached fixes for {:?}", path);
+                let source = SourceCode::text_only(content);
+                (source, entry.parse_error, entry.fixes)
+            }
+            None => {
+                let (source, parse_error) = SourceCode::parse_lenient(content);
+                let parse_error = parse_error.map(|e| e.to_string());
+                let fixes = Self::record_fixes(rules, &source, parse_error.is_none());
+
+                if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
+                    let entry = CacheEntry {
+                        parse_error: parse_error.clone(),
+                        fixes: fixes.clone(),
+                    };
+                    if let Err(e) = cache.store(key, &entry) {
+                        warnln!("Warning: {}", e);
                     }
+                }
 
-                    file_cov.line_coverages.retain(|v| v.count.is_some());
-                    file_cov.branch_coverages.retain(|v| v.taken.is_some());
-                    Ok(())
-                };
+                (source, parse_error, fixes)
+            }
+        };
+        let mut parsed = !is_cached;
 
-                // propagate error
-                let tx = tx.clone();
-                scoped.execute(move || tx.send(task()).unwrap());
+        if let Some(ref e) = parse_error {
+            if self.error_policy != ErrorPolicy::Fallback {
+                return Err(format!("Failed to parse {:?}: {}", path, e).into());
             }
-        });
+            warnln!(
+                "Warning: Failed to parse {:?}: {} (only text-based rules are applied)",
+                path,
+                e
+            );
+        }
 
-        for res in rx.iter().take(num_files) {
-            if res.is_err() {
-                return res;
+        // the records are restored if a rule fails, so that the file is kept unchanged
+        let original = if self.error_policy == ErrorPolicy::SkipFile {
+            Some((
+                file_cov.line_coverages.clone(),
+                file_cov.branch_coverages.clone(),
+            ))
+        } else {
+            None
+        };
+
+        file_cov.sort();
+
+        let applicable: Vec<bool> = rules
+            .iter()
+            .map(|rule| parse_error.is_none() || !rule.requires_ast())
+            .collect();
+
+        let mut file_report = FileFixReport {
+            path: file_cov.path().to_owned(),
+            rules: Vec::with_capacity(rules.len()),
+            adjustments: Vec::new(),
+            removed_source: Vec::new(),
+            parse_error,
+            stale,
+            cached: is_cached,
+        };
+
+        for (i, rule) in rules.iter().enumerate() {
+            if !applicable[i] {
+                debugln!("Skipping rule {:?} for {:?}", rule.name(), file_cov.path());
+                continue;
             }
+
+            // which lines were removed by the rule is tracked only to explain them
+            let masks = if self.explain {
+                Some(executable_masks(file_cov))
+            } else {
+                None
+            };
+            let before = executable_counts(file_cov);
+
+            let applied = fixes[i].take();
+            let reasons = match applied {
+                Some(ref fixes) => {
+                    fixes.apply(file_cov);
+                    Vec::new()
+                }
+                None => {
+                    if !parsed {
+                        source = SourceCode::parse_lenient(mem::take(&mut source.content)).0;
+                        parsed = true;
+                    }
+                    let res = rule
+                        .try_fix_file_coverage(&source, file_cov)
+                        .chain_err(|| format!("Rule {:?} failed", rule.name()));
+                    match res {
+                        Ok(reasons) => reasons,
+                        Err(e) => {
+                            if let Some((lines, branches)) = original {
+                                file_cov.line_coverages = lines;
+                                file_cov.branch_coverages = branches;
+                            }
+                            return Err(e);
+                        }
+                    }
+                }
+            };
+
+            let after = executable_counts(file_cov);
+
+            if let Some((line_mask, branch_mask)) = masks {
+                let lines: Vec<usize> = file_cov
+                    .line_coverages()
+                    .iter()
+                    .zip(line_mask)
+                    .filter(|(v, executable)| *executable && v.count.is_none())
+                    .map(|(v, _)| v.line_number)
+                    .collect();
+                let branches: Vec<usize> = file_cov
+                    .branch_coverages()
+                    .iter()
+                    .zip(branch_mask)
+                    .filter(|(v, executable)| *executable && v.taken.is_none())
+                    .map(|(v, _)| v.line_number)
+                    .collect();
+
+                if !lines.is_empty() || !branches.is_empty() {
+                    let mut kept: Vec<usize> = file_cov
+                        .line_coverages()
+                        .iter()
+                        .filter(|v| v.count.is_some())
+                        .map(|v| v.line_number)
+                        .chain(
+                            file_cov
+                                .branch_coverages()
+                                .iter()
+                                .filter(|v| v.taken.is_some())
+                                .map(|v| v.line_number),
+                        )
+                        .collect();
+                    kept.sort_unstable();
+
+                    let reason_of = |line: usize| {
+                        if let Some(origin) = applied.as_ref().and_then(|f| f.origin_of(line)) {
+                            return (origin.reason.as_str(), Some(origin.span));
+                        }
+
+                        let reason = reasons
+                            .iter()
+                            .find(|&&(l, _)| l == line)
+                            .map_or(rule.reason(), |(_, reason)| reason.as_str());
+                        (reason, None)
+                    };
+
+                    file_report.adjustments.extend(group_adjustments(
+                        rule.name(),
+                        &lines,
+                        &branches,
+                        &kept,
+                        reason_of,
+                    ));
+                }
+            }
+
+            file_report.rules.push(RuleFixReport {
+                rule: rule.name().to_owned(),
+                lines_removed: before.0.saturating_sub(after.0),
+                branches_removed: before.1.saturating_sub(after.1),
+            });
         }
 
-        let new = CoverageSummary::new(data);
+        file_cov.line_coverages.retain(|v| v.count.is_some());
+        file_cov.branch_coverages.retain(|v| v.taken.is_some());
+        file_cov.set_declared_totals(DeclaredTotals::default());
+
+        if self.explain {
+            let mut removed: Vec<usize> = file_report
+                .adjustments
+                .iter()
+                .flat_map(|a| a.lines.iter().chain(&a.branches))
+                .cloned()
+                .collect();
+            removed.sort_unstable();
+            removed.dedup();
+            let source_lines: Vec<&str> = source.content.lines().collect();
+            file_report.removed_source = removed
+                .into_iter()
+                .filter_map(|l| {
+                    let text = source_lines.get(l.checked_sub(1)?)?;
+                    Some((l, text.to_string()))
+                })
+                .collect();
+        }
 
-        infoln!("Coverages are fixed successfully!");
-        report_diff(&old, &new);
+        Ok(Some(file_report))
+    }
+}
 
-        Ok(())
+/// whether each line and branch is executable
+fn executable_masks(file_cov: &FileCoverage) -> (Vec<bool>, Vec<bool>) {
+    let line_mask = file_cov
+        .line_coverages()
+        .iter()
+        .map(|v| v.count.is_some())
+        .collect();
+    let branch_mask = file_cov
+        .branch_coverages()
+        .iter()
+        .map(|v| v.taken.is_some())
+        .collect();
+    (line_mask, branch_mask)
+}
+
+/// number of executable lines and branches
+fn executable_counts(file_cov: &FileCoverage) -> (usize, usize) {
+    let lines = file_cov
+        .line_coverages()
+        .iter()
+        .filter(|v| v.count.is_some())
+        .count();
+    let branches = file_cov
+        .branch_coverages()
+        .iter()
+        .filter(|v| v.taken.is_some())
+        .count();
+    (lines, branches)
+}
+
+/// remove the elements at the sorted indices
+fn remove_indices<T>(vec: &mut Vec<T>, indices: &[usize]) {
+    for &idx in indices.iter().rev() {
+        vec.remove(idx);
     }
 }
 
 fn report_diff(old: &CoverageSummary, new: &CoverageSummary) {
     infoln!(
-        "  line:   {:.2}% ({} of {} lines)    => {:.2}% ({} of {} lines)",
-        old.line_percent(),
+        "  line:   {} ({} of {} lines)    => {} ({} of {} lines)",
+        format_percent(old.line_percent()),
         old.line_executed,
         old.line_total,
-        new.line_percent(),
+        format_percent(new.line_percent()),
         new.line_executed,
         new.line_total,
     );
 
     infoln!(
-        "  branch: {:.2}% ({} of {} branches) => {:.2}% ({} of {} branches)\n",
-        old.branch_percent(),
+        "  branch: {} ({} of {} branches) => {} ({} of {} branches)\n",
+        format_percent(old.branch_percent()),
         old.branch_executed,
         old.branch_total,
-        new.branch_percent(),
+        format_percent(new.branch_percent()),
         new.branch_executed,
         new.branch_total,
     );
@@ -157,28 +621,190 @@ impl Default for CoverageFixer {
     }
 }
 
-struct CoverageSummary {
-    line_executed: usize,
-    line_total: usize,
-    branch_executed: usize,
-    branch_total: usize,
+/// Set of the rules applied to the files matching the glob patterns
+///
+/// Patterns are matched relative to the root directory of `CoverageFixer` (see
+/// `GlobPattern` for the syntax).
+pub struct RuleScope {
+    patterns: Vec<GlobPattern>,
+    rules: Vec<Box<dyn Rule>>,
 }
 
-impl CoverageSummary {
-    fn new(data: &PackageCoverage) -> Self {
+impl RuleScope {
+    pub fn new<I: Into<Vec<Box<dyn Rule>>>>(rules: I) -> Self {
         Self {
-            line_executed: data.line_executed(),
-            line_total: data.line_total(),
-            branch_executed: data.branch_executed(),
-            branch_total: data.branch_total(),
+            patterns: Vec::new(),
+            rules: rules.into(),
         }
     }
 
-    fn line_percent(&self) -> f64 {
-        (self.line_executed as f64) / (self.line_total as f64) * 100.0
+    pub fn add_pattern<S: Into<String>>(&mut self, pattern: S) {
+        self.patterns.push(GlobPattern::new(pattern));
+    }
+
+    pub fn patterns(&self) -> &[GlobPattern] {
+        &self.patterns
     }
 
-    fn branch_percent(&self) -> f64 {
-        (self.branch_executed as f64) / (self.branch_total as f64) * 100.0
+    pub fn rules(&self) -> &[Box<dyn Rule>] {
+        &self.rules
     }
+
+    /// returns `true` if the path matches one of the patterns
+    pub fn is_match(&self, path: &Path) -> bool {
+        self.patterns.iter().any(|p| p.is_match(path))
+    }
+}
+
+/// How to handle the files which cannot be fixed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ErrorPolicy {
+    /// apply only the rules which do not require the syntax tree to the files which
+    /// cannot be parsed, and abort on the other errors
+    #[default]
+    Fallback,
+    /// abort the whole fix and return the error
+    Abort,
+    /// keep the coverage of the file unchanged
+    SkipFile,
+    /// remove the coverage of the file
+    DropFile,
+}
+
+/// Error occurred while fixing a file, which was handled by the error policy
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileWarning {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+/// Result of `CoverageFixer::fix`
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixReport {
+    /// totals before applying the rules (after repairing)
+    pub before: CoverageSummary,
+    /// totals after applying the rules
+    pub after: CoverageSummary,
+    /// inconsistencies repaired before applying the rules
+    pub repaired: Vec<ValidationFinding>,
+    /// reports for each processed file. Skipped files are not included.
+    pub files: Vec<FileFixReport>,
+    /// files which could not be fixed (see `ErrorPolicy`)
+    pub warnings: Vec<FileWarning>,
+}
+
+impl FixReport {
+    fn new(data: &PackageCoverage, repaired: Vec<ValidationFinding>) -> Self {
+        let summary = CoverageSummary::new(data);
+        Self {
+            before: summary,
+            after: summary,
+            repaired,
+            files: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// number of executable lines removed by the rules
+    pub fn lines_removed(&self) -> usize {
+        self.files.iter().map(|f| f.lines_removed()).sum()
+    }
+
+    /// number of executable branches removed by the rules
+    pub fn branches_removed(&self) -> usize {
+        self.files.iter().map(|f| f.branches_removed()).sum()
+    }
+
+    /// percentage of executable lines removed by the rules
+    pub fn line_removed_percent(&self) -> Option<f64> {
+        percent(self.lines_removed(), self.before.line_total)
+    }
+
+    /// percentage of executable branches removed by the rules
+    pub fn branch_removed_percent(&self) -> Option<f64> {
+        percent(self.branches_removed(), self.before.branch_total)
+    }
+
+    /// files to which only text-based rules were applied
+    pub fn downgraded_files(&self) -> Vec<&FileFixReport> {
+        self.files
+            .iter()
+            .filter(|f| f.parse_error.is_some())
+            .collect()
+    }
+
+    /// removed lines and branches for each rule, summed over all files
+    pub fn rule_totals(&self) -> Vec<RuleFixReport> {
+        let mut totals: Vec<RuleFixReport> = Vec::new();
+
+        for rule_report in self.files.iter().flat_map(|f| f.rules.iter()) {
+            match totals.iter_mut().find(|v| v.rule == rule_report.rule) {
+                Some(total) => {
+                    total.lines_removed += rule_report.lines_removed;
+                    total.branches_removed += rule_report.branches_removed;
+                }
+                None => totals.push(rule_report.clone()),
+            }
+        }
+
+        totals
+    }
+}
+
+/// Changes made to a single file by `CoverageFixer::fix`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileFixReport {
+    pub path: PathBuf,
+    /// reports for each rule in the order of application
+    pub rules: Vec<RuleFixReport>,
+    /// provenance of the removed lines and branches. Empty unless
+    /// `CoverageFixer::set_explain()` is enabled.
+    pub adjustments: Vec<Adjustment>,
+    /// line numbers and contents of the removed lines, in ascending order. Empty unless
+    /// `CoverageFixer::set_explain()` is enabled.
+    pub removed_source: Vec<(usize, String)>,
+    /// error message if the source file cannot be parsed. In that case, the rules which
+    /// require the syntax tree are not applied.
+    pub parse_error: Option<String>,
+    /// reasons why the source file seems to differ from the one which produced the
+    /// coverage. Rules are applied anyway.
+    pub stale: Vec<StaleReason>,
+    /// whether the fixes were loaded from the cache
+    pub cached: bool,
+}
+
+impl FileFixReport {
+    pub fn lines_removed(&self) -> usize {
+        self.rules.iter().map(|r| r.lines_removed).sum()
+    }
+
+    pub fn branches_removed(&self) -> usize {
+        self.rules.iter().map(|r| r.branches_removed).sum()
+    }
+
+    /// content of the removed line, or `None` if the line was not removed
+    pub fn removed_line(&self, line_number: usize) -> Option<&str> {
+        self.removed_source
+            .binary_search_by_key(&line_number, |&(l, _)| l)
+            .ok()
+            .map(|idx| self.removed_source[idx].1.as_str())
+    }
+
+    /// report of the rule with the name
+    pub fn rule(&self, name: &str) -> Option<&RuleFixReport> {
+        self.rules.iter().find(|r| r.rule == name)
+    }
+}
+
+/// Number of executable lines and branches removed by a rule
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuleFixReport {
+    /// name of the rule (`Rule::name()`)
+    pub rule: String,
+    pub lines_removed: usize,
+    pub branches_removed: usize,
 }
@@ -1,9 +1,10 @@
+use std::fs;
 use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
 
 use crate::coverage::{
-    BranchCoverage, CoverageReader, CoverageWriter, FileCoverage, LineCoverage, PackageCoverage,
-    TotalCoverage,
+    normalize_path, relative_path, BranchCoverage, CoverageReader, CoverageWriter, DeclaredTotals,
+    FileCoverage, LineCoverage, PackageCoverage, TotalCoverage,
 };
 use crate::error::*;
 
@@ -27,8 +28,8 @@ enum RawData<'a> {
     /// \# Fn Executed
     FNH(u32),
 
-    /// Executions for some Line
-    DA(usize, u32),
+    /// Executions for some Line (with optional checksum of the line)
+    DA(usize, u32, Option<&'a str>),
 
     /// \# Lines Found
     LF(u32),
@@ -49,8 +50,19 @@ enum RawData<'a> {
     EndOfRecord,
 }
 
+/// How to write the paths of source files outside the project root
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutsidePathStyle {
+    /// write absolute paths (e.g. `/home/user/sibling/src/lib.rs`)
+    Absolute,
+    /// write paths relative to the project root (e.g. `../sibling/src/lib.rs`)
+    Relative,
+}
+
 pub struct LcovParser {
     root: PathBuf,
+    remaps: Vec<(PathBuf, PathBuf)>,
+    outside_path_style: OutsidePathStyle,
 }
 
 impl CoverageReader for LcovParser {
@@ -59,6 +71,8 @@ impl CoverageReader for LcovParser {
         let mut line_coverages = Vec::new();
         let mut branch_coverages = Vec::new();
         let mut file_coverages = Vec::new();
+        let mut declared = DeclaredTotals::default();
+        let mut checksums = Vec::new();
         let mut filename = PathBuf::new();
         let mut testname = String::new();
 
@@ -80,12 +94,15 @@ impl CoverageReader for LcovParser {
                 RawData::SF(file) => {
                     filename = file.into();
                 }
-                RawData::DA(line, count) => {
+                RawData::DA(line, count, checksum) => {
                     if line > 0 {
                         line_coverages.push(LineCoverage {
                             line_number: line,
                             count: Some(count),
                         });
+                        if let Some(checksum) = checksum {
+                            checksums.push((line, checksum.to_owned()));
+                        }
                     }
                 }
                 RawData::BRDA(line, block, _, taken) => {
@@ -97,14 +114,26 @@ impl CoverageReader for LcovParser {
                         });
                     }
                 }
+                RawData::LF(n) => declared.line_total = Some(n as usize),
+                RawData::LH(n) => declared.line_executed = Some(n as usize),
+                RawData::BRF(n) => declared.branch_total = Some(n as usize),
+                RawData::BRH(n) => declared.branch_executed = Some(n as usize),
                 RawData::EndOfRecord => {
-                    let filepath = self.root.join(&filename);
+                    let mut filepath = normalize_path(&self.root.join(self.remap(&filename)));
+                    if let Some(relative) = self.strip_root(&filepath) {
+                        filepath = normalize_path(&self.root.join(relative));
+                    }
 
-                    let file_coverage = FileCoverage::new(
+                    let mut file_coverage = FileCoverage::new(
                         filepath,
                         take_vec(&mut line_coverages),
                         take_vec(&mut branch_coverages),
                     );
+                    file_coverage.set_declared_totals(declared);
+                    declared = DeclaredTotals::default();
+                    for (line, checksum) in checksums.drain(..) {
+                        file_coverage.set_line_checksum(line, checksum);
+                    }
                     file_coverages.push(file_coverage);
                 }
                 _ => {}
@@ -125,7 +154,97 @@ impl CoverageWriter for LcovParser {
 
 impl LcovParser {
     pub fn new<P: Into<PathBuf>>(root: P) -> Self {
-        Self { root: root.into() }
+        Self {
+            root: root.into(),
+            remaps: Vec::new(),
+            outside_path_style: OutsidePathStyle::Absolute,
+        }
+    }
+
+    /// set how to write the paths of source files outside the project root
+    pub fn set_outside_path_style(&mut self, style: OutsidePathStyle) {
+        self.outside_path_style = style;
+    }
+
+    /// replace the path prefix `from` with `to` when reading the coverage
+    ///
+    /// This is useful when the coverage was collected in another environment
+    /// (e.g. inside the docker container). Relative `to` path is resolved from the
+    /// project root. When writing the coverage, the paths under `to` are mapped back
+    /// to `from`. If several rules match, the one with the longest prefix is used
+    /// regardless of the order of the rules.
+    pub fn add_remap<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q) {
+        self.remaps
+            .push((normalize_path(from.as_ref()), normalize_path(to.as_ref())));
+    }
+
+    fn remap(&self, path: &Path) -> PathBuf {
+        let path = normalize_path(path);
+        let matched = self
+            .remaps
+            .iter()
+            .rev()
+            .filter_map(|(from, to)| {
+                let rest = path.strip_prefix(from).ok()?;
+                Some((from.components().count(), to.join(rest)))
+            })
+            .max_by_key(|&(len, _)| len);
+
+        match matched {
+            Some((_, remapped)) => remapped,
+            None => path,
+        }
+    }
+
+    fn unmap(&self, path: &Path) -> Option<PathBuf> {
+        let path = normalize_path(path);
+        self.remaps
+            .iter()
+            .rev()
+            .filter_map(|(from, to)| {
+                let to = normalize_path(&self.root.join(to));
+                let rest = path.strip_prefix(&to).ok()?;
+                Some((to.components().count(), from.join(rest)))
+            })
+            .max_by_key(|&(len, _)| len)
+            .map(|(_, unmapped)| unmapped)
+    }
+
+    /// path relative to the project root, or `None` if the file is outside the root
+    ///
+    /// Symbolic links are resolved if the path is not under the root as it is, so that
+    /// the same file is regarded as inside the root when both reading and writing.
+    fn strip_root(&self, path: &Path) -> Option<PathBuf> {
+        let path = normalize_path(path);
+        let root = normalize_path(&self.root);
+        if let Ok(relative) = path.strip_prefix(&root) {
+            return Some(relative.to_owned());
+        }
+
+        let real_path = fs::canonicalize(&path).ok()?;
+        let real_root = fs::canonicalize(&root).ok()?;
+        real_path
+            .strip_prefix(&real_root)
+            .ok()
+            .map(|relative| relative.to_owned())
+    }
+
+    /// path written into the `SF` field
+    fn output_path(&self, path: &Path) -> PathBuf {
+        if let Some(path) = self.unmap(path) {
+            return path;
+        }
+
+        if let Some(relative) = self.strip_root(path) {
+            return relative;
+        }
+
+        let path = normalize_path(path);
+        let root = normalize_path(&self.root);
+        match self.outside_path_style {
+            OutsidePathStyle::Absolute => path,
+            OutsidePathStyle::Relative => relative_path(&path, &root).unwrap_or(path),
+        }
     }
 
     fn parse_line<'a>(&self, line: &'a str) -> Option<RawData<'a>> {
@@ -161,7 +280,8 @@ impl LcovParser {
             "DA" => {
                 let line = contents.next()?.parse().ok()?;
                 let count = contents.next()?.parse().ok()?;
-                Some(RawData::DA(line, count))
+                let checksum = contents.next().filter(|v| !v.is_empty());
+                Some(RawData::DA(line, count, checksum))
             }
             "LF" => Some(RawData::LF(contents.next()?.parse().ok()?)),
             "LH" => Some(RawData::LH(contents.next()?.parse().ok()?)),
@@ -199,7 +319,7 @@ impl LcovParser {
         writer: &mut W,
         data: &FileCoverage,
     ) -> Result<(), Error> {
-        let path = data.path().strip_prefix(&self.root).unwrap();
+        let path = self.output_path(data.path());
         writeln!(writer, "SF:{}", path.display())?;
 
         let mut current_line = 1;
@@ -219,7 +339,7 @@ impl LcovParser {
         writeln!(writer, "BRH:{}", data.branch_executed())?;
 
         for cov in data.line_coverages() {
-            self.write_line_coverage(writer, cov)?;
+            self.write_line_coverage(writer, cov, data.line_checksum(cov.line_number))?;
         }
 
         writeln!(writer, "LF:{}", data.line_total())?;
@@ -254,9 +374,15 @@ impl LcovParser {
         &self,
         writer: &mut W,
         data: &LineCoverage,
+        checksum: Option<&str>,
     ) -> Result<(), Error> {
         if let Some(count) = data.count {
-            writeln!(writer, "DA:{},{}", data.line_number, count)?;
+            match checksum {
+                Some(checksum) => {
+                    writeln!(writer, "DA:{},{},{}", data.line_number, count, checksum)?
+                }
+                None => writeln!(writer, "DA:{},{}", data.line_number, count)?,
+            }
         }
 
         Ok(())
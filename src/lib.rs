@@ -11,6 +11,30 @@
 //! You can also create custom reader/writer by implementing
 //! `CoverageReader`/`CoverageWriter` trait. If you want to define a new rule to
 //! fix the coverage, implement `Rule` trait.
+//!
+//! # Serialization
+//!
+//! With `serde` feature enabled, coverage types implement `Serialize` and
+//! `Deserialize`. `PackageCoverage` is represented in JSON as follows, and this shape is
+//! kept stable across releases. `null` means that the line (or branch) is not executable.
+//!
+//! ```json
+//! {
+//!   "name": "test name",
+//!   "file_coverages": [
+//!     {
+//!       "path": "/path/to/src/lib.rs",
+//!       "line_coverages": [
+//!         { "line_number": 1, "count": 3 },
+//!         { "line_number": 2, "count": null }
+//!       ],
+//!       "branch_coverages": [
+//!         { "line_number": 1, "block_number": 0, "taken": true }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
 
 #[macro_use]
 mod logger;
@@ -22,8 +46,58 @@ pub use coverage::*;
 mod fix;
 pub use fix::*;
 
+mod cache;
+pub use cache::*;
+
+#[cfg(feature = "config")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config")))]
+mod config;
+#[cfg(feature = "config")]
+pub use config::*;
+
+mod engine;
+
+mod diff;
+pub use diff::*;
+
+mod explain;
+pub use explain::*;
+
+mod filter;
+pub use filter::*;
+
+#[cfg(feature = "pattern")]
+#[cfg_attr(docsrs, doc(cfg(feature = "pattern")))]
+mod pattern;
+#[cfg(feature = "pattern")]
+pub use pattern::*;
+
+#[cfg(feature = "plugin")]
+#[cfg_attr(docsrs, doc(cfg(feature = "plugin")))]
+mod plugin;
+#[cfg(feature = "plugin")]
+pub use plugin::*;
+
+mod source;
+pub use source::*;
+
+mod stale;
+pub use stale::*;
+
+mod summary;
+pub use summary::*;
+
+mod validate;
+pub use validate::*;
+
 pub mod rule;
 
+#[cfg(feature = "workspace")]
+#[cfg_attr(docsrs, doc(cfg(feature = "workspace")))]
+mod workspace;
+#[cfg(feature = "workspace")]
+pub use workspace::*;
+
 pub mod error;
 
 #[cfg(feature = "lcov")]
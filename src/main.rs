@@ -1,16 +1,36 @@
 #[macro_use]
 extern crate rust_covfix;
 
-use argparse::{ArgumentParser, Print, Store, StoreOption, StoreTrue};
+use argparse::{ArgumentParser, Collect, List, Print, StoreConst, StoreOption, StoreTrue};
 use error_chain::{bail, ChainedError};
 use std::env;
-use std::io::BufWriter;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::process::{self, Command};
 
 use rust_covfix::error::*;
-use rust_covfix::rule;
-use rust_covfix::{parser::LcovParser, CoverageFixer, CoverageReader, CoverageWriter};
+use rust_covfix::rule::RuleRegistry;
+use rust_covfix::{
+    parser::{LcovParser, OutsidePathStyle},
+    CoverageDiff, CoverageFixer, CoverageReader, CoverageSummary, CoverageWriter, ErrorPolicy,
+    FixCache, FsSourceProvider, GitSourceProvider, PackageCoverage, PathFilter, SourceProvider,
+};
+
+#[cfg(feature = "parallel")]
+use argparse::Store;
+
+#[cfg(feature = "workspace")]
+use rust_covfix::{format_percent, Workspace};
+
+#[cfg(feature = "config")]
+use rust_covfix::{Config, PatternConfig, PluginConfig, RuleOverride};
+
+#[cfg(feature = "plugin")]
+use rust_covfix::PluginRule;
+
+#[cfg(feature = "archive")]
+use rust_covfix::ArchiveSourceProvider;
 
 fn main() {
     if let Err(e) = run() {
@@ -20,7 +40,8 @@ fn main() {
 }
 
 fn run() -> Result<(), Error> {
-    let options = Arguments::parse()?;
+    #[cfg_attr(not(feature = "config"), allow(unused_mut))]
+    let mut options = Arguments::parse()?;
 
     if options.verbose {
         rust_covfix::set_verbosity(4);
@@ -28,50 +49,289 @@ fn run() -> Result<(), Error> {
         rust_covfix::set_verbosity(3);
     }
 
-    let root_dir = options
-        .root
-        .clone()
-        .or_else(find_root_dir)
-        .ok_or("cannot find the project root directory. Did you run `cargo test` at first?")?;
+    let root_dir = match options.root.clone().or_else(find_root_dir) {
+        Some(dir) => dir,
+        // rules can be listed outside of the project
+        None if options.list_rules => {
+            env::current_dir().chain_err(|| "cannot detect the current directory.")?
+        }
+        None => bail!("cannot find the project root directory. Did you run `cargo test` at first?"),
+    };
 
     debugln!("Project root directory: {:?}", root_dir);
 
-    let parser = LcovParser::new(root_dir);
+    #[cfg(feature = "config")]
+    {
+        let config = match options.config {
+            Some(ref file) => Some(Config::from_file(file)?),
+            None if !options.no_config => Config::discover(&root_dir)?,
+            None => None,
+        };
+
+        if let Some(config) = config {
+            if let Some(path) = config.path() {
+                debugln!("Loaded configuration from {:?}", path);
+            }
+            options.merge_config(config);
+        }
+    }
+
+    options
+        .validate_merged()
+        .chain_err(|| "Argument validation failed")?;
+
+    let mut parser = LcovParser::new(&root_dir);
+    for (from, to) in &options.remaps {
+        parser.add_remap(from, to);
+    }
+    if options.relative_outside_root() {
+        parser.set_outside_path_style(OutsidePathStyle::Relative);
+    }
 
-    let mut fixer = match options.rules {
-        Some(ref rule_str) => {
-            let mut rules = vec![];
-            for segment in rule_str.split(',').filter(|v| !v.is_empty()) {
-                rules.push(rule::from_str(segment)?);
+    let mut filter = PathFilter::new(&root_dir);
+    for pattern in &options.includes {
+        filter.add_include(pattern.as_str());
+    }
+    for pattern in &options.excludes {
+        filter.add_exclude(pattern.as_str());
+    }
+
+    #[cfg_attr(
+        not(any(feature = "plugin", all(feature = "config", feature = "pattern"))),
+        allow(unused_mut)
+    )]
+    let mut registry = RuleRegistry::new();
+
+    #[cfg(all(feature = "config", feature = "pattern"))]
+    {
+        for pattern in &options.pattern_configs {
+            pattern.register(&mut registry)?;
+        }
+    }
+
+    #[cfg(feature = "plugin")]
+    {
+        for plugin in &options.plugins {
+            let pos = plugin
+                .find('=')
+                .ok_or_else(|| format!("Invalid plugin (expected NAME=COMMAND): {:?}", plugin))?;
+            PluginRule::new(&plugin[..pos], &plugin[pos + 1..]).register(&mut registry, true)?;
+        }
+
+        #[cfg(feature = "config")]
+        {
+            for plugin in &options.plugin_configs {
+                plugin.register(&mut registry)?;
             }
-            CoverageFixer::with_rules(rules)
         }
-        None => CoverageFixer::default(),
-    };
+    }
+
+    if options.list_rules {
+        println!("{}", list_rules(&registry));
+        return Ok(());
+    }
+
+    let rules = registry.build(options.rules.as_deref(), &options.rule_options)?;
+
+    let mut fixer = CoverageFixer::with_rules(rules);
+    fixer.set_root(&root_dir);
+
+    #[cfg(feature = "config")]
+    {
+        for rule_override in &options.overrides {
+            let scope = rule_override.create_scope(
+                &registry,
+                options.rules.as_deref(),
+                &options.rule_options,
+            )?;
+            fixer.add_scope(scope);
+        }
+    }
+
+    fixer.set_repair(options.repair());
+    fixer.set_explain(options.explain || options.explain_json.is_some());
+
+    if let Some(ref dir) = options.cache_dir {
+        debugln!("Using cache directory {:?}", dir);
+        fixer.set_cache(FixCache::new(dir));
+    }
+
+    let mut provider: Box<dyn SourceProvider> = Box::new(FsSourceProvider::new());
+
+    if let Some(ref rev) = options.source_rev {
+        let git_provider = GitSourceProvider::new(&root_dir, rev)?;
+        debugln!(
+            "Reading source files from git commit {}",
+            git_provider.commit()
+        );
+        provider = Box::new(git_provider);
+    }
+
+    #[cfg(feature = "archive")]
+    {
+        if let Some(ref archive) = options.source_archive {
+            debugln!("Reading source files from archive {:?}", archive);
+            let mut archive_provider = ArchiveSourceProvider::new(&root_dir);
+            archive_provider.load_file(archive)?;
+            debugln!(
+                "Found {} source files in the archive",
+                archive_provider.len()
+            );
+            provider = Box::new(archive_provider);
+        }
+    }
+
+    fixer.set_error_policy(match options.on_error() {
+        "fallback" => ErrorPolicy::Fallback,
+        "abort" => ErrorPolicy::Abort,
+        "skip-file" => ErrorPolicy::SkipFile,
+        "drop-file" => ErrorPolicy::DropFile,
+        _ => unreachable!(),
+    });
 
     #[cfg(feature = "parallel")]
     {
         fixer.set_num_threads(options.num_threads);
     }
 
-    debugln!("Reading data file {:?}", options.input_file);
+    let mut coverage = PackageCoverage::new(vec![]);
 
-    let mut coverage = parser
-        .read_from_file(&options.input_file)
-        .chain_err(|| format!("Failed to read coverage from {:?}", options.input_file))?;
+    for input_file in &options.input_files {
+        debugln!("Reading data file {:?}", input_file);
+
+        let data = parser
+            .read_from_file(input_file)
+            .chain_err(|| format!("Failed to read coverage from {:?}", input_file))?;
+        coverage.merge(data);
+    }
+
+    coverage.retain_paths(&filter);
 
     debugln!("Found {} entries", coverage.file_coverages().len());
 
+    if let Some(ref rev) = options.coverage_rev {
+        let recorded = GitSourceProvider::new(&root_dir, rev)?;
+        debugln!(
+            "Comparing source files with git commit {}",
+            recorded.commit()
+        );
+
+        for stale in coverage.check_stale_sources(&recorded, &*provider, options.remap_stale) {
+            warnln!(
+                "Warning: {:?} has changed since the coverage was collected",
+                stale.path
+            );
+            if options.remap_stale && !stale.dropped_lines.is_empty() {
+                warnln!(
+                    "Warning: Dropped {} lines which cannot be mapped to the current source: {:?}",
+                    stale.dropped_lines.len(),
+                    stale.dropped_lines
+                );
+            }
+        }
+    }
+
+    fixer.set_source_provider(provider);
+
     if !options.nofix {
-        fixer
+        let report = fixer
             .fix(&mut coverage)
             .chain_err(|| "Failed to fix coverage")?;
+
+        for total in report.rule_totals() {
+            debugln!(
+                "Rule {:?} removed {} lines and {} branches",
+                total.rule,
+                total.lines_removed,
+                total.branches_removed
+            );
+        }
+
+        if options.explain {
+            let stderr = std::io::stderr();
+            let mut writer = BufWriter::new(stderr.lock());
+            report.write_explanation(&mut writer)?;
+            writer.flush()?;
+        }
+
+        #[cfg(feature = "json")]
+        if let Some(ref file) = options.explain_json {
+            debugln!("Writing provenance to {:?}", file);
+            let f =
+                fs::File::create(file).chain_err(|| format!("Failed to open file {:?}", file))?;
+            let mut writer = BufWriter::new(f);
+            report.write_explanation_json(&mut writer)?;
+            writer.flush()?;
+        }
+    } else if options.repair() {
+        fixer.repair(&mut coverage);
     }
 
-    if let Some(file) = options.output_file {
+    let summary = CoverageSummary::new(&coverage);
+
+    if let Some(ref base_file) = options.diff {
+        debugln!("Reading base data file {:?}", base_file);
+
+        let mut base = parser
+            .read_from_file(base_file)
+            .chain_err(|| format!("Failed to read coverage from {:?}", base_file))?;
+        base.retain_paths(&filter);
+
+        if let Some(ref rev) = options.base_source_rev {
+            let git_provider = GitSourceProvider::new(&root_dir, rev)?;
+            debugln!(
+                "Reading source files of the base from git commit {}",
+                git_provider.commit()
+            );
+            fixer.set_source_provider(git_provider);
+        } else if !options.nofix || options.repair() {
+            warnln!(
+                "Warning: The base coverage is fixed with the same source files as the \
+                 coverage (use --base-source-rev to read the sources of the base)"
+            );
+        }
+
+        if !options.nofix {
+            fixer
+                .fix(&mut base)
+                .chain_err(|| "Failed to fix base coverage")?;
+        } else if options.repair() {
+            fixer.repair(&mut base);
+        }
+
+        let mut diff = CoverageDiff::new(&base, &coverage);
+        for file_diff in &mut diff.file_diffs {
+            if let Ok(path) = file_diff.path.strip_prefix(&root_dir) {
+                file_diff.path = path.to_owned();
+            }
+        }
+
+        write_diff(&diff, &options)?;
+    } else {
+        write_coverage(coverage, &parser, &root_dir, &options)?;
+    }
+
+    check_thresholds(&summary, &options)
+}
+
+#[allow(unused_variables)]
+fn write_coverage(
+    coverage: PackageCoverage,
+    parser: &LcovParser,
+    root_dir: &Path,
+    options: &Arguments,
+) -> Result<(), Error> {
+    #[cfg(feature = "workspace")]
+    {
+        if let Some(ref dir) = options.split_dir {
+            return write_per_crate(coverage, parser, root_dir, dir);
+        }
+    }
+
+    if let Some(ref file) = options.output_file {
         debugln!("Writing coverage to {:?}", file);
         parser
-            .write_to_file(&coverage, &file)
+            .write_to_file(&coverage, file)
             .chain_err(|| format!("Failed to save coverage into file {:?}", file))?;
     } else {
         debugln!("Writing coverage to stdout");
@@ -83,13 +343,162 @@ fn run() -> Result<(), Error> {
     Ok(())
 }
 
+/// fail if the coverage is lower than the thresholds
+fn check_thresholds(summary: &CoverageSummary, options: &Arguments) -> Result<(), Error> {
+    // thresholds are not checked if there are no lines (or branches)
+    if let (Some(threshold), Some(percent)) = (options.fail_under_lines, summary.line_percent()) {
+        if percent < threshold {
+            bail!(
+                "Line coverage {:.2}% is below the threshold {:.2}%",
+                percent,
+                threshold
+            );
+        }
+    }
+
+    if let (Some(threshold), Some(percent)) =
+        (options.fail_under_branches, summary.branch_percent())
+    {
+        if percent < threshold {
+            bail!(
+                "Branch coverage {:.2}% is below the threshold {:.2}%",
+                percent,
+                threshold
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// name of the tracefile for the files outside the workspace members
+#[cfg(feature = "workspace")]
+const OTHERS_NAME: &str = "others";
+
+#[cfg(feature = "workspace")]
+fn write_per_crate(
+    coverage: PackageCoverage,
+    parser: &LcovParser,
+    root_dir: &Path,
+    dir: &Path,
+) -> Result<(), Error> {
+    let workspace = Workspace::from_cargo_metadata(root_dir)
+        .chain_err(|| "Failed to detect the workspace members")?;
+    let mut grouped = workspace.group(coverage);
+
+    let others = if grouped.others.is_empty() {
+        None
+    } else {
+        if grouped
+            .package_coverages()
+            .iter()
+            .any(|v| v.name() == OTHERS_NAME)
+        {
+            bail!(
+                "Cannot write the files outside the workspace members because {:?} is a member name",
+                OTHERS_NAME
+            );
+        }
+        warnln!(
+            "Warning: {} files do not belong to any workspace member, and are written into {}.info",
+            grouped.others.len(),
+            OTHERS_NAME
+        );
+        Some(PackageCoverage::with_test_name(
+            OTHERS_NAME,
+            std::mem::take(&mut grouped.others),
+        ))
+    };
+
+    fs::create_dir_all(dir).chain_err(|| format!("Failed to create directory {:?}", dir))?;
+
+    for package_coverage in grouped.package_coverages().iter().chain(&others) {
+        let summary = CoverageSummary::new(package_coverage);
+        infoln!(
+            "{}: line {} ({} of {} lines), branch {} ({} of {} branches)",
+            package_coverage.name(),
+            format_percent(summary.line_percent()),
+            summary.line_executed,
+            summary.line_total,
+            format_percent(summary.branch_percent()),
+            summary.branch_executed,
+            summary.branch_total,
+        );
+
+        let file = dir.join(format!("{}.info", package_coverage.name()));
+        debugln!("Writing coverage to {:?}", file);
+        parser
+            .write_to_file(package_coverage, &file)
+            .chain_err(|| format!("Failed to save coverage into file {:?}", file))?;
+    }
+
+    Ok(())
+}
+
+fn write_diff(diff: &CoverageDiff, options: &Arguments) -> Result<(), Error> {
+    let mut writer: Box<dyn Write> = match options.output_file {
+        Some(ref file) => {
+            debugln!("Writing coverage difference to {:?}", file);
+            let f =
+                fs::File::create(file).chain_err(|| format!("Failed to open file {:?}", file))?;
+            Box::new(BufWriter::new(f))
+        }
+        None => {
+            debugln!("Writing coverage difference to stdout");
+            Box::new(BufWriter::new(std::io::stdout()))
+        }
+    };
+
+    match options.diff_format() {
+        "text" => diff.write_text(&mut writer)?,
+        #[cfg(feature = "json")]
+        "json" => diff.write_json(&mut writer)?,
+        _ => unreachable!(),
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
 #[derive(Default)]
 struct Arguments {
-    input_file: PathBuf,
+    input_files: Vec<PathBuf>,
     output_file: Option<PathBuf>,
     root: Option<PathBuf>,
     rules: Option<String>,
+    rule_options: Vec<String>,
+    includes: Vec<String>,
+    excludes: Vec<String>,
+    remap_args: Vec<String>,
+    /// path remaps as `(FROM, TO)` pairs
+    remaps: Vec<(String, String)>,
+    relative_outside_root: Option<bool>,
+    split_dir: Option<PathBuf>,
+    source_rev: Option<String>,
+    base_source_rev: Option<String>,
+    source_archive: Option<PathBuf>,
+    coverage_rev: Option<String>,
+    remap_stale: bool,
+    cache_dir: Option<PathBuf>,
+    config: Option<PathBuf>,
+    no_config: bool,
+    fail_under_lines: Option<f64>,
+    fail_under_branches: Option<f64>,
+    #[cfg(feature = "config")]
+    overrides: Vec<RuleOverride>,
+    plugins: Vec<String>,
+    #[cfg(feature = "config")]
+    plugin_configs: Vec<PluginConfig>,
+    #[cfg(feature = "config")]
+    pattern_configs: Vec<PatternConfig>,
+    diff: Option<PathBuf>,
+    diff_format: Option<String>,
     nofix: bool,
+    repair: Option<bool>,
+    on_error: Option<String>,
+    explain: bool,
+    explain_json: Option<PathBuf>,
+    list_rules: bool,
     num_threads: usize,
     verbose: bool,
 }
@@ -103,11 +512,20 @@ impl Arguments {
             args.num_threads = num_cpus::get();
         }
 
+        let registry = RuleRegistry::new();
+        let rule_names: Vec<&str> = registry.entries().iter().map(|e| e.name()).collect();
+        let rules_help = format!(
+            "use specified rules to fix coverages (e.g. `default,-loop`). Valid names are [{}]",
+            rule_names.join(", ")
+        );
+
         let mut ap = ArgumentParser::new();
         ap.set_description("Rust coverage fixer");
-        ap.refer(&mut args.input_file)
-            .required()
-            .add_argument("file", Store, "coverage file");
+        ap.refer(&mut args.input_files).add_argument(
+            "files",
+            List,
+            "coverage files (merged into one if multiple files are given)",
+        );
         ap.add_option(
             &["-V", "--version"],
             Print(env!("CARGO_PKG_VERSION").to_owned()),
@@ -117,6 +535,35 @@ impl Arguments {
             .add_option(&["-v", "--verbose"], StoreTrue, "verbose output");
         ap.refer(&mut args.nofix)
             .add_option(&["-n", "--no-fix"], StoreTrue, "do not fix coverage");
+        ap.refer(&mut args.repair)
+            .add_option(
+                &["--repair"],
+                StoreConst(Some(true)),
+                "merge duplicate records and drop out-of-range lines before fixing coverage",
+            )
+            .add_option(
+                &["--no-repair"],
+                StoreConst(Some(false)),
+                "do not repair coverage even if enabled by the configuration",
+            );
+        ap.refer(&mut args.on_error).metavar("POLICY").add_option(
+            &["--on-error"],
+            StoreOption,
+            "how to handle the files which cannot be fixed. Valid policies are [fallback, abort, skip-file, drop-file]",
+        );
+        ap.refer(&mut args.explain).add_option(
+            &["--explain"],
+            StoreTrue,
+            "print which rule removed each line and branch to stderr",
+        );
+        #[cfg(feature = "json")]
+        {
+            ap.refer(&mut args.explain_json).metavar("FILE").add_option(
+                &["--explain-json"],
+                StoreOption,
+                "write which rule removed each line and branch into FILE in JSON format",
+            );
+        }
         ap.refer(&mut args.output_file).metavar("FILE").add_option(
             &["-o", "--output"],
             StoreOption,
@@ -130,8 +577,138 @@ impl Arguments {
         ap.refer(&mut args.rules).metavar("STR[,STR..]").add_option(
             &["--rules"],
             StoreOption,
-            "use specified rules to fix coverages. Valid names are [close, test, loop, derive]",
+            &rules_help,
+        );
+        ap.refer(&mut args.rule_options)
+            .metavar("RULE.KEY=VALUE")
+            .add_option(
+                &["--rule-option"],
+                Collect,
+                "set the option of the rule (e.g. `assert.macros=+ensure`). Can be specified multiple times",
+            );
+        #[cfg(feature = "plugin")]
+        {
+            ap.refer(&mut args.plugins).metavar("NAME=COMMAND").add_option(
+                &["--plugin"],
+                Collect,
+                "register the external executable as the rule NAME (enabled by default). Can be specified multiple times",
+            );
+        }
+        ap.refer(&mut args.list_rules).add_option(
+            &["--list-rules"],
+            StoreTrue,
+            "list available rules (including the ones from the configuration) and exit",
+        );
+        ap.refer(&mut args.includes).metavar("GLOB").add_option(
+            &["--include"],
+            Collect,
+            "only keep the coverages of files matching the pattern (can be specified multiple times)",
         );
+        ap.refer(&mut args.excludes).metavar("GLOB").add_option(
+            &["--exclude"],
+            Collect,
+            "remove the coverages of files matching the pattern (can be specified multiple times)",
+        );
+        ap.refer(&mut args.remap_args)
+            .metavar("FROM=TO")
+            .add_option(
+                &["--remap"],
+                Collect,
+                "replace path prefix FROM with TO when reading, and TO with FROM when writing",
+            );
+        ap.refer(&mut args.relative_outside_root)
+            .add_option(
+                &["--relative-outside-root"],
+                StoreConst(Some(true)),
+                "write paths of files outside the project root as relative paths",
+            )
+            .add_option(
+                &["--no-relative-outside-root"],
+                StoreConst(Some(false)),
+                "write paths of files outside the project root as absolute paths",
+            );
+        #[cfg(feature = "workspace")]
+        {
+            ap.refer(&mut args.split_dir).metavar("DIR").add_option(
+                &["--split-by-crate"],
+                StoreOption,
+                "write the coverage of each workspace member into DIR/<crate name>.info",
+            );
+        }
+        ap.refer(&mut args.source_rev).metavar("REV").add_option(
+            &["--source-rev"],
+            StoreOption,
+            "read source files at the git revision (e.g. commit hash which produced the coverage)",
+        );
+        ap.refer(&mut args.base_source_rev)
+            .metavar("REV")
+            .add_option(
+                &["--base-source-rev"],
+                StoreOption,
+                "read source files of the base coverage given by --diff at the git revision",
+            );
+        #[cfg(feature = "archive")]
+        {
+            ap.refer(&mut args.source_archive).metavar("FILE").add_option(
+                &["--source-archive"],
+                StoreOption,
+                "read source files from tar or zip archive (paths are relative to the project root)",
+            );
+        }
+        ap.refer(&mut args.coverage_rev).metavar("REV").add_option(
+            &["--coverage-rev"],
+            StoreOption,
+            "warn about source files which have changed since the git revision which produced the coverage",
+        );
+        ap.refer(&mut args.remap_stale).add_option(
+            &["--remap-stale"],
+            StoreTrue,
+            "remap line numbers of the changed files to the current sources (requires --coverage-rev)",
+        );
+        ap.refer(&mut args.cache_dir).metavar("DIR").add_option(
+            &["--cache-dir"],
+            StoreOption,
+            "cache the fix results of each source file in DIR to skip parsing unchanged files",
+        );
+        #[cfg(feature = "config")]
+        {
+            ap.refer(&mut args.config).metavar("FILE").add_option(
+                &["--config"],
+                StoreOption,
+                "read the configuration from FILE instead of covfix.toml or Cargo.toml in the project root",
+            );
+            ap.refer(&mut args.no_config).add_option(
+                &["--no-config"],
+                StoreTrue,
+                "do not read the configuration file",
+            );
+        }
+        ap.refer(&mut args.fail_under_lines)
+            .metavar("PERCENT")
+            .add_option(
+                &["--fail-under-lines"],
+                StoreOption,
+                "exit with error if the line coverage after fixing is below PERCENT",
+            );
+        ap.refer(&mut args.fail_under_branches)
+            .metavar("PERCENT")
+            .add_option(
+                &["--fail-under-branches"],
+                StoreOption,
+                "exit with error if the branch coverage after fixing is below PERCENT",
+            );
+        ap.refer(&mut args.diff).metavar("FILE").add_option(
+            &["--diff"],
+            StoreOption,
+            "compare the coverage with base coverage file instead of writing the coverage",
+        );
+        ap.refer(&mut args.diff_format)
+            .metavar("FORMAT")
+            .add_option(
+                &["--diff-format"],
+                StoreOption,
+                "output format of the coverage difference. Valid formats are [text, json (with `json` feature)]",
+            );
         #[cfg(feature = "parallel")]
         {
             ap.refer(&mut args.num_threads).metavar("NUM").add_option(
@@ -155,12 +732,189 @@ impl Arguments {
             }
         }
 
-        if !self.input_file.is_file() {
-            bail!("Input file not found: {:?}", self.input_file);
+        if self.input_files.is_empty() && !self.list_rules {
+            bail!("No input files are given");
+        }
+
+        for input_file in &self.input_files {
+            if !input_file.is_file() {
+                bail!("Input file not found: {:?}", input_file);
+            }
+        }
+
+        if let Some(ref archive) = self.source_archive {
+            if !archive.is_file() {
+                bail!("Source archive not found: {:?}", archive);
+            }
+            if self.source_rev.is_some() {
+                bail!("--source-rev and --source-archive cannot be used together");
+            }
+        }
+
+        for remap in &self.remap_args {
+            let pos = remap
+                .find('=')
+                .ok_or_else(|| format!("Invalid remap rule (expected FROM=TO): {:?}", remap))?;
+            self.remaps
+                .push((remap[..pos].to_owned(), remap[pos + 1..].to_owned()));
+        }
+
+        if self.remap_stale && self.coverage_rev.is_none() {
+            bail!("--remap-stale requires --coverage-rev");
+        }
+
+        if let Some(ref base_file) = self.diff {
+            if !base_file.is_file() {
+                bail!("Base coverage file not found: {:?}", base_file);
+            }
+            if self.split_dir.is_some() {
+                bail!("--diff and --split-by-crate cannot be used together");
+            }
+        } else if self.base_source_rev.is_some() {
+            bail!("--base-source-rev requires --diff");
+        }
+
+        if self.split_dir.is_some() && self.output_file.is_some() {
+            bail!("--split-by-crate and --output cannot be used together");
+        }
+
+        if let Some(ref config) = self.config {
+            if !config.is_file() {
+                bail!("Configuration file not found: {:?}", config);
+            }
+            if self.no_config {
+                bail!("--config and --no-config cannot be used together");
+            }
         }
 
         Ok(())
     }
+
+    /// validate the options which may be given from the configuration file
+    fn validate_merged(&self) -> Result<(), Error> {
+        let diff_formats: &[&str] = if cfg!(feature = "json") {
+            &["text", "json"]
+        } else {
+            &["text"]
+        };
+        if !diff_formats.contains(&self.diff_format()) {
+            bail!("Invalid diff format: {:?}", self.diff_format());
+        }
+
+        if !["fallback", "abort", "skip-file", "drop-file"].contains(&self.on_error()) {
+            bail!("Invalid error policy: {:?}", self.on_error());
+        }
+
+        for threshold in self
+            .fail_under_lines
+            .iter()
+            .chain(&self.fail_under_branches)
+        {
+            if !(0.0..=100.0).contains(threshold) {
+                bail!("Invalid threshold (expected 0 to 100): {}", threshold);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// apply the configuration file
+    ///
+    /// Options given from the command line take precedence, and lists in the
+    /// configuration are prepended to the ones from the command line.
+    #[cfg(feature = "config")]
+    fn merge_config(&mut self, config: Config) {
+        fn prepend<T>(list: &mut Vec<T>, mut items: Vec<T>) {
+            items.append(list);
+            *list = items;
+        }
+
+        if self.rules.is_none() {
+            self.rules = config.rules;
+        }
+        prepend(&mut self.rule_options, config.rule_options);
+        prepend(&mut self.includes, config.includes);
+        prepend(&mut self.excludes, config.excludes);
+        prepend(&mut self.remaps, config.remaps);
+
+        if self.relative_outside_root.is_none() {
+            self.relative_outside_root = config.relative_outside_root;
+        }
+        if self.repair.is_none() {
+            self.repair = config.repair;
+        }
+        if self.on_error.is_none() {
+            self.on_error = config.on_error;
+        }
+        if self.diff_format.is_none() {
+            self.diff_format = config.diff_format;
+        }
+        if self.cache_dir.is_none() {
+            self.cache_dir = config.cache_dir;
+        }
+        if self.fail_under_lines.is_none() {
+            self.fail_under_lines = config.thresholds.line;
+        }
+        if self.fail_under_branches.is_none() {
+            self.fail_under_branches = config.thresholds.branch;
+        }
+        self.overrides = config.overrides;
+
+        if cfg!(not(feature = "plugin")) && !config.plugins.is_empty() {
+            warnln!("Warning: Plugins are ignored because `plugin` feature is disabled");
+        }
+        self.plugin_configs = config.plugins;
+
+        if cfg!(not(feature = "pattern")) && !config.patterns.is_empty() {
+            warnln!("Warning: Pattern rules are ignored because `pattern` feature is disabled");
+        }
+        self.pattern_configs = config.patterns;
+    }
+
+    fn relative_outside_root(&self) -> bool {
+        self.relative_outside_root.unwrap_or(false)
+    }
+
+    fn repair(&self) -> bool {
+        self.repair.unwrap_or(false)
+    }
+
+    fn on_error(&self) -> &str {
+        self.on_error.as_deref().unwrap_or("fallback")
+    }
+
+    fn diff_format(&self) -> &str {
+        self.diff_format.as_deref().unwrap_or("text")
+    }
+}
+
+fn list_rules(registry: &RuleRegistry) -> String {
+    let width = registry
+        .entries()
+        .iter()
+        .map(|e| e.name().len())
+        .max()
+        .unwrap_or(0);
+
+    let lines: Vec<String> = registry
+        .entries()
+        .iter()
+        .map(|e| {
+            format!(
+                "{:<width$}  {}{}",
+                e.name(),
+                e.description(),
+                if e.is_default() {
+                    ""
+                } else {
+                    " (disabled by default)"
+                },
+                width = width
+            )
+        })
+        .collect();
+
+    lines.join("\n")
 }
 
 fn find_root_dir() -> Option<PathBuf> {
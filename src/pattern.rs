@@ -0,0 +1,319 @@
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+use syn::{Attribute, Block, Fields, Macro, MacroDelimiter, Signature};
+
+use crate::error::*;
+use crate::filter::GlobPattern;
+use crate::rule::{
+    attrs_start, path_matches, path_start_line, set_list_option, Fix, Fixes, Node, NodeKind, Rule,
+    RuleRegistry, RuleVisitor, Subscription,
+};
+
+/// What to remove from the coverage at the matched lines
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PatternScope {
+    /// remove the line coverages only
+    Line,
+    /// remove the branch coverages only
+    Branch,
+    /// remove both lines and branches
+    #[default]
+    Both,
+}
+
+impl PatternScope {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            PatternScope::Line => "line",
+            PatternScope::Branch => "branch",
+            PatternScope::Both => "both",
+        }
+    }
+
+    /// record the fix for the lines within the range (both inclusive)
+    fn ignore(self, start: usize, end: usize, fixes: &mut Fixes) {
+        match self {
+            PatternScope::Both => fixes.ignore_range(start, end),
+            PatternScope::Line => (start..=end).for_each(|l| fixes.push(Fix::IgnoreLine(l))),
+            PatternScope::Branch => (start..=end).for_each(|l| fixes.push(Fix::IgnoreBranches(l))),
+        }
+    }
+}
+
+impl FromStr for PatternScope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "line" => Ok(PatternScope::Line),
+            "branch" => Ok(PatternScope::Branch),
+            "both" => Ok(PatternScope::Both),
+            _ => Err(format!(
+                "Invalid pattern scope (expected line, branch or both): {:?}",
+                s
+            )
+            .into()),
+        }
+    }
+}
+
+impl fmt::Display for PatternScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Rule which ignores the code matching the declarative patterns
+///
+/// The following patterns are available, and the code matching any of them is removed
+/// according to the scope.
+///
+/// - regular expressions matched against each line
+/// - macro calls by the path (e.g. `tracing::trace`)
+/// - items and methods with the attribute (e.g. `inline`)
+/// - functions and methods by the glob pattern of their names (e.g. `debug_*`)
+///
+/// Regular expressions are also applied to the files which cannot be parsed. The `regex`
+/// option takes a single regular expression since it may contain commas.
+#[derive(Clone)]
+pub struct PatternRule {
+    name: String,
+    reason: String,
+    scope: PatternScope,
+    regexes: Vec<Regex>,
+    macros: Vec<String>,
+    attributes: Vec<String>,
+    functions: Vec<String>,
+}
+
+impl PatternRule {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            reason: "matched by the pattern".to_owned(),
+            scope: PatternScope::Both,
+            regexes: Vec::new(),
+            macros: Vec::new(),
+            attributes: Vec::new(),
+            functions: Vec::new(),
+        }
+    }
+
+    /// what to remove from the matched lines (default: `Both`)
+    pub fn set_scope(&mut self, scope: PatternScope) {
+        self.scope = scope;
+    }
+
+    pub fn scope(&self) -> PatternScope {
+        self.scope
+    }
+
+    pub fn set_reason<S: Into<String>>(&mut self, reason: S) {
+        self.reason = reason.into();
+    }
+
+    /// ignore the lines matching the regular expression
+    pub fn add_regex(&mut self, pattern: &str) -> Result<(), Error> {
+        let regex = Regex::new(pattern)
+            .chain_err(|| format!("Invalid regular expression: {:?}", pattern))?;
+        self.regexes.push(regex);
+        Ok(())
+    }
+
+    /// ignore the calls of the macro. Macros are compared by the whole path.
+    pub fn add_macro<S: Into<String>>(&mut self, name: S) {
+        self.macros.push(name.into());
+    }
+
+    /// ignore the items and methods with the attribute. Attributes are compared by the
+    /// whole path (e.g. `tokio::main`).
+    pub fn add_attribute<S: Into<String>>(&mut self, name: S) {
+        self.attributes.push(name.into());
+    }
+
+    /// ignore the functions and methods whose names match the glob pattern
+    pub fn add_function<S: Into<String>>(&mut self, pattern: S) {
+        self.functions.push(pattern.into());
+    }
+
+    pub fn regexes(&self) -> impl Iterator<Item = &str> {
+        self.regexes.iter().map(|r| r.as_str())
+    }
+
+    pub fn macros(&self) -> &[String] {
+        &self.macros
+    }
+
+    pub fn attributes(&self) -> &[String] {
+        &self.attributes
+    }
+
+    pub fn functions(&self) -> &[String] {
+        &self.functions
+    }
+
+    /// register the rule into the registry under its name
+    pub fn register(self, registry: &mut RuleRegistry, default: bool) -> Result<(), Error> {
+        let name = self.name.clone();
+        registry.register(&name, "code matching the patterns", default, move || {
+            Box::new(self.clone())
+        })
+    }
+
+    fn has_syntax_patterns(&self) -> bool {
+        !(self.macros.is_empty() && self.attributes.is_empty() && self.functions.is_empty())
+    }
+}
+
+impl Rule for PatternRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    fn requires_ast(&self) -> bool {
+        // syntax patterns are skipped on unparsable files, but regexes are still applied
+        self.has_syntax_patterns() && self.regexes.is_empty()
+    }
+
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(PatternVisitor {
+            rule: self,
+            functions: self.functions.iter().map(GlobPattern::new).collect(),
+        }))
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        let regexes: Vec<&str> = self.regexes().collect();
+        Some(format!(
+            "{}:{}:{:?}:{:?}:{:?}:{:?}",
+            self.name, self.scope, regexes, self.macros, self.attributes, self.functions
+        ))
+    }
+
+    fn set_option(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "scope" => self.scope = value.parse()?,
+            "reason" => self.reason = value.to_owned(),
+            "regex" => match value.strip_prefix('+') {
+                Some(value) => self.add_regex(value)?,
+                None => {
+                    self.regexes.clear();
+                    self.add_regex(value)?;
+                }
+            },
+            "macros" => set_list_option(&mut self.macros, value),
+            "attributes" => set_list_option(&mut self.attributes, value),
+            "functions" => set_list_option(&mut self.functions, value),
+            _ => return Err(ErrorKind::InvalidRuleOption(format!("{}.{}", self.name, key)).into()),
+        }
+        Ok(())
+    }
+}
+
+struct PatternVisitor<'a> {
+    rule: &'a PatternRule,
+    functions: Vec<GlobPattern>,
+}
+
+impl<'a> PatternVisitor<'a> {
+    fn visit_macro(&self, mac: &Macro, fixes: &mut Fixes) {
+        if path_matches(&mac.path, &self.rule.macros) {
+            let end = match mac.delimiter {
+                MacroDelimiter::Paren(ref p) => p.span.end().line,
+                MacroDelimiter::Brace(ref b) => b.span.end().line,
+                MacroDelimiter::Bracket(ref b) => b.span.end().line,
+            };
+            self.rule
+                .scope
+                .ignore(path_start_line(&mac.path), end, fixes);
+        }
+    }
+
+    fn visit_fn(&self, attrs: &[Attribute], sig: &Signature, block: &Block, fixes: &mut Fixes) {
+        let name = sig.ident.to_string();
+        if self.functions.iter().any(|p| p.is_match_str(&name)) {
+            let start = attrs_start(attrs).unwrap_or_else(|| sig.fn_token.span.start().line);
+            self.rule
+                .scope
+                .ignore(start, block.brace_token.span.end().line, fixes);
+        } else {
+            self.visit_attrs(attrs, block.brace_token.span.end().line, fixes);
+        }
+    }
+
+    fn visit_attrs(&self, attrs: &[Attribute], end: usize, fixes: &mut Fixes) {
+        if let Some(start) = attrs_start(attrs) {
+            if attrs
+                .iter()
+                .any(|attr| path_matches(&attr.path, &self.rule.attributes))
+            {
+                self.rule.scope.ignore(start, end, fixes);
+            }
+        }
+    }
+}
+
+impl<'a> RuleVisitor for PatternVisitor<'a> {
+    fn subscription(&self) -> Subscription {
+        let mut nodes = Vec::new();
+        if !self.rule.macros.is_empty() {
+            nodes.extend(&[NodeKind::ExprMacro, NodeKind::ItemMacro]);
+        }
+        if !self.rule.attributes.is_empty() || !self.rule.functions.is_empty() {
+            nodes.extend(&[NodeKind::ItemFn, NodeKind::ImplItemMethod]);
+        }
+        if !self.rule.attributes.is_empty() {
+            nodes.extend(&[
+                NodeKind::ItemMod,
+                NodeKind::ItemStruct,
+                NodeKind::ItemEnum,
+                NodeKind::ItemUnion,
+            ]);
+        }
+
+        Subscription {
+            nodes,
+            lines: !self.rule.regexes.is_empty(),
+        }
+    }
+
+    fn visit_node(&mut self, node: Node, fixes: &mut Fixes) {
+        match node {
+            Node::ExprMacro(expr) => self.visit_macro(&expr.mac, fixes),
+            Node::ItemMacro(item) => self.visit_macro(&item.mac, fixes),
+            Node::ItemFn(item) => self.visit_fn(&item.attrs, &item.sig, &item.block, fixes),
+            Node::ImplItemMethod(item) => self.visit_fn(&item.attrs, &item.sig, &item.block, fixes),
+            Node::ItemMod(item) => {
+                if let Some((ref brace, _)) = item.content {
+                    self.visit_attrs(&item.attrs, brace.span.end().line, fixes);
+                }
+            }
+            Node::ItemStruct(item) => {
+                let end = match item.fields {
+                    Fields::Named(ref f) => f.brace_token.span.end().line,
+                    Fields::Unnamed(ref f) => f.paren_token.span.end().line,
+                    Fields::Unit => item.ident.span().end().line,
+                };
+                self.visit_attrs(&item.attrs, end, fixes);
+            }
+            Node::ItemEnum(item) => {
+                self.visit_attrs(&item.attrs, item.brace_token.span.end().line, fixes)
+            }
+            Node::ItemUnion(item) => {
+                self.visit_attrs(&item.attrs, item.fields.brace_token.span.end().line, fixes)
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_line(&mut self, line_number: usize, line: &str, fixes: &mut Fixes) {
+        if self.rule.regexes.iter().any(|r| r.is_match(line)) {
+            self.rule.scope.ignore(line_number, line_number, fixes);
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::coverage::FileCoverage;
+use crate::error::*;
+use crate::rule::{parse_bool_option, set_list_option, Rule, RuleRegistry, SourceCode};
+use crate::source::SourceProvider;
+
+/// version of the plugin protocol sent in the request
+pub const PLUGIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Rule implemented by an external executable
+///
+/// The executable receives the request in JSON format on stdin, and writes the response
+/// in JSON format on stdout.
+///
+/// ```json
+/// {"version": 1, "rule": "NAME", "files": [
+///   {"path": "/project/src/lib.rs", "source": "...",
+///    "lines": [{"line_number": 1, "count": 3}],
+///    "branches": [{"line_number": 2, "block_number": 0, "taken": false}]}]}
+/// ```
+///
+/// ```json
+/// {"files": [
+///   {"path": "/project/src/lib.rs", "adjustments": [
+///     {"lines": [1, 2], "branches": [2], "reason": "generated by build script"}]}]}
+/// ```
+///
+/// `lines` removes the line coverages, and `branches` removes all branches on the lines.
+/// `reason` is optional, and `reason()` of the rule is used if omitted. Only executable
+/// lines and branches are sent, and files which are not included in the response are
+/// kept unchanged.
+///
+/// By default the executable runs for each file. In batch mode, it runs only once for
+/// all files of the package.
+pub struct PluginRule {
+    name: String,
+    command: PathBuf,
+    args: Vec<String>,
+    batch: bool,
+    reason: String,
+    /// adjustments computed in batch mode
+    results: Mutex<HashMap<PathBuf, Vec<PluginAdjustment>>>,
+}
+
+/// Lines and branches to remove, received from the plugin
+#[derive(Clone, Debug, Default, PartialEq)]
+struct PluginAdjustment {
+    lines: Vec<usize>,
+    branches: Vec<usize>,
+    reason: Option<String>,
+}
+
+impl PluginRule {
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(name: S, command: P) -> Self {
+        Self {
+            name: name.into(),
+            command: command.into(),
+            args: Vec::new(),
+            batch: false,
+            reason: "removed by the external plugin".to_owned(),
+            results: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn command(&self) -> &Path {
+        &self.command
+    }
+
+    pub fn add_arg<S: Into<String>>(&mut self, arg: S) {
+        self.args.push(arg.into());
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// run the executable only once for all files (default: `false`)
+    pub fn set_batch(&mut self, batch: bool) {
+        self.batch = batch;
+    }
+
+    pub fn is_batch(&self) -> bool {
+        self.batch
+    }
+
+    /// reason used for the adjustments without reasons
+    pub fn set_reason<S: Into<String>>(&mut self, reason: S) {
+        self.reason = reason.into();
+    }
+
+    /// register the plugin into the registry under its name
+    pub fn register(self, registry: &mut RuleRegistry, default: bool) -> Result<(), Error> {
+        let name = self.name.clone();
+        let description = format!("external plugin `{}`", self.command.display());
+        registry.register(&name, &description, default, move || Box::new(self.clone()))
+    }
+
+    /// run the executable and parse the response
+    fn run(&self, request: Vec<u8>) -> Result<HashMap<PathBuf, Vec<PluginAdjustment>>, Error> {
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .chain_err(|| format!("Failed to execute plugin {:?}", self.command))?;
+
+        // write in another thread so that the plugin can write the response before
+        // reading the whole request
+        let writer = child.stdin.take().map(|mut stdin| {
+            thread::spawn(move || {
+                let _ = stdin.write_all(&request);
+            })
+        });
+
+        let output = child
+            .wait_with_output()
+            .chain_err(|| format!("Failed to execute plugin {:?}", self.command))?;
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+
+        if !output.status.success() {
+            return Err(format!(
+                "Plugin {:?} failed ({}): {}",
+                self.command,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )
+            .into());
+        }
+
+        parse_response(&output.stdout)
+            .chain_err(|| format!("Invalid response from plugin {:?}", self.command))
+    }
+
+    fn write_request(
+        &self,
+        files: &[(&FileCoverage, &str)],
+        writer: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        let files: Vec<serde_json::Value> = files
+            .iter()
+            .map(|&(file_cov, content)| {
+                let lines: Vec<serde_json::Value> = file_cov
+                    .line_coverages()
+                    .iter()
+                    .filter_map(|v| {
+                        v.count.map(|count| {
+                            serde_json::json!({"line_number": v.line_number, "count": count})
+                        })
+                    })
+                    .collect();
+                let branches: Vec<serde_json::Value> = file_cov
+                    .branch_coverages()
+                    .iter()
+                    .filter_map(|v| {
+                        v.taken.map(|taken| {
+                            serde_json::json!({
+                                "line_number": v.line_number,
+                                "block_number": v.block_number,
+                                "taken": taken,
+                            })
+                        })
+                    })
+                    .collect();
+
+                serde_json::json!({
+                    "path": file_cov.path().to_string_lossy(),
+                    "source": content,
+                    "lines": lines,
+                    "branches": branches,
+                })
+            })
+            .collect();
+
+        let request = serde_json::json!({
+            "version": PLUGIN_PROTOCOL_VERSION,
+            "rule": self.name,
+            "files": files,
+        });
+        serde_json::to_writer(writer, &request).chain_err(|| "Failed to write the request")
+    }
+}
+
+impl Clone for PluginRule {
+    /// clone the configuration. Results of the batch request are not cloned.
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            command: self.command.clone(),
+            args: self.args.clone(),
+            batch: self.batch,
+            reason: self.reason.clone(),
+            results: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Rule for PluginRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    fn requires_ast(&self) -> bool {
+        false
+    }
+
+    fn set_option(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "args" => set_list_option(&mut self.args, value),
+            "batch" => self.batch = parse_bool_option(key, value)?,
+            "reason" => self.reason = value.to_owned(),
+            _ => return Err(ErrorKind::InvalidRuleOption(format!("{}.{}", self.name, key)).into()),
+        }
+        Ok(())
+    }
+
+    fn prepare(&self, files: &[&FileCoverage], provider: &dyn SourceProvider) -> Result<(), Error> {
+        if !self.batch {
+            return Ok(());
+        }
+
+        let mut contents = Vec::new();
+        for &file_cov in files {
+            match provider.read_source(file_cov.path()) {
+                Ok(content) => contents.push((file_cov, content)),
+                Err(_) => debugln!("Skipping unreadable file {:?}", file_cov.path()),
+            }
+        }
+
+        let files: Vec<(&FileCoverage, &str)> = contents
+            .iter()
+            .map(|(file_cov, content)| (*file_cov, content.as_str()))
+            .collect();
+
+        let mut request = Vec::new();
+        self.write_request(&files, &mut request)?;
+
+        debugln!("Running plugin {:?} for {} files", self.name, files.len());
+        let results = self.run(request)?;
+        *self.results.lock().unwrap() = results;
+
+        Ok(())
+    }
+
+    fn fix_file_coverage(&self, source: &SourceCode, file_cov: &mut FileCoverage) {
+        if let Err(e) = self.try_fix_file_coverage(source, file_cov) {
+            warnln!("Warning: {}", e);
+        }
+    }
+
+    fn try_fix_file_coverage(
+        &self,
+        source: &SourceCode,
+        file_cov: &mut FileCoverage,
+    ) -> Result<Vec<(usize, String)>, Error> {
+        let adjustments = if self.batch {
+            self.results.lock().unwrap().remove(file_cov.path())
+        } else {
+            let mut request = Vec::new();
+            self.write_request(&[(&*file_cov, &source.content)], &mut request)?;
+            self.run(request)?.remove(file_cov.path())
+        };
+
+        let mut reasons = Vec::new();
+
+        for adjustment in adjustments.unwrap_or_default() {
+            for &line_number in &adjustment.lines {
+                for line_cov in file_cov.line_range_mut(line_number..=line_number) {
+                    line_cov.count = None;
+                }
+            }
+            for &line_number in &adjustment.branches {
+                for branch_cov in file_cov.branch_range_mut(line_number..=line_number) {
+                    branch_cov.taken = None;
+                }
+            }
+
+            if let Some(reason) = adjustment.reason {
+                for &line_number in adjustment.lines.iter().chain(&adjustment.branches) {
+                    reasons.push((line_number, reason.clone()));
+                }
+            }
+        }
+
+        Ok(reasons)
+    }
+}
+
+fn parse_response(stdout: &[u8]) -> Result<HashMap<PathBuf, Vec<PluginAdjustment>>, Error> {
+    let response: serde_json::Value =
+        serde_json::from_slice(stdout).chain_err(|| "Failed to parse JSON")?;
+
+    let files = response["files"]
+        .as_array()
+        .ok_or("\"files\" must be an array")?;
+
+    let mut results: HashMap<PathBuf, Vec<PluginAdjustment>> = HashMap::new();
+    for file in files {
+        let path = file["path"].as_str().ok_or("\"path\" must be a string")?;
+        let adjustments = match file["adjustments"] {
+            serde_json::Value::Null => &[][..],
+            ref v => v.as_array().ok_or("\"adjustments\" must be an array")?,
+        };
+
+        let entry = results.entry(PathBuf::from(path)).or_default();
+        for adjustment in adjustments {
+            entry.push(PluginAdjustment {
+                lines: line_numbers(&adjustment["lines"], "lines")?,
+                branches: line_numbers(&adjustment["branches"], "branches")?,
+                reason: match adjustment["reason"] {
+                    serde_json::Value::Null => None,
+                    ref v => Some(v.as_str().ok_or("\"reason\" must be a string")?.to_owned()),
+                },
+            });
+        }
+    }
+
+    Ok(results)
+}
+
+/// array of line numbers. `null` is regarded as an empty array.
+fn line_numbers(value: &serde_json::Value, key: &str) -> Result<Vec<usize>, Error> {
+    let invalid = || Error::from(format!("{:?} must be an array of line numbers", key));
+
+    match *value {
+        serde_json::Value::Null => Ok(Vec::new()),
+        serde_json::Value::Array(ref values) => values
+            .iter()
+            .map(|v| v.as_u64().map(|n| n as usize).ok_or_else(invalid))
+            .collect(),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response() {
+        let stdout = br#"{"files": [
+            {"path": "/src/lib.rs", "adjustments": [
+                {"lines": [1, 2], "reason": "generated"},
+                {"branches": [3]}]},
+            {"path": "/src/main.rs"}]}"#;
+
+        let results = parse_response(stdout).unwrap();
+        assert_eq!(
+            results[Path::new("/src/lib.rs")],
+            vec![
+                PluginAdjustment {
+                    lines: vec![1, 2],
+                    branches: vec![],
+                    reason: Some("generated".to_owned()),
+                },
+                PluginAdjustment {
+                    lines: vec![],
+                    branches: vec![3],
+                    reason: None,
+                },
+            ]
+        );
+        assert_eq!(results[Path::new("/src/main.rs")], vec![]);
+
+        assert!(parse_response(b"").is_err());
+        assert!(parse_response(br#"{"files": [{"path": 1}]}"#).is_err());
+        assert!(
+            parse_response(br#"{"files": [{"path": "a", "adjustments": [{"lines": [-1]}]}]}"#)
+                .is_err()
+        );
+    }
+}
@@ -1,15 +1,15 @@
 use proc_macro2::TokenTree;
 use std::fs;
-use std::marker::PhantomData;
 use std::path::Path;
-use syn::visit::Visit;
 use syn::{
-    ExprForLoop, ExprMacro, Fields, File, Ident, ItemEnum, ItemFn, ItemMod, ItemStruct, ItemUnion,
-    MacroDelimiter,
+    Attribute, ExprForLoop, ExprMacro, Fields, File, ImplItemMethod, ItemEnum, ItemFn, ItemMacro,
+    ItemMod, ItemStruct, ItemUnion, MacroDelimiter,
 };
 
+use crate::engine::collect_fixes;
 use crate::error::*;
-use crate::{BranchCoverage, FileCoverage, LineCoverage};
+use crate::source::SourceProvider;
+use crate::FileCoverage;
 
 pub struct SourceCode {
     pub content: String,
@@ -17,6 +17,7 @@ pub struct SourceCode {
 }
 
 impl SourceCode {
+    /// read and parse the source file
     pub fn new(filename: &Path) -> Result<SourceCode, Error> {
         let content = fs::read_to_string(filename)
             .chain_err(|| ErrorKind::SourceFileNotFound(filename.to_owned()))?;
@@ -24,10 +25,422 @@ impl SourceCode {
             syn::parse_file(&content).chain_err(|| format!("Failed to parse {:?}", filename))?;
         Ok(SourceCode { content, ast })
     }
+
+    /// source code with the empty syntax tree
+    ///
+    /// This is used for the files which cannot be parsed, so that the rules which do not
+    /// require the syntax tree (see `Rule::requires_ast()`) can be applied.
+    pub fn text_only(content: String) -> SourceCode {
+        let ast = File {
+            shebang: None,
+            attrs: Vec::new(),
+            items: Vec::new(),
+        };
+        SourceCode { content, ast }
+    }
+
+    /// read the source file without failing on syntax errors
+    ///
+    /// If the source code cannot be parsed, the syntax tree is empty and the parse error
+    /// is returned together.
+    pub fn new_lenient(filename: &Path) -> Result<(SourceCode, Option<syn::Error>), Error> {
+        let content = fs::read_to_string(filename)
+            .chain_err(|| ErrorKind::SourceFileNotFound(filename.to_owned()))?;
+        Ok(SourceCode::parse_lenient(content))
+    }
+
+    /// parse the source code without failing on syntax errors (see `new_lenient()`)
+    pub fn parse_lenient(content: String) -> (SourceCode, Option<syn::Error>) {
+        match syn::parse_file(&content) {
+            Ok(ast) => (SourceCode { content, ast }, None),
+            Err(e) => (SourceCode::text_only(content), Some(e)),
+        }
+    }
 }
 
 pub trait Rule: Send + Sync {
-    fn fix_file_coverage(&self, source: &SourceCode, file_cov: &mut FileCoverage);
+    /// name of the rule used in the reports
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// reason why the lines and branches are removed by this rule
+    fn reason(&self) -> &str {
+        ""
+    }
+
+    /// whether this rule requires the syntax tree
+    ///
+    /// If the source file cannot be parsed, only the rules which return `false` are
+    /// applied.
+    fn requires_ast(&self) -> bool {
+        true
+    }
+
+    /// create the visitor which receives the subscribed nodes and lines of a file
+    ///
+    /// `CoverageFixer` walks each source file only once for all rules which return the
+    /// visitor. Rules which return `None` are applied by `fix_file_coverage()` instead.
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        None
+    }
+
+    /// identifier of the rule and its configuration used for the cache key
+    ///
+    /// Fixes are cached only if all rules return `Some`. Rules must return different keys
+    /// if they may record different fixes for the same source code.
+    fn cache_key(&self) -> Option<String> {
+        None
+    }
+
+    /// set the option of the rule from the string value
+    ///
+    /// This is used to configure the rules from the command line. Lists are given as
+    /// comma-separated values.
+    fn set_option(&mut self, key: &str, _value: &str) -> Result<(), Error> {
+        Err(ErrorKind::InvalidRuleOption(format!("{}.{}", self.name(), key)).into())
+    }
+
+    /// called by `CoverageFixer` once before fixing the files of the package
+    ///
+    /// `files` are the files to which the rule is applied, and rules can compute the
+    /// results of all of them at once here. Rules which are not applied to any file are
+    /// not prepared. The default implementation does nothing.
+    fn prepare(
+        &self,
+        _files: &[&FileCoverage],
+        _provider: &dyn SourceProvider,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// apply the rule to the coverage. `file_cov` must be sorted by line numbers.
+    ///
+    /// The default implementation runs the visitor on the source code and applies the
+    /// recorded fixes.
+    fn fix_file_coverage(&self, source: &SourceCode, file_cov: &mut FileCoverage) {
+        let mut visitors = [self.visitor()];
+        for fixes in collect_fixes(&mut visitors, source).into_iter().flatten() {
+            fixes.apply(file_cov);
+        }
+    }
+
+    /// fallible version of `fix_file_coverage()`, which is used by `CoverageFixer`
+    ///
+    /// Returns the line numbers removed for a different reason than `reason()` with their
+    /// reasons. Errors are handled by the error policy of `CoverageFixer`. The default
+    /// implementation calls `fix_file_coverage()`.
+    fn try_fix_file_coverage(
+        &self,
+        source: &SourceCode,
+        file_cov: &mut FileCoverage,
+    ) -> Result<Vec<(usize, String)>, Error> {
+        self.fix_file_coverage(source, file_cov);
+        Ok(Vec::new())
+    }
+}
+
+/// Kind of the syntax tree node which the rule visitor can subscribe to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    ItemFn,
+    ItemMod,
+    ItemStruct,
+    ItemEnum,
+    ItemUnion,
+    ItemMacro,
+    ImplItemMethod,
+    ExprForLoop,
+    ExprMacro,
+}
+
+impl NodeKind {
+    pub const ALL: &'static [NodeKind] = &[
+        NodeKind::ItemFn,
+        NodeKind::ItemMod,
+        NodeKind::ItemStruct,
+        NodeKind::ItemEnum,
+        NodeKind::ItemUnion,
+        NodeKind::ItemMacro,
+        NodeKind::ImplItemMethod,
+        NodeKind::ExprForLoop,
+        NodeKind::ExprMacro,
+    ];
+}
+
+/// Syntax tree node dispatched to the rule visitors
+#[derive(Clone, Copy)]
+pub enum Node<'ast> {
+    ItemFn(&'ast ItemFn),
+    ItemMod(&'ast ItemMod),
+    ItemStruct(&'ast ItemStruct),
+    ItemEnum(&'ast ItemEnum),
+    ItemUnion(&'ast ItemUnion),
+    ItemMacro(&'ast ItemMacro),
+    ImplItemMethod(&'ast ImplItemMethod),
+    ExprForLoop(&'ast ExprForLoop),
+    ExprMacro(&'ast ExprMacro),
+}
+
+impl<'ast> Node<'ast> {
+    pub fn kind(&self) -> NodeKind {
+        match *self {
+            Node::ItemFn(_) => NodeKind::ItemFn,
+            Node::ItemMod(_) => NodeKind::ItemMod,
+            Node::ItemStruct(_) => NodeKind::ItemStruct,
+            Node::ItemEnum(_) => NodeKind::ItemEnum,
+            Node::ItemUnion(_) => NodeKind::ItemUnion,
+            Node::ItemMacro(_) => NodeKind::ItemMacro,
+            Node::ImplItemMethod(_) => NodeKind::ImplItemMethod,
+            Node::ExprForLoop(_) => NodeKind::ExprForLoop,
+            Node::ExprMacro(_) => NodeKind::ExprMacro,
+        }
+    }
+}
+
+/// Node kinds and lines which the rule visitor receives
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Subscription {
+    pub nodes: Vec<NodeKind>,
+    /// receive every line of the source code
+    pub lines: bool,
+}
+
+/// Per-file state of the rule
+///
+/// Visitors do not modify the coverage directly. They record the fixes, which are
+/// applied in the order of the rules after the traversal.
+pub trait RuleVisitor {
+    fn subscription(&self) -> Subscription;
+
+    /// called for each subscribed node in the order of appearance
+    fn visit_node(&mut self, _node: Node, _fixes: &mut Fixes) {}
+
+    /// called for each line (1-indexed) if the visitor subscribes to the lines
+    ///
+    /// Lines are visited before the nodes.
+    fn visit_line(&mut self, _line_number: usize, _line: &str, _fixes: &mut Fixes) {}
+
+    /// called once after all subscribed lines and nodes are visited
+    fn finish(&mut self, _fixes: &mut Fixes) {}
+}
+
+/// Modification to the coverage recorded by the rule visitor
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fix {
+    /// remove the lines and branches within the range (both inclusive)
+    IgnoreRange { start: usize, end: usize },
+    /// remove the line
+    IgnoreLine(usize),
+    /// remove all branches at the line
+    IgnoreBranches(usize),
+    /// remove the first branch which was not taken at the line. If `executed_only` is
+    /// `true`, the branch is removed only if the line was executed.
+    IgnoreUntakenBranch {
+        line_number: usize,
+        executed_only: bool,
+    },
+}
+
+impl Fix {
+    /// returns `true` if the fix removes the line or the branches at the line
+    pub fn contains(&self, line_number: usize) -> bool {
+        match *self {
+            Fix::IgnoreRange { start, end } => start <= line_number && line_number <= end,
+            Fix::IgnoreLine(n) | Fix::IgnoreBranches(n) => n == line_number,
+            Fix::IgnoreUntakenBranch { line_number: n, .. } => n == line_number,
+        }
+    }
+}
+
+/// Provenance of the fix, reported by `--explain`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixOrigin {
+    /// reason of the removal, which is used instead of `Rule::reason()` (e.g. which
+    /// marker comment caused the fix)
+    pub reason: String,
+    /// first and last line of the code which caused the fix (both inclusive)
+    pub span: (usize, usize),
+}
+
+/// Fixes recorded by the rule visitor
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fixes {
+    fixes: Vec<Fix>,
+    /// indices of the fixes recorded with the origins
+    origins: Vec<(usize, FixOrigin)>,
+}
+
+impl Fixes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, fix: Fix) {
+        self.fixes.push(fix);
+    }
+
+    /// record the fix with its provenance
+    pub fn push_with_origin(&mut self, fix: Fix, origin: FixOrigin) {
+        self.origins.push((self.fixes.len(), origin));
+        self.fixes.push(fix);
+    }
+
+    /// set the origin of the last fix. Returns `false` if there are no fixes or the last
+    /// fix already has the origin.
+    pub(crate) fn set_last_origin(&mut self, origin: FixOrigin) -> bool {
+        let idx = match self.fixes.len().checked_sub(1) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        if matches!(self.origins.last(), Some(&(i, _)) if i == idx) {
+            return false;
+        }
+
+        self.origins.push((idx, origin));
+        true
+    }
+
+    /// fixes recorded with the origins
+    pub fn origins(&self) -> impl Iterator<Item = (&Fix, &FixOrigin)> {
+        self.origins
+            .iter()
+            .map(move |(idx, origin)| (&self.fixes[*idx], origin))
+    }
+
+    /// origin of the first fix which contains the line
+    pub fn origin_of(&self, line_number: usize) -> Option<&FixOrigin> {
+        self.origins()
+            .find(|(fix, _)| fix.contains(line_number))
+            .map(|(_, origin)| origin)
+    }
+
+    /// remove the lines and branches within the range (both inclusive)
+    pub fn ignore_range(&mut self, start: usize, end: usize) {
+        self.push(Fix::IgnoreRange { start, end });
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Fix> {
+        self.fixes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fixes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fixes.is_empty()
+    }
+
+    /// apply the fixes in order. `file_cov` must be sorted by line numbers.
+    pub fn apply(&self, file_cov: &mut FileCoverage) {
+        for fix in &self.fixes {
+            match *fix {
+                Fix::IgnoreRange { start, end } => {
+                    for line_cov in file_cov.line_range_mut(start..=end) {
+                        line_cov.count = None;
+                    }
+                    for branch_cov in file_cov.branch_range_mut(start..=end) {
+                        branch_cov.taken = None;
+                    }
+                }
+                Fix::IgnoreLine(line_number) => {
+                    for line_cov in file_cov.line_range_mut(line_number..=line_number) {
+                        line_cov.count = None;
+                    }
+                }
+                Fix::IgnoreBranches(line_number) => {
+                    for branch_cov in file_cov.branch_range_mut(line_number..=line_number) {
+                        branch_cov.taken = None;
+                    }
+                }
+                Fix::IgnoreUntakenBranch {
+                    line_number,
+                    executed_only,
+                } => {
+                    if executed_only
+                        && !file_cov
+                            .line_range(line_number..=line_number)
+                            .iter()
+                            .any(|v| matches!(v.count, Some(c) if c > 0))
+                    {
+                        continue;
+                    }
+
+                    let branches = file_cov.branch_range_mut(line_number..=line_number);
+                    if let Some(branch_cov) = branches.iter_mut().find(|v| v.taken == Some(false)) {
+                        branch_cov.taken = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Fixes {
+    type Item = &'a Fix;
+    type IntoIter = std::slice::Iter<'a, Fix>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.fixes.iter()
+    }
+}
+
+/// first line of the attributes. `None` if there are no attributes.
+pub(crate) fn attrs_start(attrs: &[Attribute]) -> Option<usize> {
+    attrs
+        .first()
+        .map(|attr| attr.pound_token.spans[0].start().line)
+}
+
+fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+    attrs
+        .iter()
+        .any(|attr| attr.path.segments.len() == 1 && attr.path.segments[0].ident == name)
+}
+
+/// path as a string (e.g. `tokio::test`)
+pub(crate) fn path_to_string(path: &syn::Path) -> String {
+    let segments: Vec<String> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+    segments.join("::")
+}
+
+pub(crate) fn path_matches(path: &syn::Path, names: &[String]) -> bool {
+    let path = path_to_string(path);
+    names.contains(&path)
+}
+
+pub(crate) fn path_start_line(path: &syn::Path) -> usize {
+    match path.segments.first() {
+        Some(segment) => segment.ident.span().start().line,
+        None => 0,
+    }
+}
+
+/// set the comma-separated list. If the value starts with `+`, the items are appended to
+/// the current list.
+pub(crate) fn set_list_option(list: &mut Vec<String>, value: &str) {
+    let value = match value.strip_prefix('+') {
+        Some(value) => value,
+        None => {
+            list.clear();
+            value
+        }
+    };
+
+    for item in value.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        if !list.iter().any(|v| v == item) {
+            list.push(item.to_owned());
+        }
+    }
+}
+
+pub(crate) fn parse_bool_option(key: &str, value: &str) -> Result<bool, Error> {
+    match value {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(ErrorKind::InvalidRuleOption(format!("{}={}", key, value)).into()),
+    }
 }
 
 pub struct CloseBlockRule;
@@ -37,7 +450,7 @@ impl CloseBlockRule {
         Self
     }
 
-    fn match_line(&self, line: &str) -> bool {
+    fn match_line(line: &str) -> bool {
         let mut it = line.as_bytes().iter();
         while let Some(b) = it.next() {
             match *b {
@@ -65,112 +478,153 @@ impl CloseBlockRule {
 }
 
 impl Rule for CloseBlockRule {
-    fn fix_file_coverage(&self, source: &SourceCode, file_cov: &mut FileCoverage) {
-        for entry in PerLineIterator::new(&source.content, file_cov) {
-            if entry.line_cov.is_none() && entry.branch_covs.is_empty() {
-                continue;
-            }
+    fn name(&self) -> &str {
+        "close"
+    }
 
-            if self.match_line(entry.line) {
-                if let Some(line_cov) = entry.line_cov {
-                    line_cov.count = None;
-                }
+    fn reason(&self) -> &str {
+        "closing brackets are not executable"
+    }
 
-                entry.branch_covs.iter_mut().for_each(|v| v.taken = None);
-            }
+    fn requires_ast(&self) -> bool {
+        false
+    }
+
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(CloseBlockVisitor))
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some(self.name().to_owned())
+    }
+}
+
+struct CloseBlockVisitor;
+
+impl RuleVisitor for CloseBlockVisitor {
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            nodes: vec![],
+            lines: true,
+        }
+    }
+
+    fn visit_line(&mut self, line_number: usize, line: &str, fixes: &mut Fixes) {
+        if CloseBlockRule::match_line(line) {
+            fixes.ignore_range(line_number, line_number);
         }
     }
 }
 
-pub struct TestRule;
+pub struct TestRule {
+    attributes: Vec<String>,
+}
 
 impl TestRule {
     pub fn new() -> Self {
-        Self
+        Self {
+            attributes: vec!["test".to_owned()],
+        }
+    }
+
+    /// attributes of the test functions (default: `test`)
+    ///
+    /// Attributes are compared by the whole path (e.g. `tokio::test`).
+    pub fn set_attributes<I, S>(&mut self, attributes: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.attributes = attributes.into_iter().map(|v| v.into()).collect();
+    }
+
+    pub fn add_attribute<S: Into<String>>(&mut self, attribute: S) {
+        self.attributes.push(attribute.into());
+    }
+
+    pub fn attributes(&self) -> &[String] {
+        &self.attributes
     }
 }
 
 impl Rule for TestRule {
-    fn fix_file_coverage(&self, source: &SourceCode, file_cov: &mut FileCoverage) {
-        let mut inner = TestRuleInner { file_cov };
-        inner.visit_file(&source.ast);
+    fn name(&self) -> &str {
+        "test"
     }
-}
 
-struct TestRuleInner<'a> {
-    file_cov: &'a mut FileCoverage,
-}
+    fn reason(&self) -> &str {
+        "tests are not a target of coverage"
+    }
 
-impl<'a> TestRuleInner<'a> {
-    fn ignore_range(&mut self, start: usize, end: usize) {
-        for line_cov in self
-            .file_cov
-            .line_coverages
-            .iter_mut()
-            .skip_while(|e| e.line_number < start)
-            .take_while(|e| e.line_number <= end)
-        {
-            line_cov.count = None;
-        }
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(TestVisitor { rule: self }))
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some(format!("test:{}", self.attributes.join(",")))
+    }
 
-        for branch_cov in self
-            .file_cov
-            .branch_coverages
-            .iter_mut()
-            .skip_while(|e| e.line_number < start)
-            .take_while(|e| e.line_number <= end)
-        {
-            branch_cov.taken = None;
+    fn set_option(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "attributes" => set_list_option(&mut self.attributes, value),
+            _ => return Err(ErrorKind::InvalidRuleOption(format!("test.{}", key)).into()),
         }
+        Ok(())
     }
 }
 
-impl<'ast, 'a> Visit<'ast> for TestRuleInner<'a> {
-    fn visit_item_fn(&mut self, item: &'ast ItemFn) {
-        let start = match item.attrs.get(0) {
-            Some(attr) => attr.pound_token.spans[0].start().line,
-            None => return,
-        };
-        let end = item.block.brace_token.span.end().line;
+struct TestVisitor<'a> {
+    rule: &'a TestRule,
+}
 
-        for attr in item.attrs.iter() {
-            if attr.path.segments.len() == 1 && attr.path.segments[0].ident == "test" {
-                self.ignore_range(start, end);
-                return;
-            }
+impl<'a> RuleVisitor for TestVisitor<'a> {
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            nodes: vec![NodeKind::ItemFn, NodeKind::ItemMod],
+            lines: false,
         }
-
-        syn::visit::visit_item_fn(self, item);
     }
 
-    fn visit_item_mod(&mut self, item: &'ast ItemMod) {
-        let span = match item.content {
-            Some((ref brace, _)) => brace.span,
-            None => return,
-        };
-
-        for attr in item.attrs.iter() {
-            if attr.path.segments.len() == 1 && attr.path.segments[0].ident == "cfg" {
-                for token in attr.tokens.clone() {
-                    if let TokenTree::Group(g) = token {
-                        let token = match g.stream().into_iter().next() {
-                            Some(t) => t,
-                            None => continue,
-                        };
-
-                        if let TokenTree::Ident(ident) = token {
-                            if ident == "test" {
-                                self.ignore_range(span.start().line, span.end().line);
-                                return;
-                            }
-                        }
+    fn visit_node(&mut self, node: Node, fixes: &mut Fixes) {
+        match node {
+            Node::ItemFn(item) => {
+                if let Some(start) = attrs_start(&item.attrs) {
+                    if item
+                        .attrs
+                        .iter()
+                        .any(|attr| path_matches(&attr.path, &self.rule.attributes))
+                    {
+                        fixes.ignore_range(start, item.block.brace_token.span.end().line);
                     }
                 }
             }
+            Node::ItemMod(item) => {
+                let span = match item.content {
+                    Some((ref brace, _)) => brace.span,
+                    None => return,
+                };
+
+                if item.attrs.iter().any(is_cfg_test) {
+                    fixes.ignore_range(span.start().line, span.end().line);
+                }
+            }
+            _ => {}
         }
+    }
+}
 
-        syn::visit::visit_item_mod(self, item);
+/// whether the attribute is `#[cfg(test)]`
+fn is_cfg_test(attr: &Attribute) -> bool {
+    if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "cfg" {
+        return false;
     }
+
+    attr.tokens.clone().into_iter().any(|token| match token {
+        TokenTree::Group(g) => {
+            matches!(g.stream().into_iter().next(), Some(TokenTree::Ident(ident)) if ident == "test")
+        }
+        _ => false,
+    })
 }
 
 pub struct LoopRule;
@@ -182,42 +636,47 @@ impl LoopRule {
 }
 
 impl Rule for LoopRule {
-    fn fix_file_coverage(&self, source: &SourceCode, file_cov: &mut FileCoverage) {
-        let mut inner = LoopRuleInner {
-            it: PerLineIterator::new(&source.content, file_cov),
-            current_line: 0,
-        };
-        inner.visit_file(&source.ast);
+    fn name(&self) -> &str {
+        "loop"
     }
-}
 
-struct LoopRuleInner<'a, 'b> {
-    it: PerLineIterator<'a, 'b>,
-    current_line: usize,
-}
+    fn reason(&self) -> &str {
+        "exit branch of the executed for loop"
+    }
 
-impl<'ast, 'a, 'b> Visit<'ast> for LoopRuleInner<'a, 'b> {
-    fn visit_expr_for_loop(&mut self, expr: &'ast ExprForLoop) {
-        let line = expr.for_token.span.start().line;
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(LoopVisitor { last_line: 0 }))
+    }
 
-        if let Some(entry) = self.it.nth(line - self.current_line - 1) {
-            self.current_line = line;
+    fn cache_key(&self) -> Option<String> {
+        Some(self.name().to_owned())
+    }
+}
 
-            let should_be_fixed = entry
-                .line_cov
-                .map_or(false, |v| v.count.map_or(false, |c| c > 0));
+struct LoopVisitor {
+    /// line of the last for loop. Only the first loop in a line is fixed.
+    last_line: usize,
+}
 
-            if should_be_fixed {
-                for branch_cov in entry.branch_covs {
-                    if branch_cov.taken == Some(false) {
-                        branch_cov.taken = None;
-                        break;
-                    }
-                }
-            }
+impl RuleVisitor for LoopVisitor {
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            nodes: vec![NodeKind::ExprForLoop],
+            lines: false,
         }
+    }
 
-        syn::visit::visit_expr_for_loop(self, expr);
+    fn visit_node(&mut self, node: Node, fixes: &mut Fixes) {
+        if let Node::ExprForLoop(expr) = node {
+            let line = expr.for_token.span.start().line;
+            if line > self.last_line {
+                self.last_line = line;
+                fixes.push(Fix::IgnoreUntakenBranch {
+                    line_number: line,
+                    executed_only: true,
+                });
+            }
+        }
     }
 }
 
@@ -230,212 +689,245 @@ impl DeriveRule {
 }
 
 impl Rule for DeriveRule {
-    fn fix_file_coverage(&self, source: &SourceCode, file_cov: &mut FileCoverage) {
-        let mut inner = DeriveLoopInner { file_cov };
-        inner.visit_file(&source.ast);
+    fn name(&self) -> &str {
+        "derive"
     }
-}
 
-struct DeriveLoopInner<'a> {
-    file_cov: &'a mut FileCoverage,
-}
+    fn reason(&self) -> &str {
+        "derived implementations are generated by the compiler"
+    }
 
-impl<'a> DeriveLoopInner<'a> {
-    fn ignore_range(&mut self, start: usize, end: usize) {
-        for line_cov in self
-            .file_cov
-            .line_coverages
-            .iter_mut()
-            .skip_while(|e| e.line_number < start)
-            .take_while(|e| e.line_number <= end)
-        {
-            line_cov.count = None;
-        }
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(DeriveVisitor))
+    }
 
-        for branch_cov in self
-            .file_cov
-            .branch_coverages
-            .iter_mut()
-            .skip_while(|e| e.line_number < start)
-            .take_while(|e| e.line_number <= end)
-        {
-            branch_cov.taken = None;
-        }
+    fn cache_key(&self) -> Option<String> {
+        Some(self.name().to_owned())
     }
 }
 
-impl<'ast, 'a> Visit<'ast> for DeriveLoopInner<'a> {
-    fn visit_item_struct(&mut self, item: &'ast ItemStruct) {
-        let start = match item.attrs.get(0) {
-            Some(attr) => attr.pound_token.spans[0].start().line,
-            None => return,
-        };
-        let end = match item.fields {
-            Fields::Named(ref f) => f.brace_token.span.end().line,
-            Fields::Unnamed(ref f) => f.paren_token.span.end().line,
-            Fields::Unit => item.ident.span().end().line,
-        };
-
-        for attr in item.attrs.iter() {
-            if attr.path.segments.len() == 1 && attr.path.segments[0].ident == "derive" {
-                self.ignore_range(start, end);
-                return;
-            }
+struct DeriveVisitor;
+
+impl RuleVisitor for DeriveVisitor {
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            nodes: vec![
+                NodeKind::ItemStruct,
+                NodeKind::ItemEnum,
+                NodeKind::ItemUnion,
+            ],
+            lines: false,
         }
     }
 
-    fn visit_item_enum(&mut self, item: &'ast ItemEnum) {
-        let start = match item.attrs.get(0) {
-            Some(attr) => attr.pound_token.spans[0].start().line,
-            None => return,
-        };
-        let end = item.brace_token.span.end().line;
-
-        for attr in item.attrs.iter() {
-            if attr.path.segments.len() == 1 && attr.path.segments[0].ident == "derive" {
-                self.ignore_range(start, end);
-                break;
+    fn visit_node(&mut self, node: Node, fixes: &mut Fixes) {
+        let (attrs, end) = match node {
+            Node::ItemStruct(item) => {
+                let end = match item.fields {
+                    Fields::Named(ref f) => f.brace_token.span.end().line,
+                    Fields::Unnamed(ref f) => f.paren_token.span.end().line,
+                    Fields::Unit => item.ident.span().end().line,
+                };
+                (&item.attrs, end)
             }
-        }
-    }
-
-    fn visit_item_union(&mut self, item: &'ast ItemUnion) {
-        let start = match item.attrs.get(0) {
-            Some(attr) => attr.pound_token.spans[0].start().line,
-            None => return,
+            Node::ItemEnum(item) => (&item.attrs, item.brace_token.span.end().line),
+            Node::ItemUnion(item) => (&item.attrs, item.fields.brace_token.span.end().line),
+            _ => return,
         };
-        let end = item.fields.brace_token.span.end().line;
 
-        for attr in item.attrs.iter() {
-            if attr.path.segments.len() == 1 && attr.path.segments[0].ident == "derive" {
-                self.ignore_range(start, end);
-                break;
+        if let Some(start) = attrs_start(attrs) {
+            if has_attr(attrs, "derive") {
+                fixes.ignore_range(start, end);
             }
         }
     }
 }
 
-pub struct UnreachableRule;
+pub struct UnreachableRule {
+    macros: Vec<String>,
+}
 
 impl UnreachableRule {
     pub fn new() -> Self {
-        Self
+        Self {
+            macros: vec!["unreachable".to_owned()],
+        }
+    }
+
+    /// macros which are never executed (default: `unreachable`)
+    pub fn set_macros<I, S>(&mut self, macros: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.macros = macros.into_iter().map(|v| v.into()).collect();
+    }
+
+    pub fn add_macro<S: Into<String>>(&mut self, name: S) {
+        self.macros.push(name.into());
+    }
+
+    pub fn macros(&self) -> &[String] {
+        &self.macros
     }
 }
 
 impl Rule for UnreachableRule {
-    fn fix_file_coverage(&self, source: &SourceCode, file_cov: &mut FileCoverage) {
-        let mut inner = UnreachableRuleInner { file_cov };
-        inner.visit_file(&source.ast);
+    fn name(&self) -> &str {
+        "unreachable"
     }
-}
 
-struct UnreachableRuleInner<'a> {
-    file_cov: &'a mut FileCoverage,
-}
+    fn reason(&self) -> &str {
+        "`unreachable!()` is never executed"
+    }
 
-impl<'a> UnreachableRuleInner<'a> {
-    fn ignore_range(&mut self, start: usize, end: usize) {
-        for line_cov in self
-            .file_cov
-            .line_coverages
-            .iter_mut()
-            .skip_while(|e| e.line_number < start)
-            .take_while(|e| e.line_number <= end)
-        {
-            line_cov.count = None;
-        }
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(UnreachableVisitor { rule: self }))
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some(format!("unreachable:{}", self.macros.join(",")))
+    }
 
-        for branch_cov in self
-            .file_cov
-            .branch_coverages
-            .iter_mut()
-            .skip_while(|e| e.line_number < start)
-            .take_while(|e| e.line_number <= end)
-        {
-            branch_cov.taken = None;
+    fn set_option(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "macros" => set_list_option(&mut self.macros, value),
+            _ => return Err(ErrorKind::InvalidRuleOption(format!("unreachable.{}", key)).into()),
         }
+        Ok(())
     }
 }
 
-impl<'ast, 'a> Visit<'ast> for UnreachableRuleInner<'a> {
-    fn visit_expr_macro(&mut self, expr: &'ast ExprMacro) {
-        if let Some(ident) = expr.mac.path.get_ident() {
-            if ident == "unreachable" {
-                let start = ident.span().start().line;
+struct UnreachableVisitor<'a> {
+    rule: &'a UnreachableRule,
+}
+
+impl<'a> RuleVisitor for UnreachableVisitor<'a> {
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            nodes: vec![NodeKind::ExprMacro],
+            lines: false,
+        }
+    }
+
+    fn visit_node(&mut self, node: Node, fixes: &mut Fixes) {
+        if let Node::ExprMacro(expr) = node {
+            if path_matches(&expr.mac.path, &self.rule.macros) {
+                let start = path_start_line(&expr.mac.path);
                 let end = match expr.mac.delimiter {
                     MacroDelimiter::Paren(ref p) => p.span.end().line,
                     MacroDelimiter::Brace(ref b) => b.span.end().line,
                     MacroDelimiter::Bracket(ref b) => b.span.end().line,
                 };
-                self.ignore_range(start, end);
-                return;
+                fixes.ignore_range(start, end);
             }
         }
-
-        syn::visit::visit_expr_macro(self, expr);
     }
 }
 
-pub struct AssertRule;
+pub struct AssertRule {
+    macros: Vec<String>,
+    debug_assert: bool,
+}
 
 impl AssertRule {
     pub fn new() -> Self {
-        Self
+        Self {
+            macros: vec![
+                "assert".to_owned(),
+                "assert_eq".to_owned(),
+                "assert_ne".to_owned(),
+            ],
+            debug_assert: true,
+        }
     }
-}
 
-impl Rule for AssertRule {
-    fn fix_file_coverage(&self, source: &SourceCode, file_cov: &mut FileCoverage) {
-        let mut inner = AssertRuleInner {
-            file_cov,
-            next_idx: 0,
+    /// assertion macros (default: `assert`, `assert_eq` and `assert_ne`)
+    pub fn set_macros<I, S>(&mut self, macros: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.macros = macros.into_iter().map(|v| v.into()).collect();
+    }
+
+    pub fn add_macro<S: Into<String>>(&mut self, name: S) {
+        self.macros.push(name.into());
+    }
+
+    pub fn macros(&self) -> &[String] {
+        &self.macros
+    }
+
+    /// also recognize `debug_` variants of the macros (e.g. `debug_assert`). Default is
+    /// `true`.
+    pub fn set_debug_assert(&mut self, enabled: bool) {
+        self.debug_assert = enabled;
+    }
+
+    fn is_assert(&self, path: &syn::Path) -> bool {
+        let name = path_to_string(path);
+        let name = match name.strip_prefix("debug_") {
+            Some(stripped) if self.debug_assert => stripped,
+            _ => &name,
         };
-        inner.visit_file(&source.ast);
+
+        self.macros.iter().any(|v| v == name)
     }
 }
 
-struct AssertRuleInner<'a> {
-    file_cov: &'a mut FileCoverage,
-    next_idx: usize,
-}
+impl Rule for AssertRule {
+    fn name(&self) -> &str {
+        "assert"
+    }
 
-impl<'ast, 'a> Visit<'ast> for AssertRuleInner<'a> {
-    fn visit_expr_macro(&mut self, expr: &'ast ExprMacro) {
-        fn is_assert(name: &Ident) -> bool {
-            const NAMES: &[&str] = &[
-                "assert",
-                "assert_eq",
-                "assert_ne",
-                "debug_assert",
-                "debug_assert_eq",
-                "debug_assert_ne",
-            ];
+    fn reason(&self) -> &str {
+        "failure branch of the assertion"
+    }
 
-            NAMES.iter().any(|s| name == *s)
-        }
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(AssertVisitor { rule: self }))
+    }
 
-        if let Some(ident) = expr.mac.path.get_ident() {
-            if is_assert(ident) {
-                let start = ident.span().start().line;
+    fn cache_key(&self) -> Option<String> {
+        Some(format!(
+            "assert:{}:{}",
+            self.macros.join(","),
+            self.debug_assert
+        ))
+    }
 
-                let it = self.file_cov.branch_coverages[self.next_idx..]
-                    .iter_mut()
-                    .skip_while(|e| e.line_number < start)
-                    .take_while(|e| e.line_number == start);
+    fn set_option(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "macros" => set_list_option(&mut self.macros, value),
+            "debug_assert" => self.debug_assert = parse_bool_option(key, value)?,
+            _ => return Err(ErrorKind::InvalidRuleOption(format!("assert.{}", key)).into()),
+        }
+        Ok(())
+    }
+}
 
-                for branch in it {
-                    if branch.taken == Some(false) {
-                        branch.taken = None;
-                        return;
-                    }
-                }
+struct AssertVisitor<'a> {
+    rule: &'a AssertRule,
+}
 
-                return;
-            }
+impl<'a> RuleVisitor for AssertVisitor<'a> {
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            nodes: vec![NodeKind::ExprMacro],
+            lines: false,
         }
+    }
 
-        syn::visit::visit_expr_macro(self, expr);
+    fn visit_node(&mut self, node: Node, fixes: &mut Fixes) {
+        if let Node::ExprMacro(expr) = node {
+            if self.rule.is_assert(&expr.mac.path) {
+                fixes.push(Fix::IgnoreUntakenBranch {
+                    line_number: path_start_line(&expr.mac.path),
+                    executed_only: false,
+                });
+            }
+        }
     }
 }
 
@@ -448,207 +940,473 @@ impl CommentRule {
 }
 
 impl Rule for CommentRule {
-    fn fix_file_coverage(&self, source: &SourceCode, file_cov: &mut FileCoverage) {
-        fn ignore_line(entry: &mut CoverageEntry) {
-            if let Some(&mut ref mut line_cov) = entry.line_cov {
-                line_cov.count = None;
-            }
-        }
+    fn name(&self) -> &str {
+        "comment"
+    }
 
-        fn ignore_branch(entry: &mut CoverageEntry) {
-            entry.branch_covs.iter_mut().for_each(|v| v.taken = None);
-        }
+    fn reason(&self) -> &str {
+        "ignored by the marker comment"
+    }
 
-        fn ignore_both(entry: &mut CoverageEntry) {
-            ignore_line(entry);
-            ignore_branch(entry);
-        }
+    fn requires_ast(&self) -> bool {
+        false
+    }
 
-        let mut inside_ignore_line = false;
-        let mut inside_ignore_branch = false;
-        let mut inside_ignore_both = false;
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(CommentVisitor::default()))
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some(self.name().to_owned())
+    }
+}
 
-        for mut entry in PerLineIterator::new(&source.content, file_cov) {
-            use CommentMarker::*;
+#[derive(Default)]
+struct CommentVisitor {
+    /// begin marker and its line number of the current region
+    region: Option<(CommentMarker, usize)>,
+    last_line: usize,
+}
 
-            let marker = extract_marker(entry.line);
+impl CommentVisitor {
+    /// record the fixes of the lines marked by the marker (both inclusive)
+    fn ignore(marker: CommentMarker, start: usize, end: usize, fixes: &mut Fixes) {
+        use CommentMarker::*;
 
-            if inside_ignore_line {
-                ignore_line(&mut entry);
+        let origin = FixOrigin {
+            reason: format!("marked by `{}`", marker.as_str()),
+            span: (start, end),
+        };
 
-                if marker == Some(EndIgnoreLine) {
-                    inside_ignore_line = false;
+        match marker {
+            IgnoreLine | BeginIgnoreLine => {
+                for line_number in start..=end {
+                    fixes.push_with_origin(Fix::IgnoreLine(line_number), origin.clone());
                 }
-
-                continue;
             }
-
-            if inside_ignore_branch {
-                ignore_branch(&mut entry);
-
-                if marker == Some(EndIgnoreBranch) {
-                    inside_ignore_branch = false;
+            IgnoreBranch | BeginIgnoreBranch => {
+                for line_number in start..=end {
+                    fixes.push_with_origin(Fix::IgnoreBranches(line_number), origin.clone());
                 }
-
-                continue;
             }
+            _ => fixes.push_with_origin(Fix::IgnoreRange { start, end }, origin),
+        }
+    }
+}
 
-            if inside_ignore_both {
-                ignore_both(&mut entry);
+impl RuleVisitor for CommentVisitor {
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            nodes: vec![],
+            lines: true,
+        }
+    }
 
-                if marker == Some(EndIgnoreBoth) {
-                    inside_ignore_both = false;
-                }
+    fn visit_line(&mut self, line_number: usize, line: &str, fixes: &mut Fixes) {
+        use CommentMarker::*;
 
-                continue;
+        self.last_line = line_number;
+        let marker = extract_marker(line);
+
+        // other markers inside the region are ignored
+        if let Some((begin, start)) = self.region {
+            if marker.is_some() && marker == begin.end_marker() {
+                Self::ignore(begin, start, line_number, fixes);
+                self.region = None;
             }
+            return;
+        }
 
-            match marker {
-                Some(IgnoreLine) => ignore_line(&mut entry),
-                Some(IgnoreBranch) => ignore_branch(&mut entry),
-                Some(IgnoreBoth) => ignore_both(&mut entry),
-                Some(BeginIgnoreLine) => {
-                    ignore_line(&mut entry);
-                    inside_ignore_line = true;
-                }
-                Some(BeginIgnoreBranch) => {
-                    ignore_branch(&mut entry);
-                    inside_ignore_branch = true;
-                }
-                Some(BeginIgnoreBoth) => {
-                    ignore_both(&mut entry);
-                    inside_ignore_both = true;
-                }
-                _ => {}
+        match marker {
+            Some(m @ IgnoreLine) | Some(m @ IgnoreBranch) | Some(m @ IgnoreBoth) => {
+                Self::ignore(m, line_number, line_number, fixes)
+            }
+            Some(m @ BeginIgnoreLine) | Some(m @ BeginIgnoreBranch) | Some(m @ BeginIgnoreBoth) => {
+                self.region = Some((m, line_number))
             }
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self, fixes: &mut Fixes) {
+        // the region without the end marker continues to the end of the file
+        if let Some((begin, start)) = self.region.take() {
+            Self::ignore(begin, start, self.last_line, fixes);
         }
     }
 }
 
-pub fn default_rules() -> Vec<Box<dyn Rule>> {
-    vec![
-        Box::new(CloseBlockRule::new()),
-        Box::new(TestRule::new()),
-        Box::new(LoopRule::new()),
-        Box::new(DeriveRule::new()),
-        Box::new(UnreachableRule::new()),
-        Box::new(AssertRule::new()),
-        Box::new(CommentRule::new()),
-    ]
+/// Ignore all lines and branches of the file
+///
+/// This rule is disabled by default, and intended to be scoped to the generated
+/// sources (see `RuleScope`).
+pub struct IgnoreAllRule;
+
+impl IgnoreAllRule {
+    pub fn new() -> Self {
+        Self
+    }
 }
 
-pub fn from_str(s: &str) -> Result<Box<dyn Rule>, Error> {
-    if s == "close" {
-        return Ok(Box::new(CloseBlockRule::new()));
+impl Rule for IgnoreAllRule {
+    fn name(&self) -> &str {
+        "all"
     }
-    if s == "test" {
-        return Ok(Box::new(TestRule::new()));
+
+    fn reason(&self) -> &str {
+        "all lines are ignored in this file"
     }
-    if s == "loop" {
-        return Ok(Box::new(LoopRule::new()));
+
+    fn requires_ast(&self) -> bool {
+        false
     }
-    if s == "derive" {
-        return Ok(Box::new(DeriveRule::new()));
+
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(IgnoreAllVisitor))
     }
-    if s == "unreachable" {
-        return Ok(Box::new(UnreachableRule::new()));
+
+    fn cache_key(&self) -> Option<String> {
+        Some(self.name().to_owned())
     }
-    if s == "assert" {
-        return Ok(Box::new(AssertRule::new()));
+}
+
+struct IgnoreAllVisitor;
+
+impl RuleVisitor for IgnoreAllVisitor {
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            nodes: vec![],
+            lines: true,
+        }
     }
-    if s == "comment" {
-        return Ok(Box::new(CommentRule::new()));
+
+    fn visit_line(&mut self, line_number: usize, _line: &str, fixes: &mut Fixes) {
+        // lines past the end of file are also ignored
+        if line_number == 1 {
+            fixes.ignore_range(1, usize::MAX);
+        }
     }
+}
 
-    Err(ErrorKind::InvalidRuleName(s.to_owned()).into())
+/// rules enabled by default
+pub fn default_rules() -> Vec<Box<dyn Rule>> {
+    RuleRegistry::new().default_rules()
 }
 
-// ---------- Utilities ----------
+/// create the builtin rule by name
+pub fn from_str(s: &str) -> Result<Box<dyn Rule>, Error> {
+    RuleRegistry::new().create(s)
+}
+
+/// set the option in `RULE.KEY=VALUE` format to the rule with the name
+///
+/// Returns `false` if none of the rules has the name.
+pub fn configure(rules: &mut [Box<dyn Rule>], option: &str) -> Result<bool, Error> {
+    let invalid = || Error::from(ErrorKind::InvalidRuleOption(option.to_owned()));
+
+    let pos = option.find('=').ok_or_else(invalid)?;
+    let (target, value) = (&option[..pos], &option[pos + 1..]);
+    let pos = target.find('.').ok_or_else(invalid)?;
+    let (name, key) = (&target[..pos], &target[pos + 1..]);
+
+    match rules.iter_mut().find(|r| r.name() == name) {
+        Some(rule) => {
+            rule.set_option(key, value)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
 
-struct CoverageEntry<'a, 'b> {
-    line: &'a str,
-    line_cov: Option<&'b mut LineCoverage>,
-    branch_covs: &'b mut [BranchCoverage],
+type RuleFactory = Box<dyn Fn() -> Box<dyn Rule> + Send + Sync>;
+
+/// Rule registered in `RuleRegistry`
+pub struct RuleEntry {
+    name: String,
+    description: String,
+    default: bool,
+    factory: RuleFactory,
 }
 
-/// A coverage iterator over the lines of a source files.
-#[derive(Clone)]
-struct PerLineIterator<'a, 'b> {
-    line_number: usize,
-    lines: Vec<&'a str>,
-    lp: *mut LineCoverage,
-    lp_end: *mut LineCoverage,
-    bp: *mut BranchCoverage,
-    bp_end: *mut BranchCoverage,
-    _borrow: PhantomData<&'b FileCoverage>,
+impl RuleEntry {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// whether the rule is enabled by default
+    pub fn is_default(&self) -> bool {
+        self.default
+    }
+
+    /// create the new instance of the rule
+    pub fn create(&self) -> Box<dyn Rule> {
+        (self.factory)()
+    }
+}
+
+/// Set of the rules available by name
+///
+/// `new()` creates the registry with builtin rules, and library users can register
+/// their own rules with `register()`. Rules are listed in the order of registration.
+pub struct RuleRegistry {
+    entries: Vec<RuleEntry>,
 }
 
-impl<'a, 'b> PerLineIterator<'a, 'b> {
-    fn new(source: &'a str, file_cov: &'b mut FileCoverage) -> PerLineIterator<'a, 'b> {
-        let lp = file_cov.line_coverages.as_mut_ptr();
-        let bp = file_cov.branch_coverages.as_mut_ptr();
-        let lp_end = unsafe { lp.add(file_cov.line_coverages.len()) };
-        let bp_end = unsafe { bp.add(file_cov.branch_coverages.len()) };
+impl RuleRegistry {
+    /// create the registry with builtin rules
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
 
+        registry.add(
+            "close",
+            "lines which consist of closing brackets (e.g. `}`, `});`, `} else {`)",
+            true,
+            CloseBlockRule::new,
+        );
+        registry.add(
+            "test",
+            "functions with `#[test]` attribute and `#[cfg(test)]` modules",
+            true,
+            TestRule::new,
+        );
+        registry.add(
+            "loop",
+            "exit branch of the executed `for` loops",
+            true,
+            LoopRule::new,
+        );
+        registry.add(
+            "derive",
+            "structs, enums and unions with `#[derive(..)]` attribute",
+            true,
+            DeriveRule::new,
+        );
+        registry.add(
+            "unreachable",
+            "`unreachable!()` macro calls",
+            true,
+            UnreachableRule::new,
+        );
+        registry.add(
+            "assert",
+            "failure branch of `assert!()`, `assert_eq!()` and so on",
+            true,
+            AssertRule::new,
+        );
+        registry.add(
+            "comment",
+            "lines and branches marked by `cov:ignore` comments",
+            true,
+            CommentRule::new,
+        );
+        registry.add(
+            "all",
+            "all lines and branches (e.g. for generated code)",
+            false,
+            IgnoreAllRule::new,
+        );
+
+        registry
+    }
+
+    /// create the registry without any rules
+    pub fn empty() -> Self {
         Self {
-            line_number: 1,
-            lines: source.lines().collect(),
-            lp,
-            bp,
-            lp_end,
-            bp_end,
-            _borrow: PhantomData,
+            entries: Vec::new(),
         }
     }
-}
 
-impl<'a, 'b> Iterator for PerLineIterator<'a, 'b> {
-    type Item = CoverageEntry<'a, 'b>;
+    fn add<R: Rule + 'static>(
+        &mut self,
+        name: &str,
+        description: &str,
+        default: bool,
+        factory: fn() -> R,
+    ) {
+        self.entries.push(RuleEntry {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            default,
+            factory: Box::new(move || Box::new(factory())),
+        });
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.line_number > self.lines.len() {
-            return None;
+    /// register the rule under the name
+    ///
+    /// If `default` is `true`, the rule is enabled by default (i.e. included in
+    /// `default_rules()`). Names must be unique.
+    pub fn register<F>(
+        &mut self,
+        name: &str,
+        description: &str,
+        default: bool,
+        factory: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn() -> Box<dyn Rule> + Send + Sync + 'static,
+    {
+        if name.is_empty() || name == "default" || name.contains([',', '+']) {
+            return Err(ErrorKind::InvalidRuleName(name.to_owned()).into());
+        }
+        if self.get(name).is_some() {
+            return Err(ErrorKind::DuplicateRuleName(name.to_owned()).into());
         }
 
-        unsafe {
-            let line = self.lines.get_unchecked_mut(self.line_number - 1);
+        self.entries.push(RuleEntry {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            default,
+            factory: Box::new(factory),
+        });
 
-            // line coverage at current line
-            let line_cov = if self.lp < self.lp_end && (*self.lp).line_number == self.line_number {
-                let val = Some(&mut *self.lp);
-                self.lp = self.lp.add(1);
-                val
-            } else {
-                None
-            };
+        Ok(())
+    }
 
-            // branch coverages at current line
-            let branch_covs = if self.bp < self.bp_end && (*self.bp).line_number == self.line_number
-            {
-                let start = self.bp;
-                self.bp = self.bp.add(1);
-                let mut count = 1;
-                while self.bp < self.bp_end && (*self.bp).line_number == self.line_number {
-                    self.bp = self.bp.add(1);
-                    count += 1;
+    pub fn get(&self, name: &str) -> Option<&RuleEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// registered rules in the order of registration
+    pub fn entries(&self) -> &[RuleEntry] {
+        &self.entries
+    }
+
+    /// create the rule by name
+    pub fn create(&self, name: &str) -> Result<Box<dyn Rule>, Error> {
+        self.get(name)
+            .map(|e| e.create())
+            .ok_or_else(|| ErrorKind::InvalidRuleName(name.to_owned()).into())
+    }
+
+    /// create the rules enabled by default
+    pub fn default_rules(&self) -> Vec<Box<dyn Rule>> {
+        self.entries
+            .iter()
+            .filter(|e| e.default)
+            .map(|e| e.create())
+            .collect()
+    }
+
+    /// names of the rules selected by the specification
+    ///
+    /// The specification is a comma-separated list of the following items.
+    ///
+    /// - `default`: all rules enabled by default
+    /// - `NAME` or `+NAME`: the rule
+    /// - `-NAME`: remove the rule selected by the preceding items
+    ///
+    /// If the first item starts with `+` or `-`, the default rules are selected at first
+    /// (e.g. `-loop` selects all default rules except `loop`).
+    pub fn select(&self, spec: &str) -> Result<Vec<&str>, Error> {
+        let items: Vec<&str> = spec
+            .split(',')
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .collect();
+
+        let mut selected: Vec<&str> = Vec::new();
+        if matches!(items.first(), Some(v) if v.starts_with('+') || v.starts_with('-')) {
+            selected.extend(self.default_names());
+        }
+
+        for item in items {
+            if item == "default" {
+                for name in self.default_names() {
+                    if !selected.contains(&name) {
+                        selected.push(name);
+                    }
                 }
-                ::std::slice::from_raw_parts_mut(start, count)
+                continue;
+            }
+
+            let (remove, name) = if let Some(name) = item.strip_prefix('-') {
+                (true, name)
             } else {
-                &mut []
+                (false, item.strip_prefix('+').unwrap_or(item))
             };
 
-            self.line_number += 1;
+            let entry = self
+                .get(name)
+                .ok_or_else(|| Error::from(ErrorKind::InvalidRuleName(name.to_owned())))?;
+
+            if remove {
+                selected.retain(|&v| v != entry.name);
+            } else if !selected.contains(&entry.name.as_str()) {
+                selected.push(&entry.name);
+            }
+        }
+
+        Ok(selected)
+    }
+
+    /// create the rules selected by the specification. See `select()` for the syntax.
+    pub fn parse(&self, spec: &str) -> Result<Vec<Box<dyn Rule>>, Error> {
+        self.select(spec)?
+            .into_iter()
+            .map(|name| self.create(name))
+            .collect()
+    }
+
+    /// create the rules selected by the specification (default rules if `None`), and set
+    /// the options in `RULE.KEY=VALUE` format
+    ///
+    /// Options for the registered rules which are not selected are ignored.
+    pub fn build<'a, I>(&self, spec: Option<&str>, options: I) -> Result<Vec<Box<dyn Rule>>, Error>
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let mut rules = match spec {
+            Some(spec) => self.parse(spec)?,
+            None => self.default_rules(),
+        };
 
-            Some(CoverageEntry {
-                line,
-                line_cov,
-                branch_covs,
-            })
+        for option in options {
+            if !self.configure(&mut rules, option)? {
+                debugln!("Ignoring option {:?} for the disabled rule", option);
+            }
         }
+
+        Ok(rules)
+    }
+
+    /// set the option in `RULE.KEY=VALUE` format (see `configure()`)
+    ///
+    /// Returns `false` if the rule is registered but not included in `rules`, and fails
+    /// if the rule is not registered.
+    pub fn configure(&self, rules: &mut [Box<dyn Rule>], option: &str) -> Result<bool, Error> {
+        if configure(rules, option)? {
+            return Ok(true);
+        }
+
+        let name = option.split('.').next().unwrap_or_default();
+        if self.get(name).is_none() {
+            return Err(ErrorKind::InvalidRuleName(name.to_owned()).into());
+        }
+
+        Ok(false)
+    }
+
+    fn default_names(&self) -> impl Iterator<Item = &str> {
+        self.entries
+            .iter()
+            .filter(|e| e.default)
+            .map(|e| e.name.as_str())
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl Default for RuleRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ---------- Utilities ----------
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum CommentMarker {
     IgnoreLine,
     IgnoreBranch,
@@ -661,6 +1419,36 @@ enum CommentMarker {
     EndIgnoreBoth,
 }
 
+impl CommentMarker {
+    fn as_str(&self) -> &'static str {
+        use CommentMarker::*;
+
+        match *self {
+            IgnoreLine => "cov:ignore-line",
+            IgnoreBranch => "cov:ignore-branch",
+            IgnoreBoth => "cov:ignore",
+            BeginIgnoreLine => "cov:begin-ignore-line",
+            BeginIgnoreBranch => "cov:begin-ignore-branch",
+            BeginIgnoreBoth => "cov:begin-ignore",
+            EndIgnoreLine => "cov:end-ignore-line",
+            EndIgnoreBranch => "cov:end-ignore-branch",
+            EndIgnoreBoth => "cov:end-ignore",
+        }
+    }
+
+    /// marker which ends the region started by this marker
+    fn end_marker(&self) -> Option<CommentMarker> {
+        use CommentMarker::*;
+
+        match *self {
+            BeginIgnoreLine => Some(EndIgnoreLine),
+            BeginIgnoreBranch => Some(EndIgnoreBranch),
+            BeginIgnoreBoth => Some(EndIgnoreBoth),
+            _ => None,
+        }
+    }
+}
+
 fn extract_marker(line: &str) -> Option<CommentMarker> {
     fn is_character(byte: u8) -> bool {
         (0x41 <= byte && byte <= 0x5a)
@@ -743,7 +1531,10 @@ impl_default!(CloseBlockRule);
 impl_default!(TestRule);
 impl_default!(LoopRule);
 impl_default!(DeriveRule);
+impl_default!(UnreachableRule);
+impl_default!(AssertRule);
 impl_default!(CommentRule);
+impl_default!(IgnoreAllRule);
 // cov:end-ignore
 
 #[cfg(test)]
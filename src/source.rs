@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+
+use crate::coverage::normalize_path;
+use crate::error::*;
+
+/// Source of the source code text
+///
+/// `CoverageFixer` reads the source files through this trait, so that the coverage can
+/// be fixed without the source files on the filesystem.
+pub trait SourceProvider: Send + Sync {
+    /// read the text of the source file
+    ///
+    /// `path` is the path stored in the coverage (usually an absolute path). If the file
+    /// does not exist, `ErrorKind::SourceFileNotFound` should be returned.
+    fn read_source(&self, path: &Path) -> Result<String, Error>;
+}
+
+/// Read the source files from the filesystem
+#[derive(Clone, Debug, Default)]
+pub struct FsSourceProvider;
+
+impl FsSourceProvider {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl SourceProvider for FsSourceProvider {
+    fn read_source(&self, path: &Path) -> Result<String, Error> {
+        fs::read_to_string(path).chain_err(|| ErrorKind::SourceFileNotFound(path.to_owned()))
+    }
+}
+
+/// Read the source files from the in-memory map
+#[derive(Clone, Debug, Default)]
+pub struct MemorySourceProvider {
+    files: HashMap<PathBuf, String>,
+}
+
+impl MemorySourceProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add the source file. The path is normalized before stored.
+    pub fn insert<P: AsRef<Path>, S: Into<String>>(&mut self, path: P, content: S) {
+        self.files
+            .insert(normalize_path(path.as_ref()), content.into());
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl SourceProvider for MemorySourceProvider {
+    fn read_source(&self, path: &Path) -> Result<String, Error> {
+        self.files
+            .get(&normalize_path(path))
+            .cloned()
+            .ok_or_else(|| ErrorKind::SourceFileNotFound(path.to_owned()).into())
+    }
+}
+
+/// Read the source files at the specific revision of the git repository
+///
+/// The contents of `<rev>:<path>` are read, so that the coverage can be fixed against
+/// the sources which produced it even if the working tree has changed. A single
+/// `git cat-file --batch` process is started on the first read and used for all files.
+#[derive(Debug)]
+pub struct GitSourceProvider {
+    /// top level directory of the repository
+    toplevel: PathBuf,
+    /// the directory given to `new()`
+    dir: PathBuf,
+    /// path of `dir` relative to `toplevel`
+    prefix: PathBuf,
+    /// full commit hash
+    commit: String,
+    /// started on the first read
+    batch: Mutex<Option<CatFile>>,
+}
+
+impl GitSourceProvider {
+    /// open the repository which contains `dir`
+    ///
+    /// `rev` can be any revision accepted by git (e.g. commit hash, branch or tag name),
+    /// and is resolved to the commit hash at this time.
+    pub fn new(dir: &Path, rev: &str) -> Result<Self, Error> {
+        let toplevel = git(dir, &["rev-parse", "--show-toplevel"])?;
+        let prefix = git(dir, &["rev-parse", "--show-prefix"])?;
+        let commit = git(
+            dir,
+            &["rev-parse", "--verify", &format!("{}^{{commit}}", rev)],
+        )
+        .chain_err(|| format!("Invalid git revision: {:?}", rev))?;
+
+        Ok(Self {
+            toplevel: normalize_path(Path::new(&toplevel)),
+            dir: normalize_path(dir),
+            prefix: PathBuf::from(prefix),
+            commit,
+            batch: Mutex::new(None),
+        })
+    }
+
+    /// full hash of the commit from which the sources are read
+    pub fn commit(&self) -> &str {
+        &self.commit
+    }
+
+    /// path of the file relative to the top level of the repository
+    fn repo_path(&self, path: &Path) -> Option<PathBuf> {
+        let path = normalize_path(path);
+        if let Ok(relative) = path.strip_prefix(&self.toplevel) {
+            return Some(relative.to_owned());
+        }
+        if let Ok(relative) = path.strip_prefix(&self.dir) {
+            return Some(self.prefix.join(relative));
+        }
+
+        None
+    }
+}
+
+impl SourceProvider for GitSourceProvider {
+    fn read_source(&self, path: &Path) -> Result<String, Error> {
+        let relative = self
+            .repo_path(path)
+            .ok_or_else(|| ErrorKind::SourceFileNotFound(path.to_owned()))?;
+
+        // git always uses `/` as the path separator
+        let relative: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        let object = format!("{}:{}", self.commit, relative.join("/"));
+        if object.contains('\n') {
+            return Err(ErrorKind::SourceFileNotFound(path.to_owned()).into());
+        }
+
+        let content = {
+            let mut batch = self.batch.lock().unwrap();
+            if batch.is_none() {
+                *batch = Some(CatFile::spawn(&self.toplevel)?);
+            }
+
+            let result = batch.as_mut().unwrap().read(&object);
+            if result.is_err() {
+                // start a new process on the next read
+                *batch = None;
+            }
+            result.chain_err(|| "Failed to read from `git cat-file`")?
+        };
+
+        let content = content.ok_or_else(|| ErrorKind::SourceFileNotFound(path.to_owned()))?;
+        String::from_utf8(content)
+            .map_err(|_| format!("Source file {:?} is not valid UTF-8", path).into())
+    }
+}
+
+impl Clone for GitSourceProvider {
+    /// clone the repository information. The `git cat-file` process is not shared.
+    fn clone(&self) -> Self {
+        Self {
+            toplevel: self.toplevel.clone(),
+            dir: self.dir.clone(),
+            prefix: self.prefix.clone(),
+            commit: self.commit.clone(),
+            batch: Mutex::new(None),
+        }
+    }
+}
+
+/// `git cat-file --batch` process which reads the objects given on stdin
+#[derive(Debug)]
+struct CatFile {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl CatFile {
+    fn spawn(dir: &Path) -> Result<Self, Error> {
+        let mut child = Command::new("git")
+            .args(["cat-file", "--batch"])
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .chain_err(|| "Failed to execute `git cat-file`")?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// read the content of the blob. `None` is returned if the object is missing or is
+    /// not a blob.
+    fn read(&mut self, object: &str) -> io::Result<Option<Vec<u8>>> {
+        writeln!(self.stdin, "{}", object)?;
+        self.stdin.flush()?;
+
+        let mut header = String::new();
+        if self.stdout.read_line(&mut header)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "process exited unexpectedly",
+            ));
+        }
+
+        // `<object> missing` or `<hash> <type> <size>`
+        let header = header.trim_end();
+        if header.ends_with(" missing") || header.ends_with(" ambiguous") {
+            return Ok(None);
+        }
+
+        let mut fields = header.rsplitn(3, ' ');
+        let size = fields.next().and_then(|s| s.parse::<usize>().ok());
+        let kind = fields.next();
+        let size = size.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid header: {:?}", header),
+            )
+        })?;
+
+        // content is followed by a newline
+        let mut content = vec![0; size + 1];
+        self.stdout.read_exact(&mut content)?;
+        content.pop();
+
+        Ok(Some(content).filter(|_| kind == Some("blob")))
+    }
+}
+
+impl Drop for CatFile {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// execute git command and return the trimmed stdout
+fn git(dir: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .chain_err(|| "Failed to execute `git`")?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// Read the source files from tar or zip archive
+///
+/// Archive entries are loaded into memory by `load_*` methods, and their paths are
+/// resolved relative to the project root (e.g. `src/lib.rs` in the archive is used for
+/// `<root>/src/lib.rs`). Only `.rs` files are loaded.
+#[cfg(feature = "archive")]
+#[cfg_attr(docsrs, doc(cfg(feature = "archive")))]
+#[derive(Clone, Debug)]
+pub struct ArchiveSourceProvider {
+    root: PathBuf,
+    strip_components: usize,
+    sources: MemorySourceProvider,
+}
+
+#[cfg(feature = "archive")]
+impl ArchiveSourceProvider {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            strip_components: 0,
+            sources: MemorySourceProvider::new(),
+        }
+    }
+
+    /// remove the leading components from the entry paths (like `tar --strip-components`)
+    ///
+    /// This must be called before loading the archives.
+    pub fn set_strip_components(&mut self, n: usize) {
+        self.strip_components = n;
+    }
+
+    /// load the archive file. The format is detected by the file extension (`.tar`,
+    /// `.tar.gz`, `.tgz` or `.zip`).
+    pub fn load_file(&mut self, path: &Path) -> Result<(), Error> {
+        let name = path
+            .file_name()
+            .map(|v| v.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let f = fs::File::open(path).chain_err(|| format!("Failed to open archive {:?}", path))?;
+
+        let res = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            self.load_tar(flate2::read::GzDecoder::new(f))
+        } else if name.ends_with(".tar") {
+            self.load_tar(f)
+        } else if name.ends_with(".zip") {
+            self.load_zip(f)
+        } else {
+            Err(format!("Unsupported archive format: {:?}", path).into())
+        };
+
+        res.chain_err(|| format!("Failed to read archive {:?}", path))
+    }
+
+    /// load the tar archive
+    pub fn load_tar<R: std::io::Read>(&mut self, reader: R) -> Result<(), Error> {
+        let mut archive = tar::Archive::new(reader);
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let path = entry.path()?.into_owned();
+            if let Some(path) = self.entry_path(&path) {
+                let mut content = String::new();
+                std::io::Read::read_to_string(&mut entry, &mut content)?;
+                self.sources.insert(path, content);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// load the zip archive
+    pub fn load_zip<R: std::io::Read + std::io::Seek>(&mut self, reader: R) -> Result<(), Error> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            if !file.is_file() {
+                continue;
+            }
+
+            let path = match file.enclosed_name() {
+                Some(path) => path.to_owned(),
+                None => continue,
+            };
+            if let Some(path) = self.entry_path(&path) {
+                let mut content = String::new();
+                std::io::Read::read_to_string(&mut file, &mut content)?;
+                self.sources.insert(path, content);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// number of loaded source files
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    fn entry_path(&self, path: &Path) -> Option<PathBuf> {
+        if path.extension() != Some(std::ffi::OsStr::new("rs")) {
+            return None;
+        }
+
+        let relative: PathBuf = path.components().skip(self.strip_components).collect();
+        if relative.as_os_str().is_empty() {
+            return None;
+        }
+
+        Some(self.root.join(relative))
+    }
+}
+
+#[cfg(feature = "archive")]
+impl SourceProvider for ArchiveSourceProvider {
+    fn read_source(&self, path: &Path) -> Result<String, Error> {
+        self.sources.read_source(path)
+    }
+}
+
+impl<P: SourceProvider + ?Sized> SourceProvider for Box<P> {
+    fn read_source(&self, path: &Path) -> Result<String, Error> {
+        (**self).read_source(path)
+    }
+}
@@ -0,0 +1,328 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::coverage::{DeclaredTotals, FileCoverage, PackageCoverage};
+use crate::source::SourceProvider;
+
+/// maximum size of the table used to compute the difference between the old and new
+/// contents. Changed region larger than this is regarded as unmappable.
+const MAX_DIFF_CELLS: usize = 1 << 22;
+
+/// Reason why the source file seems to be different from the one which produced the
+/// coverage
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StaleReason {
+    /// the coverage has a line past the end of the source file
+    LinePastEnd {
+        line_number: usize,
+        line_count: usize,
+    },
+    /// the checksums recorded in the coverage do not match the source lines
+    ChecksumMismatch { line_numbers: Vec<usize> },
+    /// the content differs from the revision which produced the coverage
+    ContentChanged,
+}
+
+impl fmt::Display for StaleReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StaleReason::LinePastEnd {
+                line_number,
+                line_count,
+            } => write!(
+                f,
+                "line {} is past the end of file ({} lines)",
+                line_number, line_count
+            ),
+            StaleReason::ChecksumMismatch { ref line_numbers } => {
+                write!(f, "checksum mismatch at {} lines", line_numbers.len())
+            }
+            StaleReason::ContentChanged => write!(f, "content has changed"),
+        }
+    }
+}
+
+/// Source file which seems to be stale
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StaleFile {
+    pub path: PathBuf,
+    pub reasons: Vec<StaleReason>,
+    /// lines which could not be remapped and were dropped from the coverage
+    pub dropped_lines: Vec<usize>,
+}
+
+/// check whether the coverage matches the source code
+///
+/// Lines past the end of file and the line checksums recorded in the coverage (if any)
+/// are checked.
+pub fn detect_stale(file_cov: &FileCoverage, content: &str) -> Vec<StaleReason> {
+    let mut reasons = Vec::new();
+    let lines: Vec<&str> = content.lines().collect();
+
+    let last_line = file_cov
+        .line_coverages()
+        .iter()
+        .map(|v| v.line_number)
+        .chain(file_cov.branch_coverages().iter().map(|v| v.line_number))
+        .max();
+    if let Some(line_number) = last_line {
+        if line_number > lines.len() {
+            reasons.push(StaleReason::LinePastEnd {
+                line_number,
+                line_count: lines.len(),
+            });
+        }
+    }
+
+    let line_numbers: Vec<usize> = file_cov
+        .line_checksums()
+        .filter(|&(line_number, checksum)| {
+            match line_number.checked_sub(1).and_then(|i| lines.get(i)) {
+                Some(line) => line_checksum(line) != checksum,
+                None => false,
+            }
+        })
+        .map(|(line_number, _)| line_number)
+        .collect();
+    if !line_numbers.is_empty() {
+        reasons.push(StaleReason::ChecksumMismatch { line_numbers });
+    }
+
+    reasons
+}
+
+/// checksum of the source line in lcov format (base64-encoded MD5 without padding)
+pub fn line_checksum(line: &str) -> String {
+    let digest = md5::compute(line.trim_end_matches(&['\r', '\n'][..]).as_bytes());
+    base64_encode(&digest.0)
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len() * 4 / 3 + 1);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).cloned().unwrap_or(0),
+            chunk.get(2).cloned().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+
+        for i in 0..=chunk.len() {
+            encoded.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+
+    encoded
+}
+
+/// Mapping of line numbers from the old content to the new content
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineMap {
+    /// new line number for each old line (0-indexed)
+    map: Vec<Option<usize>>,
+}
+
+impl LineMap {
+    /// compute the mapping from the difference of the contents
+    ///
+    /// Only the lines which are not changed are mapped.
+    pub fn new(old: &str, new: &str) -> Self {
+        let old: Vec<&str> = old.lines().collect();
+        let new: Vec<&str> = new.lines().collect();
+        let mut map = vec![None; old.len()];
+
+        let prefix = old
+            .iter()
+            .zip(new.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        for (i, v) in map.iter_mut().enumerate().take(prefix) {
+            *v = Some(i + 1);
+        }
+        for (i, v) in map.iter_mut().rev().enumerate().take(suffix) {
+            *v = Some(new.len() - i);
+        }
+
+        let old_middle = &old[prefix..old.len() - suffix];
+        let new_middle = &new[prefix..new.len() - suffix];
+        for (i, j) in lcs(old_middle, new_middle) {
+            map[prefix + i] = Some(prefix + j + 1);
+        }
+
+        Self { map }
+    }
+
+    /// new line number of the old line. `None` if the line was changed or removed.
+    pub fn map(&self, line_number: usize) -> Option<usize> {
+        line_number
+            .checked_sub(1)
+            .and_then(|idx| self.map.get(idx))
+            .cloned()
+            .flatten()
+    }
+}
+
+/// pairs of indices of the longest common subsequence
+fn lcs(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    if a.is_empty() || b.is_empty() || (a.len() + 1) * (b.len() + 1) > MAX_DIFF_CELLS {
+        return Vec::new();
+    }
+
+    let width = b.len() + 1;
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i * width + j] = if a[i] == b[j] {
+                table[(i + 1) * width + j + 1] + 1
+            } else {
+                table[(i + 1) * width + j].max(table[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * width + j] >= table[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    pairs
+}
+
+impl FileCoverage {
+    /// renumber the lines and branches by the line map
+    ///
+    /// Lines and branches which cannot be mapped are removed, and their line numbers are
+    /// returned.
+    pub fn remap_lines(&mut self, map: &LineMap) -> Vec<usize> {
+        let mut dropped = Vec::new();
+
+        self.line_coverages
+            .retain_mut(|v| match map.map(v.line_number) {
+                Some(n) => {
+                    v.line_number = n;
+                    true
+                }
+                None => {
+                    dropped.push(v.line_number);
+                    false
+                }
+            });
+        self.branch_coverages
+            .retain_mut(|v| match map.map(v.line_number) {
+                Some(n) => {
+                    v.line_number = n;
+                    true
+                }
+                None => {
+                    dropped.push(v.line_number);
+                    false
+                }
+            });
+
+        self.checksums = std::mem::take(&mut self.checksums)
+            .into_iter()
+            .filter_map(|(k, v)| map.map(k).map(|n| (n, v)))
+            .collect();
+        self.set_declared_totals(DeclaredTotals::default());
+        self.sort();
+
+        dropped.sort_unstable();
+        dropped.dedup();
+        dropped
+    }
+}
+
+impl PackageCoverage {
+    /// compare the sources which produced the coverage with the current sources
+    ///
+    /// `recorded` provides the sources which produced the coverage (e.g. the git commit
+    /// of the coverage run), and `current` provides the sources used for fixing. If
+    /// `remap` is `true`, line numbers of the changed files are remapped through the
+    /// difference of the contents. Files which cannot be read from either provider are
+    /// not checked.
+    pub fn check_stale_sources(
+        &mut self,
+        recorded: &dyn SourceProvider,
+        current: &dyn SourceProvider,
+        remap: bool,
+    ) -> Vec<StaleFile> {
+        let mut stale_files = Vec::new();
+
+        for file_cov in &mut self.file_coverages {
+            let path = file_cov.path().to_owned();
+            let (old, new) = match (recorded.read_source(&path), current.read_source(&path)) {
+                (Ok(old), Ok(new)) => (old, new),
+                _ => {
+                    debugln!("Skipping stale check for {:?}", path);
+                    continue;
+                }
+            };
+
+            if old == new {
+                continue;
+            }
+
+            let dropped_lines = if remap {
+                file_cov.remap_lines(&LineMap::new(&old, &new))
+            } else {
+                Vec::new()
+            };
+
+            stale_files.push(StaleFile {
+                path,
+                reasons: vec![StaleReason::ContentChanged],
+                dropped_lines,
+            });
+        }
+
+        stale_files
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        // base64-encoded MD5 digests without padding
+        assert_eq!(line_checksum(""), "1B2M2Y8AsgTpgAmY7PhCfg");
+        assert_eq!(line_checksum("fn main() {"), "PylIMgUP+gYGTRSh1c+xqA");
+        assert_eq!(line_checksum("}\r\n"), line_checksum("}"));
+    }
+
+    #[test]
+    fn line_map() {
+        let old = "a\nb\nc\nd\ne\n";
+        let new = "a\nx\nb\nd\ne\nf\n";
+        let map = LineMap::new(old, new);
+
+        assert_eq!(map.map(1), Some(1));
+        assert_eq!(map.map(2), Some(3));
+        assert_eq!(map.map(3), None);
+        assert_eq!(map.map(4), Some(4));
+        assert_eq!(map.map(5), Some(5));
+        assert_eq!(map.map(6), None);
+        assert_eq!(map.map(0), None);
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use syn::visit::Visit;
+use syn::{ImplItemMethod, Item, ItemFn, ItemMod, Lit, Meta, TraitItemMethod};
+
+use crate::coverage::{normalize_path, percent, CoverageSummary, FileCoverage, PackageCoverage};
+use crate::error::*;
+use crate::rule::SourceCode;
+use crate::source::{FsSourceProvider, SourceProvider};
+
+/// A node of the hierarchical coverage summary
+///
+/// Each node holds the totals of all files (or modules) under it.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SummaryNode {
+    /// directory (or file) name, or module name
+    pub name: String,
+    pub summary: CoverageSummary,
+    /// number of functions which has at least one executed line
+    pub function_executed: usize,
+    /// number of functions which has at least one executable line
+    pub function_total: usize,
+    pub children: Vec<SummaryNode>,
+}
+
+impl SummaryNode {
+    fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn child(&self, name: &str) -> Option<&SummaryNode> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    /// find the descendant node by path
+    ///
+    /// Segments are separated by `/` or `::`, and the name of this node may be omitted
+    /// at the beginning (e.g. `crate::net::http`, `net::http` or `src/net`).
+    pub fn find(&self, path: &str) -> Option<&SummaryNode> {
+        let mut segments: Vec<&str> = path
+            .split(&['/', ':'][..])
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        if segments.first() == Some(&self.name.as_str()) {
+            segments.remove(0);
+        }
+
+        segments
+            .iter()
+            .try_fold(self, |node, segment| node.child(segment))
+    }
+
+    /// percentage of executed functions. `None` if there are no functions.
+    pub fn function_percent(&self) -> Option<f64> {
+        percent(self.function_executed, self.function_total)
+    }
+
+    fn add<'a, I: IntoIterator<Item = &'a str>>(&mut self, path: I, counts: &Counts) {
+        self.add_counts(counts);
+
+        let mut node = self;
+        for name in path {
+            let idx = match node.children.iter().position(|c| c.name == name) {
+                Some(idx) => idx,
+                None => {
+                    node.children.push(SummaryNode::new(name));
+                    node.children.len() - 1
+                }
+            };
+
+            node = &mut node.children[idx];
+            node.add_counts(counts);
+        }
+    }
+
+    fn add_counts(&mut self, counts: &Counts) {
+        self.summary.line_executed += counts.summary.line_executed;
+        self.summary.line_total += counts.summary.line_total;
+        self.summary.branch_executed += counts.summary.branch_executed;
+        self.summary.branch_total += counts.summary.branch_total;
+        self.function_executed += counts.function_executed;
+        self.function_total += counts.function_total;
+    }
+}
+
+/// summarize the coverage by directories
+///
+/// Paths are relative to `root`. Leaf nodes represent the source files. Function
+/// totals are computed from the source code, and are zero if the source file cannot
+/// be parsed.
+pub fn summarize_by_directory(data: &PackageCoverage, root: &Path) -> SummaryNode {
+    summarize_by_directory_with(data, root, &FsSourceProvider::new())
+}
+
+/// same as `summarize_by_directory()`, but read the source files from the provider
+pub fn summarize_by_directory_with(
+    data: &PackageCoverage,
+    root: &Path,
+    provider: &dyn SourceProvider,
+) -> SummaryNode {
+    let mut tree = SummaryNode::new("");
+
+    for file_cov in data.file_coverages() {
+        let functions = match parse_source(provider, file_cov.path()) {
+            Ok(source) => function_spans(&source),
+            Err(_) => {
+                debugln!("Failed to parse {:?}", file_cov.path());
+                Vec::new()
+            }
+        };
+
+        let counts = Counts::new(file_cov, &functions, |_| true);
+
+        let path = file_cov
+            .path()
+            .strip_prefix(root)
+            .unwrap_or(file_cov.path());
+        let names: Vec<_> = path
+            .iter()
+            .map(|c| c.to_string_lossy().into_owned())
+            .collect();
+        tree.add(names.iter().map(|s| s.as_str()), &counts);
+    }
+
+    tree
+}
+
+/// summarize the coverage by Rust modules
+///
+/// The module structure is detected by following `mod` declarations from the crate root
+/// (e.g. `src/lib.rs`). The root node is named `crate`. Files which are not reachable
+/// from the crate root are not included.
+pub fn summarize_by_module(
+    data: &PackageCoverage,
+    crate_root: &Path,
+) -> Result<SummaryNode, Error> {
+    summarize_by_module_with(data, crate_root, &FsSourceProvider::new())
+}
+
+/// same as `summarize_by_module()`, but read the source files from the provider
+pub fn summarize_by_module_with(
+    data: &PackageCoverage,
+    crate_root: &Path,
+    provider: &dyn SourceProvider,
+) -> Result<SummaryNode, Error> {
+    let mut modules = ModuleMap {
+        provider,
+        files: HashMap::new(),
+    };
+    let crate_root = normalize_path(crate_root);
+    let mod_dir = crate_root.parent().map(Path::to_owned).unwrap_or_default();
+    modules.load_file(&crate_root, &mod_dir, vec![])?;
+
+    let mut tree = SummaryNode::new("crate");
+
+    for file_cov in data.file_coverages() {
+        let path = normalize_path(file_cov.path());
+        let info = match modules.files.get(&path) {
+            Some(info) => info,
+            None => {
+                debugln!("{:?} is not a module of the crate", file_cov.path());
+                continue;
+            }
+        };
+
+        for (idx, module) in info.modules.iter().enumerate() {
+            let counts = Counts::new(file_cov, &info.functions, |line| {
+                info.module_index(line) == idx
+            });
+            tree.add(module.path.iter().map(|s| s.as_str()), &counts);
+        }
+    }
+
+    Ok(tree)
+}
+
+#[derive(Default)]
+struct Counts {
+    summary: CoverageSummary,
+    function_executed: usize,
+    function_total: usize,
+}
+
+impl Counts {
+    fn new<F: Fn(usize) -> bool>(
+        file_cov: &FileCoverage,
+        functions: &[(usize, usize)],
+        filter: F,
+    ) -> Self {
+        let mut counts = Counts::default();
+
+        for line_cov in file_cov.line_coverages() {
+            if let Some(count) = line_cov.count {
+                if filter(line_cov.line_number) {
+                    counts.summary.line_total += 1;
+                    if count > 0 {
+                        counts.summary.line_executed += 1;
+                    }
+                }
+            }
+        }
+
+        for branch_cov in file_cov.branch_coverages() {
+            if let Some(taken) = branch_cov.taken {
+                if filter(branch_cov.line_number) {
+                    counts.summary.branch_total += 1;
+                    if taken {
+                        counts.summary.branch_executed += 1;
+                    }
+                }
+            }
+        }
+
+        for &(start, end) in functions.iter().filter(|&&(start, _)| filter(start)) {
+            let mut executable = false;
+            let mut executed = false;
+
+            for line_cov in file_cov.line_coverages() {
+                if line_cov.line_number >= start && line_cov.line_number <= end {
+                    if let Some(count) = line_cov.count {
+                        executable = true;
+                        executed |= count > 0;
+                    }
+                }
+            }
+
+            if executable {
+                counts.function_total += 1;
+            }
+            if executed {
+                counts.function_executed += 1;
+            }
+        }
+
+        counts
+    }
+}
+
+/// read the source file from the provider and parse it
+fn parse_source(provider: &dyn SourceProvider, path: &Path) -> Result<SourceCode, Error> {
+    let content = provider.read_source(path)?;
+    let ast = syn::parse_file(&content).chain_err(|| format!("Failed to parse {:?}", path))?;
+    Ok(SourceCode { content, ast })
+}
+
+/// line ranges of functions in the source code
+fn function_spans(source: &SourceCode) -> Vec<(usize, usize)> {
+    let mut visitor = FunctionVisitor { spans: Vec::new() };
+    visitor.visit_file(&source.ast);
+    visitor.spans
+}
+
+struct FunctionVisitor {
+    spans: Vec<(usize, usize)>,
+}
+
+impl<'ast> Visit<'ast> for FunctionVisitor {
+    fn visit_item_fn(&mut self, item: &'ast ItemFn) {
+        self.spans.push((
+            item.sig.fn_token.span.start().line,
+            item.block.brace_token.span.end().line,
+        ));
+        syn::visit::visit_item_fn(self, item);
+    }
+
+    fn visit_impl_item_method(&mut self, item: &'ast ImplItemMethod) {
+        self.spans.push((
+            item.sig.fn_token.span.start().line,
+            item.block.brace_token.span.end().line,
+        ));
+        syn::visit::visit_impl_item_method(self, item);
+    }
+
+    fn visit_trait_item_method(&mut self, item: &'ast TraitItemMethod) {
+        if let Some(ref block) = item.default {
+            self.spans.push((
+                item.sig.fn_token.span.start().line,
+                block.brace_token.span.end().line,
+            ));
+        }
+        syn::visit::visit_trait_item_method(self, item);
+    }
+}
+
+struct ModuleMap<'a> {
+    provider: &'a dyn SourceProvider,
+    files: HashMap<PathBuf, FileModules>,
+}
+
+/// modules defined in a single file
+struct FileModules {
+    /// the first entry is the module of the file itself
+    modules: Vec<ModuleRange>,
+    functions: Vec<(usize, usize)>,
+}
+
+struct ModuleRange {
+    path: Vec<String>,
+    start: usize,
+    end: usize,
+}
+
+impl FileModules {
+    /// index of the innermost module which contains the line
+    fn module_index(&self, line: usize) -> usize {
+        self.modules
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.start <= line && line <= m.end)
+            .max_by_key(|(_, m)| m.start)
+            .map_or(0, |(idx, _)| idx)
+    }
+}
+
+impl<'a> ModuleMap<'a> {
+    fn load_file(&mut self, file: &Path, mod_dir: &Path, path: Vec<String>) -> Result<(), Error> {
+        if self.files.contains_key(file) {
+            return Ok(());
+        }
+
+        let source = parse_source(self.provider, file)?;
+
+        let mut modules = vec![ModuleRange {
+            path: path.clone(),
+            start: 1,
+            end: usize::MAX,
+        }];
+        let mut children = Vec::new();
+        collect_modules(
+            &source.ast.items,
+            file,
+            mod_dir,
+            &path,
+            &mut modules,
+            &mut children,
+        );
+
+        self.files.insert(
+            file.to_owned(),
+            FileModules {
+                modules,
+                functions: function_spans(&source),
+            },
+        );
+
+        // the first candidate which exists is loaded
+        for (candidates, child_path) in children {
+            for child_file in candidates {
+                let child_dir = match child_file.file_name() {
+                    Some(f) if f == "mod.rs" => child_file.parent().unwrap().to_owned(),
+                    _ => child_file.with_extension(""),
+                };
+
+                if let Err(e) = self.load_file(&child_file, &child_dir, child_path.clone()) {
+                    if let ErrorKind::SourceFileNotFound(_) = *e.kind() {
+                        continue;
+                    }
+                    debugln!("Failed to load module file {:?}: {}", child_file, e);
+                }
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// collect inline modules and the files of external modules
+fn collect_modules(
+    items: &[Item],
+    file: &Path,
+    mod_dir: &Path,
+    path: &[String],
+    modules: &mut Vec<ModuleRange>,
+    children: &mut Vec<(Vec<PathBuf>, Vec<String>)>,
+) {
+    for item in items {
+        let item = match item {
+            Item::Mod(item) => item,
+            _ => continue,
+        };
+
+        let name = item.ident.to_string();
+        let mut child_path = path.to_vec();
+        child_path.push(name.clone());
+
+        match item.content {
+            Some((ref brace, ref items)) => {
+                modules.push(ModuleRange {
+                    path: child_path.clone(),
+                    start: brace.span.start().line,
+                    end: brace.span.end().line,
+                });
+                collect_modules(
+                    items,
+                    file,
+                    &mod_dir.join(&name),
+                    &child_path,
+                    modules,
+                    children,
+                );
+            }
+            None => children.push((module_files(item, file, mod_dir, &name), child_path)),
+        }
+    }
+}
+
+/// candidates of the file path of `mod name;` declaration
+fn module_files(item: &ItemMod, file: &Path, mod_dir: &Path, name: &str) -> Vec<PathBuf> {
+    for attr in &item.attrs {
+        if !attr.path.is_ident("path") {
+            continue;
+        }
+
+        if let Ok(Meta::NameValue(nv)) = attr.parse_meta() {
+            if let Lit::Str(s) = nv.lit {
+                let dir = file.parent().unwrap_or_else(|| Path::new(""));
+                return vec![normalize_path(&dir.join(s.value()))];
+            }
+        }
+    }
+
+    vec![
+        normalize_path(&mod_dir.join(format!("{}.rs", name))),
+        normalize_path(&mod_dir.join(name).join("mod.rs")),
+    ]
+}
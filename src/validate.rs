@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use crate::coverage::{
+    duplicate_branches, duplicate_lines, line_entries, normalize_path, DeclaredTotals,
+    FileCoverage, PackageCoverage, TotalCoverage,
+};
+use crate::source::{FsSourceProvider, SourceProvider};
+
+/// Inconsistency found in the coverage information
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ValidationIssue {
+    /// the file appears in multiple records
+    DuplicateFile { records: usize },
+    /// the line appears multiple times in a record
+    DuplicateLine { line_number: usize, entries: usize },
+    /// the branches of the block appear as many times as the duplicate line
+    DuplicateBranch {
+        line_number: usize,
+        block_number: Option<usize>,
+        entries: usize,
+    },
+    /// the line is past the end of the source file
+    LineOutOfRange {
+        line_number: usize,
+        line_count: usize,
+    },
+    /// the branch is past the end of the source file
+    BranchOutOfRange {
+        line_number: usize,
+        line_count: usize,
+    },
+    /// the branch is taken although the line was never executed
+    BranchTakenOnUnexecutedLine { line_number: usize },
+    /// declared number of executable lines (`LF`) differs from line records
+    LineTotalMismatch { declared: usize, actual: usize },
+    /// declared number of executed lines (`LH`) differs from line records
+    LineExecutedMismatch { declared: usize, actual: usize },
+    /// declared number of branches (`BRF`) differs from branch records
+    BranchTotalMismatch { declared: usize, actual: usize },
+    /// declared number of taken branches (`BRH`) differs from branch records
+    BranchExecutedMismatch { declared: usize, actual: usize },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationIssue::DuplicateFile { records } => {
+                write!(f, "file appears in {} records", records)
+            }
+            ValidationIssue::DuplicateLine {
+                line_number,
+                entries,
+            } => write!(f, "line {} appears {} times", line_number, entries),
+            ValidationIssue::DuplicateBranch {
+                line_number,
+                block_number,
+                entries,
+            } => write!(
+                f,
+                "branches of block {} at line {} appear {} times",
+                block_number.unwrap_or(0),
+                line_number,
+                entries
+            ),
+            ValidationIssue::LineOutOfRange {
+                line_number,
+                line_count,
+            } => write!(
+                f,
+                "line {} is out of range (file has {} lines)",
+                line_number, line_count
+            ),
+            ValidationIssue::BranchOutOfRange {
+                line_number,
+                line_count,
+            } => write!(
+                f,
+                "branch at line {} is out of range (file has {} lines)",
+                line_number, line_count
+            ),
+            ValidationIssue::BranchTakenOnUnexecutedLine { line_number } => write!(
+                f,
+                "branch is taken at line {} which was never executed",
+                line_number
+            ),
+            ValidationIssue::LineTotalMismatch { declared, actual } => {
+                write!(f, "LF is {} but {} lines are recorded", declared, actual)
+            }
+            ValidationIssue::LineExecutedMismatch { declared, actual } => {
+                write!(f, "LH is {} but {} lines are executed", declared, actual)
+            }
+            ValidationIssue::BranchTotalMismatch { declared, actual } => write!(
+                f,
+                "BRF is {} but {} branches are recorded",
+                declared, actual
+            ),
+            ValidationIssue::BranchExecutedMismatch { declared, actual } => {
+                write!(f, "BRH is {} but {} branches are taken", declared, actual)
+            }
+        }
+    }
+}
+
+/// Inconsistency found in the coverage of a file
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValidationFinding {
+    pub path: PathBuf,
+    pub issue: ValidationIssue,
+}
+
+impl fmt::Display for ValidationFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.issue)
+    }
+}
+
+impl PackageCoverage {
+    /// check the consistency of the coverage information
+    ///
+    /// Source files are read to check the line ranges. If the source file cannot be read,
+    /// the range check is skipped for that file.
+    pub fn validate(&self) -> Vec<ValidationFinding> {
+        self.validate_with(&FsSourceProvider::new())
+    }
+
+    /// same as `validate()`, but read the source files from the provider
+    pub fn validate_with(&self, provider: &dyn SourceProvider) -> Vec<ValidationFinding> {
+        let mut findings = Vec::new();
+
+        let mut records: HashMap<PathBuf, usize> = HashMap::new();
+        for file_cov in &self.file_coverages {
+            *records.entry(normalize_path(file_cov.path())).or_insert(0) += 1;
+        }
+
+        let mut reported = Vec::new();
+        for file_cov in &self.file_coverages {
+            let path = normalize_path(file_cov.path());
+            let count = records[&path];
+            if count > 1 && !reported.contains(&path) {
+                findings.push(ValidationFinding {
+                    path: file_cov.path().to_owned(),
+                    issue: ValidationIssue::DuplicateFile { records: count },
+                });
+                reported.push(path);
+            }
+
+            findings.extend(validate_file(file_cov, provider).into_iter().map(|issue| {
+                ValidationFinding {
+                    path: file_cov.path().to_owned(),
+                    issue,
+                }
+            }));
+        }
+
+        findings
+    }
+
+    /// repair the inconsistencies found by `validate()`
+    ///
+    /// Duplicate records of the same file, duplicate lines and their duplicate branches
+    /// are merged, and the lines and branches past the end of the source file are
+    /// removed. Declared totals are cleared if they differ from the records. Branches
+    /// taken on unexecuted lines are left as is because it cannot be decided which is
+    /// correct. Returns the changes actually made.
+    pub fn repair(&mut self) -> Vec<ValidationFinding> {
+        self.repair_with(&FsSourceProvider::new())
+    }
+
+    /// same as `repair()`, but read the source files from the provider
+    pub fn repair_with(&mut self, provider: &dyn SourceProvider) -> Vec<ValidationFinding> {
+        let mut repaired = Vec::new();
+
+        let mut records: Vec<(PathBuf, &Path, usize)> = Vec::new();
+        for file_cov in &self.file_coverages {
+            let path = normalize_path(file_cov.path());
+            match records.iter_mut().find(|(p, _, _)| *p == path) {
+                Some(record) => record.2 += 1,
+                None => records.push((path, file_cov.path(), 1)),
+            }
+        }
+        for &(_, path, records) in records.iter().filter(|r| r.2 > 1) {
+            repaired.push(ValidationFinding {
+                path: path.to_owned(),
+                issue: ValidationIssue::DuplicateFile { records },
+            });
+        }
+
+        if !repaired.is_empty() {
+            let file_coverages = mem::take(&mut self.file_coverages);
+            let mut merged = PackageCoverage::new(vec![]);
+            merged.merge(PackageCoverage::new(file_coverages));
+            self.file_coverages = merged.file_coverages;
+        }
+
+        for file_cov in &mut self.file_coverages {
+            let issues = repair_file(file_cov, provider);
+            repaired.extend(issues.into_iter().map(|issue| ValidationFinding {
+                path: file_cov.path().to_owned(),
+                issue,
+            }));
+        }
+
+        repaired
+    }
+}
+
+fn validate_file(file_cov: &FileCoverage, provider: &dyn SourceProvider) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    let line_entries = line_entries(file_cov);
+    let duplicates = duplicate_lines(&line_entries);
+    issues.extend(duplicates.iter().map(|&(line_number, entries)| {
+        ValidationIssue::DuplicateLine {
+            line_number,
+            entries,
+        }
+    }));
+    issues.extend(duplicate_branches(file_cov, &duplicates).into_iter().map(
+        |(line_number, block_number, entries)| ValidationIssue::DuplicateBranch {
+            line_number,
+            block_number,
+            entries,
+        },
+    ));
+
+    if let Some(line_count) = source_line_count(file_cov.path(), provider) {
+        for line_cov in file_cov.line_coverages() {
+            if line_cov.line_number > line_count {
+                issues.push(ValidationIssue::LineOutOfRange {
+                    line_number: line_cov.line_number,
+                    line_count,
+                });
+            }
+        }
+
+        for branch_cov in file_cov.branch_coverages() {
+            if branch_cov.line_number > line_count {
+                issues.push(ValidationIssue::BranchOutOfRange {
+                    line_number: branch_cov.line_number,
+                    line_count,
+                });
+            }
+        }
+    }
+
+    let mut taken_lines = Vec::new();
+    for branch_cov in file_cov.branch_coverages() {
+        let line_number = branch_cov.line_number;
+        if branch_cov.taken == Some(true)
+            && line_entries.contains_key(&line_number)
+            && !taken_lines.contains(&line_number)
+            && file_cov
+                .line_coverages()
+                .iter()
+                .filter(|v| v.line_number == line_number)
+                .all(|v| v.count == Some(0))
+        {
+            issues.push(ValidationIssue::BranchTakenOnUnexecutedLine { line_number });
+            taken_lines.push(line_number);
+        }
+    }
+
+    issues.extend(check_declared_totals(file_cov));
+
+    issues
+}
+
+/// check the declared totals against the records
+fn check_declared_totals(file_cov: &FileCoverage) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    let declared = file_cov.declared_totals();
+    check_declared(
+        &mut issues,
+        declared.line_total,
+        file_cov.line_total(),
+        |d, a| ValidationIssue::LineTotalMismatch {
+            declared: d,
+            actual: a,
+        },
+    );
+    check_declared(
+        &mut issues,
+        declared.line_executed,
+        file_cov.line_executed(),
+        |d, a| ValidationIssue::LineExecutedMismatch {
+            declared: d,
+            actual: a,
+        },
+    );
+    check_declared(
+        &mut issues,
+        declared.branch_total,
+        file_cov.branch_total(),
+        |d, a| ValidationIssue::BranchTotalMismatch {
+            declared: d,
+            actual: a,
+        },
+    );
+    check_declared(
+        &mut issues,
+        declared.branch_executed,
+        file_cov.branch_executed(),
+        |d, a| ValidationIssue::BranchExecutedMismatch {
+            declared: d,
+            actual: a,
+        },
+    );
+
+    issues
+}
+
+fn check_declared(
+    issues: &mut Vec<ValidationIssue>,
+    declared: Option<usize>,
+    actual: usize,
+    issue: fn(usize, usize) -> ValidationIssue,
+) {
+    if let Some(declared) = declared {
+        if declared != actual {
+            issues.push(issue(declared, actual));
+        }
+    }
+}
+
+/// repair the record of the file, and return the changes
+fn repair_file(file_cov: &mut FileCoverage, provider: &dyn SourceProvider) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    let (duplicate_lines, duplicate_branches) = file_cov.combine_duplicates();
+    issues.extend(
+        duplicate_branches
+            .into_iter()
+            .map(
+                |(line_number, block_number, entries)| ValidationIssue::DuplicateBranch {
+                    line_number,
+                    block_number,
+                    entries,
+                },
+            ),
+    );
+    issues.extend(duplicate_lines.into_iter().map(|(line_number, entries)| {
+        ValidationIssue::DuplicateLine {
+            line_number,
+            entries,
+        }
+    }));
+
+    if let Some(line_count) = source_line_count(file_cov.path(), provider) {
+        for line_cov in file_cov.line_coverages() {
+            if line_cov.line_number > line_count {
+                issues.push(ValidationIssue::LineOutOfRange {
+                    line_number: line_cov.line_number,
+                    line_count,
+                });
+            }
+        }
+        for branch_cov in file_cov.branch_coverages() {
+            if branch_cov.line_number > line_count {
+                issues.push(ValidationIssue::BranchOutOfRange {
+                    line_number: branch_cov.line_number,
+                    line_count,
+                });
+            }
+        }
+
+        file_cov
+            .line_coverages
+            .retain(|v| v.line_number <= line_count);
+        file_cov
+            .branch_coverages
+            .retain(|v| v.line_number <= line_count);
+    }
+
+    let mismatches = check_declared_totals(file_cov);
+    if !mismatches.is_empty() {
+        file_cov.set_declared_totals(DeclaredTotals::default());
+        issues.extend(mismatches);
+    }
+
+    issues
+}
+
+fn source_line_count(path: &Path, provider: &dyn SourceProvider) -> Option<usize> {
+    provider.read_source(path).ok().map(|s| s.lines().count())
+}
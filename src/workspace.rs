@@ -0,0 +1,211 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::coverage::{
+    normalize_path, CoverageSummary, FileCoverage, PackageCoverage, TotalCoverage,
+};
+use crate::error::*;
+
+/// A member crate of the cargo workspace
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkspaceMember {
+    name: String,
+    root: PathBuf,
+}
+
+impl WorkspaceMember {
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(name: S, root: P) -> Self {
+        Self {
+            name: name.into(),
+            root: absolute_path(&root.into()),
+        }
+    }
+
+    /// package name of the crate
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// directory which contains `Cargo.toml` of the crate, as an absolute path
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+/// Cargo workspace used to group the coverages by member crates
+#[derive(Clone, Debug, PartialEq)]
+pub struct Workspace {
+    members: Vec<WorkspaceMember>,
+}
+
+impl Workspace {
+    pub fn new(members: Vec<WorkspaceMember>) -> Self {
+        Self { members }
+    }
+
+    /// detect the workspace members using `cargo metadata` command
+    ///
+    /// `dir` is the directory which contains `Cargo.toml` of the workspace (or
+    /// package).
+    pub fn from_cargo_metadata(dir: &Path) -> Result<Self, Error> {
+        let output = Command::new("cargo")
+            .args(["metadata", "--no-deps", "--format-version", "1"])
+            .current_dir(dir)
+            .output()
+            .chain_err(|| "Failed to execute `cargo metadata`")?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "`cargo metadata` failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )
+            .into());
+        }
+
+        let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .chain_err(|| "Failed to parse the output of `cargo metadata`")?;
+
+        let mut members = Vec::new();
+        let packages = metadata["packages"].as_array().map_or(&[][..], |v| v);
+        for package in packages {
+            let name = package["name"].as_str();
+            let manifest_path = package["manifest_path"].as_str().map(Path::new);
+
+            if let (Some(name), Some(manifest_path)) = (name, manifest_path) {
+                if let Some(root) = manifest_path.parent() {
+                    members.push(WorkspaceMember::new(name, root));
+                }
+            }
+        }
+
+        Ok(Self::new(members))
+    }
+
+    pub fn members(&self) -> &[WorkspaceMember] {
+        &self.members
+    }
+
+    /// find the member crate which contains the file
+    ///
+    /// If crates are nested, the innermost crate is returned.
+    pub fn find_member(&self, path: &Path) -> Option<&WorkspaceMember> {
+        self.find_member_index(path).map(|idx| &self.members[idx])
+    }
+
+    fn find_member_index(&self, path: &Path) -> Option<usize> {
+        let path = absolute_path(path);
+        self.members
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| path.starts_with(&m.root))
+            .max_by_key(|(_, m)| m.root.components().count())
+            .map(|(idx, _)| idx)
+    }
+
+    /// group the file coverages by member crates
+    pub fn group(&self, data: PackageCoverage) -> WorkspaceCoverage {
+        let mut package_coverages: Vec<PackageCoverage> = self
+            .members
+            .iter()
+            .map(|m| PackageCoverage::with_test_name(m.name(), vec![]))
+            .collect();
+        let mut others = Vec::new();
+
+        for file_cov in data.file_coverages {
+            match self.find_member_index(file_cov.path()) {
+                Some(idx) => package_coverages[idx].file_coverages.push(file_cov),
+                None => {
+                    debugln!(
+                        "{:?} does not belong to any workspace member",
+                        file_cov.path()
+                    );
+                    others.push(file_cov);
+                }
+            }
+        }
+
+        WorkspaceCoverage {
+            package_coverages,
+            others,
+        }
+    }
+}
+
+/// Coverage information grouped by the workspace members
+#[derive(Debug, PartialEq)]
+pub struct WorkspaceCoverage {
+    #[doc(hidden)]
+    pub package_coverages: Vec<PackageCoverage>,
+    #[doc(hidden)]
+    pub others: Vec<FileCoverage>,
+}
+
+impl WorkspaceCoverage {
+    /// coverages for each member crate. The test name is set to the crate name.
+    pub fn package_coverages(&self) -> &[PackageCoverage] {
+        &self.package_coverages
+    }
+
+    /// coverages for the files which do not belong to any member crate
+    pub fn others(&self) -> &[FileCoverage] {
+        &self.others
+    }
+
+    /// coverage summaries for each member crate
+    pub fn summaries(&self) -> Vec<(&str, CoverageSummary)> {
+        self.package_coverages
+            .iter()
+            .map(|v| (v.name(), CoverageSummary::new(v)))
+            .collect()
+    }
+}
+
+impl TotalCoverage for WorkspaceCoverage {
+    fn line_executed(&self) -> usize {
+        self.package_coverages
+            .iter()
+            .map(|v| v.line_executed())
+            .chain(self.others.iter().map(|v| v.line_executed()))
+            .sum()
+    }
+
+    fn line_total(&self) -> usize {
+        self.package_coverages
+            .iter()
+            .map(|v| v.line_total())
+            .chain(self.others.iter().map(|v| v.line_total()))
+            .sum()
+    }
+
+    fn branch_executed(&self) -> usize {
+        self.package_coverages
+            .iter()
+            .map(|v| v.branch_executed())
+            .chain(self.others.iter().map(|v| v.branch_executed()))
+            .sum()
+    }
+
+    fn branch_total(&self) -> usize {
+        self.package_coverages
+            .iter()
+            .map(|v| v.branch_total())
+            .chain(self.others.iter().map(|v| v.branch_total()))
+            .sum()
+    }
+}
+
+/// absolute path with the symbolic links resolved
+///
+/// Paths which do not exist are made absolute from the current directory instead, so
+/// that relative coverage paths can be compared with the member roots.
+fn absolute_path(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+
+    match env::current_dir() {
+        Ok(dir) => normalize_path(&dir.join(path)),
+        Err(_) => normalize_path(path),
+    }
+}
@@ -0,0 +1,122 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_covfix::rule::{self, Rule, SourceCode};
+use rust_covfix::{
+    parser::LcovParser, CoverageFixer, CoverageReader, CoverageWriter, FileCoverage, FixCache,
+};
+
+fn fix(ws: &WorkSpace, fixer: &CoverageFixer) -> (String, bool) {
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    let mut output = Vec::new();
+    parser.write(&coverage, &mut output).unwrap();
+    (String::from_utf8(output).unwrap(), report.files[0].cached)
+}
+
+#[test]
+fn reuse() {
+    let ws = WorkSpace::from_template("tests/cache");
+    let expected = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+
+    let mut fixer = CoverageFixer::new();
+    fixer.set_cache(FixCache::new(ws.path().join("cache")));
+
+    assert_eq!(fix(&ws, &fixer), (expected.clone(), false));
+    assert_eq!(fs::read_dir(ws.path().join("cache")).unwrap().count(), 1);
+    assert_eq!(fix(&ws, &fixer), (expected, true));
+
+    // modified file is parsed again
+    let source_file = ws.path().join("src/lib.rs");
+    let content = fs::read_to_string(&source_file).unwrap();
+    fs::write(&source_file, content.replace("a + b", "b + a")).unwrap();
+    assert!(!fix(&ws, &fixer).1);
+    assert!(fix(&ws, &fixer).1);
+
+    // rule configuration is a part of the key
+    let mut fixer = CoverageFixer::with_rules(vec![rule::from_str("close").unwrap()]);
+    fixer.set_cache(FixCache::new(ws.path().join("cache")));
+    assert!(!fix(&ws, &fixer).1);
+
+    FixCache::new(ws.path().join("cache")).clear().unwrap();
+    assert!(!ws.path().join("cache").exists());
+}
+
+#[test]
+fn corrupted_entry() {
+    let ws = WorkSpace::from_template("tests/cache");
+    let expected = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+
+    let mut fixer = CoverageFixer::new();
+    fixer.set_cache(FixCache::new(ws.path().join("cache")));
+    fix(&ws, &fixer);
+
+    for entry in fs::read_dir(ws.path().join("cache")).unwrap() {
+        fs::write(
+            entry.unwrap().path(),
+            "rust-covfix-cache 1\nrules 7\nrule 0\n",
+        )
+        .unwrap();
+    }
+
+    assert_eq!(fix(&ws, &fixer), (expected.clone(), false));
+    assert_eq!(fix(&ws, &fixer), (expected, true));
+}
+
+/// rule which does not support caching
+struct NopRule;
+
+impl Rule for NopRule {
+    fn fix_file_coverage(&self, _source: &SourceCode, _file_cov: &mut FileCoverage) {}
+}
+
+#[test]
+fn uncacheable_rule() {
+    let ws = WorkSpace::from_template("tests/cache");
+
+    let rules: Vec<Box<dyn Rule>> = vec![rule::from_str("close").unwrap(), Box::new(NopRule)];
+    let mut fixer = CoverageFixer::with_rules(rules);
+    fixer.set_cache(FixCache::new(ws.path().join("cache")));
+
+    assert!(!fix(&ws, &fixer).1);
+    assert!(!fix(&ws, &fixer).1);
+    assert!(!ws.path().join("cache").exists());
+}
+
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/cache");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov_out.info");
+    let expected_content = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+
+    for _ in 0..2 {
+        let status = Command::new(&exe)
+            .arg("--root")
+            .arg(ws.path())
+            .arg("--cache-dir")
+            .arg(ws.path().join("cache"))
+            .arg("-o")
+            .arg(&output)
+            .arg(ws.path().join("lcov.info"))
+            .status()
+            .unwrap();
+
+        assert!(status.success());
+        assert_eq!(fs::read_to_string(&output).unwrap(), expected_content);
+    }
+
+    assert_eq!(fs::read_dir(ws.path().join("cache")).unwrap().count(), 1);
+}
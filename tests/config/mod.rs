@@ -0,0 +1,249 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use rust_covfix::rule::RuleRegistry;
+use rust_covfix::{Config, Thresholds};
+
+fn run(root: &Path, args: &[&str]) -> Output {
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    Command::new(exe)
+        .arg("--root")
+        .arg(root)
+        .args(args)
+        .arg(root.join("lcov.info"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn parse() {
+    let content = r#"
+rules = "default,-loop"
+include = ["src/**"]
+exclude = "src/generated.rs"
+repair = true
+on_error = "skip-file"
+cache_dir = "target/covfix-cache"
+
+[remap]
+"/build" = "."
+
+[rule.assert]
+macros = ["+ensure", "check"]
+debug_assert = false
+
+[threshold]
+line = 80
+branch = 62.5
+"#;
+
+    let config = Config::from_toml(content, Path::new("/project")).unwrap();
+    assert_eq!(config.rules.as_deref(), Some("default,-loop"));
+    assert_eq!(
+        config.rule_options,
+        vec!["assert.debug_assert=false", "assert.macros=+ensure,check"]
+    );
+    assert_eq!(config.includes, vec!["src/**"]);
+    assert_eq!(config.excludes, vec!["src/generated.rs"]);
+    assert_eq!(config.remaps, vec![("/build".to_owned(), ".".to_owned())]);
+    assert_eq!(config.repair, Some(true));
+    assert_eq!(config.relative_outside_root, None);
+    assert_eq!(config.on_error.as_deref(), Some("skip-file"));
+    assert_eq!(
+        config.cache_dir,
+        Some(Path::new("/project").join("target/covfix-cache"))
+    );
+    assert_eq!(
+        config.thresholds,
+        Thresholds {
+            line: Some(80.0),
+            branch: Some(62.5)
+        }
+    );
+
+    let rules = config.create_rules(&RuleRegistry::new()).unwrap();
+    let names: Vec<&str> = rules.iter().map(|r| r.name()).collect();
+    assert!(!names.contains(&"loop"));
+    let assert_rule = rules.iter().find(|r| r.name() == "assert").unwrap();
+    assert_eq!(
+        assert_rule.cache_key().unwrap(),
+        "assert:assert,assert_eq,assert_ne,ensure,check:false"
+    );
+
+    let content = r#"remap = [{ from = "/build/vendor", to = "third_party" }, { from = "/build", to = "." }]"#;
+    let config = Config::from_toml(content, Path::new("")).unwrap();
+    assert_eq!(
+        config.remaps,
+        vec![
+            ("/build/vendor".to_owned(), "third_party".to_owned()),
+            ("/build".to_owned(), ".".to_owned())
+        ]
+    );
+    assert!(Config::from_toml("remap = [{ from = \"/build\" }]", Path::new("")).is_err());
+
+    assert!(Config::from_toml("repair = 1", Path::new("")).is_err());
+    assert!(Config::from_toml("[threshold]\nline = 120", Path::new("")).is_err());
+    assert!(Config::from_toml("rules = [", Path::new("")).is_err());
+}
+
+#[test]
+fn discover() {
+    let ws = WorkSpace::from_template("tests/config");
+
+    let config = Config::discover(ws.path()).unwrap().unwrap();
+    assert_eq!(config.path(), Some(ws.path().join("covfix.toml").as_path()));
+    assert_eq!(config.excludes, vec!["src/generated.rs"]);
+
+    // Cargo.toml without metadata is ignored
+    fs::remove_file(ws.path().join("covfix.toml")).unwrap();
+    fs::write(ws.path().join("Cargo.toml"), "[package]\nname = \"foo\"\n").unwrap();
+    assert_eq!(Config::discover(ws.path()).unwrap(), None);
+
+    fs::write(
+        ws.path().join("Cargo.toml"),
+        "[package]\nname = \"foo\"\n\n[package.metadata.covfix]\nexclude = [\"tests/*\"]\n\n\
+         [workspace.metadata.covfix]\nexclude = [\"src/generated.rs\"]\n",
+    )
+    .unwrap();
+    let config = Config::discover(ws.path()).unwrap().unwrap();
+    assert_eq!(config.path(), Some(ws.path().join("Cargo.toml").as_path()));
+    assert_eq!(config.excludes, vec!["src/generated.rs"]);
+
+    let config = Config::from_file(&ws.path().join("Cargo.toml")).unwrap();
+    assert_eq!(config.excludes, vec!["src/generated.rs"]);
+}
+
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/config");
+    let output = ws.path().join("lcov_out.info");
+
+    let result = run(ws.path(), &["-o", output.to_str().unwrap()]);
+    assert!(result.status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+    let content = fs::read_to_string(&output).unwrap();
+    assert_eq!(content, expected_content);
+
+    // options from the command line take precedence
+    let result = run(ws.path(), &["--fail-under-branches", "75"]);
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("Branch coverage 50.00% is below the threshold 75.00%"));
+}
+
+#[test]
+fn cli_flags() {
+    let ws = WorkSpace::from_template("tests/config");
+    let config = fs::read_to_string(ws.path().join("covfix.toml")).unwrap();
+    fs::write(
+        ws.path().join("covfix.toml"),
+        format!("repair = true\n{}", config),
+    )
+    .unwrap();
+    fs::write(
+        ws.path().join("lcov.info"),
+        "TN:\nSF:/build/src/lib.rs\nDA:1,1\nDA:1,1\nend_of_record\n",
+    )
+    .unwrap();
+
+    let result = run(ws.path(), &["--no-fix", "--fail-under-lines", "0"]);
+    assert!(result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("Repaired"));
+
+    // the configuration is overridden from the command line
+    let result = run(
+        ws.path(),
+        &["--no-fix", "--no-repair", "--fail-under-lines", "0"],
+    );
+    assert!(result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(!stderr.contains("Repaired"));
+}
+
+#[test]
+fn cli_cargo_metadata() {
+    let ws = WorkSpace::from_template("tests/config");
+    fs::rename(ws.path().join("covfix.toml"), ws.path().join("config.toml")).unwrap();
+    fs::write(
+        ws.path().join("Cargo.toml"),
+        "[package]\nname = \"foo\"\n\n[package.metadata.covfix]\n\
+         exclude = [\"src/generated.rs\"]\nremap = { \"/build\" = \".\" }\n\n\
+         [package.metadata.covfix.threshold]\nline = 90\n",
+    )
+    .unwrap();
+
+    // coverage file is written even if the coverage is below the threshold
+    let output = ws.path().join("lcov_out.info");
+    let result = run(ws.path(), &["-o", output.to_str().unwrap()]);
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("Line coverage 83.33% is below the threshold 90.00%"));
+    assert!(output.is_file());
+
+    // paths are not remapped without the configuration
+    let result = run(ws.path(), &["--no-config"]);
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("\"/build/src/lib.rs\" not found"));
+
+    let result = run(ws.path(), &["--config", "config.toml"]);
+    assert!(!result.status.success());
+
+    let config = ws.path().join("config.toml");
+    let result = run(ws.path(), &["--config", config.to_str().unwrap()]);
+    assert!(result.status.success());
+}
+
+#[test]
+fn plugins() {
+    let content = r#"
+[plugin.internal]
+command = "scripts/plugin.sh"
+args = ["--strict"]
+batch = true
+default = false
+
+[plugin.vendor]
+command = "covfix-vendor"
+reason = "vendored code"
+"#;
+
+    let config = Config::from_toml(content, Path::new("/project")).unwrap();
+    assert_eq!(config.plugins.len(), 2);
+    assert_eq!(config.plugins[0].name, "internal");
+    assert_eq!(
+        config.plugins[0].command,
+        Path::new("/project").join("scripts/plugin.sh")
+    );
+    assert_eq!(config.plugins[0].args, vec!["--strict"]);
+    assert!(config.plugins[0].batch);
+    assert!(!config.plugins[0].default);
+    assert_eq!(config.plugins[1].command, Path::new("covfix-vendor"));
+    assert_eq!(config.plugins[1].reason.as_deref(), Some("vendored code"));
+    assert!(config.plugins[1].default);
+
+    #[cfg(feature = "plugin")]
+    {
+        let mut registry = RuleRegistry::new();
+        for plugin in &config.plugins {
+            plugin.register(&mut registry).unwrap();
+        }
+        assert!(!registry.get("internal").unwrap().is_default());
+        assert!(registry.get("vendor").unwrap().is_default());
+        assert_eq!(registry.create("vendor").unwrap().reason(), "vendored code");
+        assert!(config.plugins[0].register(&mut registry).is_err());
+    }
+
+    assert!(Config::from_toml("[plugin.internal]\nbatch = true", Path::new("")).is_err());
+}
@@ -0,0 +1,10 @@
+pub fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+pub fn check(v: i32) -> i32 {
+    if v < 0 {
+        panic!("negative value");
+    }
+    v
+}
@@ -0,0 +1,121 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_covfix::{parser::LcovParser, CoverageDiff, CoverageReader, CoverageSummary};
+
+#[test]
+fn diff() {
+    let ws = WorkSpace::from_template("tests/diff");
+
+    let parser = LcovParser::new(ws.path());
+    let old = parser
+        .read_from_file(&ws.path().join("lcov_base.info"))
+        .unwrap();
+    let new = parser
+        .read_from_file(&ws.path().join("lcov_new.info"))
+        .unwrap();
+
+    let diff = CoverageDiff::new(&old, &new);
+
+    assert_eq!(
+        diff.new,
+        CoverageSummary {
+            line_executed: 4,
+            line_total: 6,
+            branch_executed: 2,
+            branch_total: 2,
+        }
+    );
+    assert_eq!(diff.file_diffs.len(), 3);
+
+    let lib = &diff.file_diffs[0];
+    assert_eq!(lib.path, ws.path().join("src/lib.rs"));
+    assert_eq!(lib.newly_covered_lines, vec![4]);
+    assert_eq!(lib.newly_uncovered_lines, vec![5]);
+    assert_eq!(lib.line_executed_delta(), 0);
+    assert_eq!(lib.line_total_delta(), 1);
+    assert_eq!(lib.branch_executed_delta(), 1);
+    assert_eq!(lib.branch_total_delta(), 0);
+
+    let added = &diff.file_diffs[1];
+    assert_eq!(added.path, ws.path().join("src/new.rs"));
+    assert_eq!(added.old, None);
+    assert_eq!(added.newly_covered_lines, vec![1]);
+
+    let removed = &diff.file_diffs[2];
+    assert_eq!(removed.path, ws.path().join("src/old.rs"));
+    assert_eq!(removed.new, None);
+    assert_eq!(removed.line_executed_delta(), -1);
+    assert_eq!(removed.old.unwrap().line_percent(), Some(100.0));
+    assert_eq!(removed.old.unwrap().branch_percent(), None);
+}
+
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/diff");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let result = Command::new(&exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--no-fix")
+        .arg("--diff")
+        .arg(ws.path().join("lcov_base.info"))
+        .arg(ws.path().join("lcov_new.info"))
+        .output()
+        .unwrap();
+
+    assert!(result.status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("diff.txt")).unwrap();
+    assert_eq!(String::from_utf8(result.stdout).unwrap(), expected_content);
+
+    if cfg!(feature = "json") {
+        let result = Command::new(&exe)
+            .arg("--root")
+            .arg(ws.path())
+            .arg("--no-fix")
+            .arg("--diff")
+            .arg(ws.path().join("lcov_base.info"))
+            .arg("--diff-format")
+            .arg("json")
+            .arg(ws.path().join("lcov_new.info"))
+            .output()
+            .unwrap();
+
+        assert!(result.status.success());
+
+        let json = String::from_utf8(result.stdout).unwrap();
+        assert!(json.starts_with(
+            r#"{"old":{"line_executed":4,"line_total":5,"branch_executed":1,"branch_total":2},"#
+        ));
+        assert!(json.contains(
+            r#"{"path":"src/new.rs","old":null,"new":{"line_executed":1,"line_total":1,"branch_executed":0,"branch_total":0},"newly_covered_lines":[1],"newly_uncovered_lines":[]}"#
+        ));
+    }
+
+    if cfg!(feature = "workspace") {
+        let result = Command::new(&exe)
+            .arg("--root")
+            .arg(ws.path())
+            .arg("--diff")
+            .arg(ws.path().join("lcov_base.info"))
+            .arg("--split-by-crate")
+            .arg(ws.path().join("coverage"))
+            .arg(ws.path().join("lcov_new.info"))
+            .output()
+            .unwrap();
+
+        assert!(!result.status.success());
+        assert!(!ws.path().join("coverage").exists());
+    }
+}
@@ -0,0 +1,207 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_covfix::rule::{self, Rule, SourceCode};
+use rust_covfix::{
+    parser::LcovParser, CoverageFixer, CoverageReader, FileCoverage, LineCoverage,
+    MemorySourceProvider, PackageCoverage,
+};
+
+#[test]
+fn provenance() {
+    let ws = WorkSpace::from_template("tests/explain");
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    // provenance is not recorded by default
+    let fixer = CoverageFixer::new();
+    let report = fixer.fix(&mut coverage).unwrap();
+    assert!(report.files[0].adjustments.is_empty());
+    assert!(report.files[0].removed_source.is_empty());
+    assert_eq!(report.lines_removed(), 6);
+
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+    let mut fixer = CoverageFixer::new();
+    fixer.set_explain(true);
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    let adjustments = &report.files[0].adjustments;
+    let spans: Vec<_> = adjustments
+        .iter()
+        .map(|a| (a.rule.as_str(), a.start_line, a.end_line))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            ("close", 9, 9),
+            ("close", 14, 14),
+            ("close", 16, 16),
+            ("derive", 1, 2),
+            ("unreachable", 13, 13),
+        ]
+    );
+
+    assert_eq!(adjustments[3].lines, vec![1, 2]);
+    assert_eq!(
+        adjustments[3].reason,
+        "derived implementations are generated by the compiler"
+    );
+
+    // source files are not read again
+    fs::remove_file(ws.path().join("src/lib.rs")).unwrap();
+
+    let mut text = Vec::new();
+    report.write_explanation(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("derive: lines 1-2"));
+    assert!(text.contains("unreachable!();"));
+    assert!(text.ends_with("Total: 6 lines and 0 branches removed\n"));
+}
+
+#[test]
+fn markers() {
+    let source = "pub fn f() {
+    log(); // cov:ignore-line
+    // cov:begin-ignore
+    a();
+    b();
+    // cov:end-ignore
+    c();
+}
+";
+    let mut provider = MemorySourceProvider::new();
+    provider.insert("/project/src/lib.rs", source);
+
+    let lines = [1, 2, 4, 5, 7, 8]
+        .iter()
+        .map(|&line_number| LineCoverage {
+            line_number,
+            count: Some(1),
+        })
+        .collect();
+    let mut coverage = PackageCoverage::new(vec![FileCoverage::new(
+        "/project/src/lib.rs",
+        lines,
+        vec![],
+    )]);
+
+    let mut fixer = CoverageFixer::with_rules(vec![rule::from_str("comment").unwrap()]);
+    fixer.set_source_provider(provider);
+    fixer.set_explain(true);
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    let adjustments: Vec<_> = report.files[0]
+        .adjustments
+        .iter()
+        .map(|a| (a.reason.as_str(), a.origin, a.lines.clone()))
+        .collect();
+    assert_eq!(
+        adjustments,
+        vec![
+            ("marked by `cov:ignore-line`", Some((2, 2)), vec![2]),
+            ("marked by `cov:begin-ignore`", Some((3, 6)), vec![4, 5]),
+        ]
+    );
+
+    let mut text = Vec::new();
+    report.write_explanation(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("comment: line 2 (marked by `cov:ignore-line` at line 2)"));
+    assert!(text.contains("comment: lines 4-5 (marked by `cov:begin-ignore` at lines 3-6)"));
+
+    #[cfg(feature = "json")]
+    {
+        let mut json = Vec::new();
+        report.write_explanation_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let adjustments = &json["files"][0]["adjustments"];
+        assert_eq!(adjustments[0]["origin"], serde_json::json!([2, 2]));
+        assert_eq!(adjustments[1]["origin"], serde_json::json!([3, 6]));
+    }
+}
+
+/// rule which removes all lines
+struct AllLinesRule;
+
+impl Rule for AllLinesRule {
+    fn name(&self) -> &str {
+        "all-lines"
+    }
+
+    fn fix_file_coverage(&self, _source: &SourceCode, file_cov: &mut FileCoverage) {
+        for line_cov in file_cov.line_range_mut(..) {
+            line_cov.count = None;
+        }
+    }
+}
+
+#[test]
+fn line_zero() {
+    let mut provider = MemorySourceProvider::new();
+    provider.insert("/project/src/lib.rs", "fn f() {}\n");
+
+    let lines = [0, 1]
+        .iter()
+        .map(|&line_number| LineCoverage {
+            line_number,
+            count: Some(1),
+        })
+        .collect();
+    let mut coverage = PackageCoverage::new(vec![FileCoverage::new(
+        "/project/src/lib.rs",
+        lines,
+        vec![],
+    )]);
+
+    let mut fixer = CoverageFixer::with_rules(vec![Box::new(AllLinesRule) as Box<dyn Rule>]);
+    fixer.set_source_provider(provider);
+    fixer.set_explain(true);
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(report.files[0].adjustments[0].lines, vec![0, 1]);
+    assert_eq!(report.files[0].removed_line(0), None);
+    assert_eq!(report.files[0].removed_line(1), Some("fn f() {}"));
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/explain");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let json_file = ws.path().join("explain.json");
+
+    let output = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--explain")
+        .arg("--explain-json")
+        .arg(&json_file)
+        .arg(ws.path().join("lcov.info"))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("close: line 9 (closing brackets are not executable)"));
+
+    let content = fs::read_to_string(json_file).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+    let adjustments = json["files"][0]["adjustments"].as_array().unwrap();
+    assert_eq!(adjustments.len(), 5);
+    assert_eq!(adjustments[4]["rule"], "unreachable");
+    assert_eq!(adjustments[4]["lines"], serde_json::json!([13]));
+    assert_eq!(adjustments[4]["origin"], serde_json::Value::Null);
+    assert_eq!(json["after"]["line_total"], 5);
+}
@@ -0,0 +1,16 @@
+#[derive(Clone, Debug)]
+pub struct Point {
+    x: i32,
+    y: i32,
+}
+
+pub fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+pub fn check(v: i32) -> i32 {
+    if v < 0 {
+        unreachable!();
+    }
+    v
+}
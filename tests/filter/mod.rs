@@ -0,0 +1,77 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_covfix::{parser::LcovParser, CoverageReader, PathFilter};
+
+#[test]
+fn retain_paths() {
+    let ws = WorkSpace::from_template("tests/filter");
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    let mut filter = PathFilter::new(ws.path());
+    filter.add_exclude("tests/*");
+    coverage.retain_paths(&filter);
+
+    let paths: Vec<_> = coverage
+        .file_coverages()
+        .iter()
+        .map(|v| v.path().to_owned())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            ws.path().join("src/lib.rs"),
+            ws.path().join("src/generated/bindings.rs")
+        ]
+    );
+
+    let mut filter = PathFilter::new(ws.path());
+    filter.add_include("src/*.rs");
+    filter.set_exclude_external(false);
+
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+    coverage.retain_paths(&filter);
+    assert_eq!(coverage.file_coverages().len(), 1);
+    assert_eq!(
+        coverage.file_coverages()[0].path(),
+        ws.path().join("src/lib.rs")
+    );
+}
+
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/filter");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov3.info");
+
+    let status = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--exclude")
+        .arg("src/generated")
+        .arg("--exclude")
+        .arg("tests/*")
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov.info"))
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov2.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
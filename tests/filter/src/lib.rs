@@ -0,0 +1,3 @@
+pub fn hello() -> i32 {
+    1
+}
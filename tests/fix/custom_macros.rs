@@ -0,0 +1,14 @@
+fn check(v: i32) -> i32 {
+    ensure!(v > 0);
+    prop_assert!(v < 10);
+    if v == 5 {
+        bug!("five");
+    }
+    debug_assert!(v != 3);
+    v
+}
+
+#[tokio::test]
+async fn it_works() {
+    check(1);
+}
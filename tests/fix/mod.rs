@@ -1,8 +1,13 @@
 use super::WorkSpace;
 use pretty_assertions::assert_eq;
 
-use rust_covfix::rule;
-use rust_covfix::{BranchCoverage, CoverageFixer, FileCoverage, LineCoverage, PackageCoverage};
+use rust_covfix::rule::{
+    self, Fix, Fixes, Node, NodeKind, Rule, RuleVisitor, SourceCode, Subscription,
+};
+use rust_covfix::{
+    error::Error, BranchCoverage, CoverageFixer, ErrorPolicy, FileCoverage, LineCoverage,
+    PackageCoverage,
+};
 
 macro_rules! line_coverages {
     () => {
@@ -477,3 +482,420 @@ fn assert() {
         &*expected_branch_covs
     );
 }
+
+#[test]
+fn report() {
+    let ws = WorkSpace::from_template("./tests/fix");
+    let source_file = ws.path().join("closing_brackets.rs");
+
+    let original_line_covs = line_coverages!(
+        1 => 1,
+        2 => 1,
+        3 => 1,
+        4 => 1,
+        5 => 1,
+        6 => 0,
+        7 => 0,
+        8 => 0,
+        10 => 0,
+        11 => 0,
+    );
+
+    let mut coverage = PackageCoverage::new(vec![FileCoverage::new(
+        &source_file,
+        original_line_covs,
+        vec![],
+    )]);
+
+    let fixer = CoverageFixer::new();
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(report.before.line_total, 10);
+    assert_eq!(report.after.line_total, 7);
+    assert_eq!(report.lines_removed(), 3);
+    assert_eq!(report.branches_removed(), 0);
+    assert!((report.line_removed_percent().unwrap() - 30.0).abs() < 1e-9);
+
+    assert_eq!(report.files.len(), 1);
+    let file_report = &report.files[0];
+    assert_eq!(file_report.path, source_file);
+    assert_eq!(file_report.rule("close").unwrap().lines_removed, 3);
+
+    let rules: Vec<_> = report.rule_totals().into_iter().map(|r| r.rule).collect();
+    assert_eq!(
+        rules,
+        vec![
+            "close",
+            "test",
+            "loop",
+            "derive",
+            "unreachable",
+            "assert",
+            "comment"
+        ]
+    );
+}
+
+#[test]
+fn unparsable() {
+    let ws = WorkSpace::from_template("./tests/fix");
+    let source_file = ws.path().join("unparsable.rs");
+
+    let original_line_covs = line_coverages!(
+        1 => 1,
+        2 => 1,
+        4 => 1,
+        6 => 1,
+        7 => 1,
+        8 => 1,
+    );
+
+    let expected_line_covs = line_coverages!(
+        1 => 1,
+        2 => 1,
+        6 => 1,
+        7 => 1,
+    );
+
+    let mut coverage = PackageCoverage::new(vec![FileCoverage::new(
+        &source_file,
+        original_line_covs,
+        vec![],
+    )]);
+
+    let fixer = CoverageFixer::new();
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(
+        coverage.file_coverages()[0].line_coverages(),
+        &*expected_line_covs
+    );
+
+    let downgraded = report.downgraded_files();
+    assert_eq!(downgraded.len(), 1);
+    assert!(downgraded[0].parse_error.is_some());
+
+    let rules: Vec<_> = downgraded[0]
+        .rules
+        .iter()
+        .map(|r| r.rule.as_str())
+        .collect();
+    assert_eq!(rules, vec!["close", "comment"]);
+}
+
+#[test]
+fn unparsable_policy() {
+    let ws = WorkSpace::from_template("./tests/fix");
+    let source_file = ws.path().join("unparsable.rs");
+
+    let coverage = || {
+        PackageCoverage::new(vec![FileCoverage::new(
+            &source_file,
+            line_coverages!(1 => 1, 6 => 1, 8 => 1),
+            vec![],
+        )])
+    };
+
+    let mut fixer = CoverageFixer::new();
+    fixer.set_error_policy(ErrorPolicy::Abort);
+    let err = fixer.fix(&mut coverage()).unwrap_err();
+    assert!(err.to_string().contains("Failed to parse"));
+
+    fixer.set_error_policy(ErrorPolicy::SkipFile);
+    let mut cov = coverage();
+    let report = fixer.fix(&mut cov).unwrap();
+    assert_eq!(report.warnings.len(), 1);
+    assert!(report.files.is_empty());
+    assert_eq!(cov.file_coverages()[0].line_coverages().len(), 3);
+
+    fixer.set_error_policy(ErrorPolicy::DropFile);
+    let mut cov = coverage();
+    fixer.fix(&mut cov).unwrap();
+    assert!(cov.file_coverages().is_empty());
+}
+
+struct SignatureRule;
+
+impl Rule for SignatureRule {
+    fn name(&self) -> &str {
+        "signature"
+    }
+
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(SignatureVisitor))
+    }
+}
+
+struct SignatureVisitor;
+
+impl RuleVisitor for SignatureVisitor {
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            nodes: vec![NodeKind::ItemFn],
+            lines: false,
+        }
+    }
+
+    fn visit_node(&mut self, node: Node, fixes: &mut Fixes) {
+        if let Node::ItemFn(item) = node {
+            fixes.push(Fix::IgnoreLine(item.sig.fn_token.span.start().line));
+        }
+    }
+}
+
+struct ElseRule;
+
+impl Rule for ElseRule {
+    fn name(&self) -> &str {
+        "else"
+    }
+
+    fn requires_ast(&self) -> bool {
+        false
+    }
+
+    fn visitor(&self) -> Option<Box<dyn RuleVisitor + '_>> {
+        Some(Box::new(ElseVisitor))
+    }
+}
+
+struct ElseVisitor;
+
+impl RuleVisitor for ElseVisitor {
+    fn subscription(&self) -> Subscription {
+        Subscription {
+            nodes: vec![],
+            lines: true,
+        }
+    }
+
+    fn visit_line(&mut self, line_number: usize, line: &str, fixes: &mut Fixes) {
+        if line.contains("else") {
+            fixes.push(Fix::IgnoreLine(line_number));
+        }
+    }
+}
+
+/// rule which modifies the coverage directly without the visitor
+struct LineEightRule;
+
+impl Rule for LineEightRule {
+    fn name(&self) -> &str {
+        "line-eight"
+    }
+
+    fn fix_file_coverage(&self, _source: &SourceCode, file_cov: &mut FileCoverage) {
+        for line_cov in file_cov.line_range_mut(8..=8) {
+            line_cov.count = None;
+        }
+    }
+}
+
+/// rule which always fails
+struct FailingRule;
+
+impl Rule for FailingRule {
+    fn name(&self) -> &str {
+        "failing"
+    }
+
+    fn try_fix_file_coverage(
+        &self,
+        _source: &SourceCode,
+        _file_cov: &mut FileCoverage,
+    ) -> Result<Vec<(usize, String)>, Error> {
+        Err("broken rule".into())
+    }
+}
+
+#[test]
+fn custom_rules() {
+    let ws = WorkSpace::from_template("./tests/fix");
+    let source_file = ws.path().join("closing_brackets.rs");
+
+    let original_line_covs = line_coverages!(
+        1 => 1,
+        2 => 1,
+        3 => 1,
+        4 => 1,
+        5 => 1,
+        6 => 0,
+        7 => 1,
+        8 => 0,
+        9 => 1,
+        10 => 1,
+    );
+
+    let expected_line_covs = line_coverages!(
+        2 => 1,
+        3 => 1,
+        4 => 1,
+        6 => 0,
+        9 => 1,
+        10 => 1,
+    );
+
+    let mut coverage = PackageCoverage::new(vec![FileCoverage::new(
+        &source_file,
+        original_line_covs,
+        vec![],
+    )]);
+
+    let rules: Vec<Box<dyn Rule>> = vec![
+        Box::new(SignatureRule),
+        Box::new(LineEightRule),
+        Box::new(ElseRule),
+    ];
+    let fixer = CoverageFixer::with_rules(rules);
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(
+        coverage.file_coverages()[0].line_coverages(),
+        &*expected_line_covs
+    );
+
+    let file_report = &report.files[0];
+    assert_eq!(file_report.rule("signature").unwrap().lines_removed, 1);
+    assert_eq!(file_report.rule("line-eight").unwrap().lines_removed, 1);
+    assert_eq!(file_report.rule("else").unwrap().lines_removed, 2);
+}
+
+#[test]
+fn rule_options() {
+    let ws = WorkSpace::from_template("./tests/fix");
+    let source_file = ws.path().join("custom_macros.rs");
+
+    let original_line_covs = line_coverages!(
+        1 => 1,
+        2 => 1,
+        3 => 1,
+        4 => 1,
+        5 => 0,
+        7 => 1,
+        8 => 1,
+        11 => 1,
+        12 => 1,
+        13 => 1,
+    );
+
+    let original_branch_covs = branch_coverages!(
+        2 => true,
+        2 => false,
+        3 => true,
+        3 => false,
+        4 => false,
+        4 => true,
+        7 => true,
+        7 => false,
+    );
+
+    let expected_line_covs = line_coverages!(
+        1 => 1,
+        2 => 1,
+        3 => 1,
+        4 => 1,
+        7 => 1,
+        8 => 1,
+    );
+
+    let expected_branch_covs = branch_coverages!(
+        2 => true,
+        3 => true,
+        4 => false,
+        4 => true,
+        7 => true,
+        7 => false,
+    );
+
+    let mut assert_rule = rule::AssertRule::new();
+    assert_rule.add_macro("ensure");
+    assert_rule.add_macro("prop_assert");
+    assert_rule.set_debug_assert(false);
+
+    let mut unreachable_rule = rule::UnreachableRule::new();
+    unreachable_rule.add_macro("bug");
+
+    let mut test_rule = rule::TestRule::new();
+    test_rule.add_attribute("tokio::test");
+
+    let rules: Vec<Box<dyn Rule>> = vec![
+        Box::new(test_rule),
+        Box::new(unreachable_rule),
+        Box::new(assert_rule),
+    ];
+
+    let mut coverage = PackageCoverage::new(vec![FileCoverage::new(
+        &source_file,
+        original_line_covs.clone(),
+        original_branch_covs.clone(),
+    )]);
+    CoverageFixer::with_rules(rules).fix(&mut coverage).unwrap();
+
+    assert_eq!(
+        coverage.file_coverages()[0].line_coverages(),
+        &*expected_line_covs
+    );
+    assert_eq!(
+        coverage.file_coverages()[0].branch_coverages(),
+        &*expected_branch_covs
+    );
+
+    // same configuration in string format
+    let mut rules = rule::RuleRegistry::new()
+        .parse("test,unreachable,assert")
+        .unwrap();
+    for option in &[
+        "test.attributes=+tokio::test",
+        "unreachable.macros=+bug",
+        "assert.macros=assert,ensure,prop_assert",
+        "assert.debug_assert=false",
+    ] {
+        assert!(rule::configure(&mut rules, option).unwrap());
+    }
+    assert!(!rule::configure(&mut rules, "loop.foo=bar").unwrap());
+    assert!(rule::configure(&mut rules, "assert.foo=bar").is_err());
+    assert!(rule::configure(&mut rules, "assert.debug_assert=maybe").is_err());
+    assert!(rule::configure(&mut rules, "assert").is_err());
+
+    let mut coverage = PackageCoverage::new(vec![FileCoverage::new(
+        &source_file,
+        original_line_covs,
+        original_branch_covs,
+    )]);
+    CoverageFixer::with_rules(rules).fix(&mut coverage).unwrap();
+
+    assert_eq!(
+        coverage.file_coverages()[0].line_coverages(),
+        &*expected_line_covs
+    );
+    assert_eq!(
+        coverage.file_coverages()[0].branch_coverages(),
+        &*expected_branch_covs
+    );
+}
+
+#[test]
+fn failing_rule_policy() {
+    let ws = WorkSpace::from_template("./tests/fix");
+    let source_file = ws.path().join("closing_brackets.rs");
+
+    let original_line_covs = line_coverages!(2 => 1, 1 => 1, 8 => 0);
+    let mut coverage = PackageCoverage::new(vec![FileCoverage::new(
+        &source_file,
+        original_line_covs.clone(),
+        vec![],
+    )]);
+
+    // the lines removed by the first rule are restored
+    let rules: Vec<Box<dyn Rule>> = vec![Box::new(SignatureRule), Box::new(FailingRule)];
+    let mut fixer = CoverageFixer::with_rules(rules);
+    fixer.set_error_policy(ErrorPolicy::SkipFile);
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(report.warnings.len(), 1);
+    assert!(report.warnings[0].message.contains("broken rule"));
+    assert_eq!(
+        coverage.file_coverages()[0].line_coverages(),
+        &*original_line_covs
+    );
+}
@@ -0,0 +1,8 @@
+#[derive(Debug)]
+struct Foo {
+    a: i32,
+}
+
+fn broken( {
+    1 + 2
+}
@@ -0,0 +1,172 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rust_covfix::error::ErrorKind;
+use rust_covfix::{
+    parser::LcovParser, CoverageFixer, CoverageReader, CoverageWriter, GitSourceProvider,
+    SourceProvider,
+};
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// commit `lib_old.rs` as `src/lib.rs`, and then overwrite it with `lib_new.rs`
+fn setup() -> WorkSpace {
+    let ws = WorkSpace::from_template("tests/git_source");
+
+    fs::create_dir(ws.path().join("src")).unwrap();
+    fs::copy(ws.path().join("lib_old.rs"), ws.path().join("src/lib.rs")).unwrap();
+
+    git(ws.path(), &["init", "-q"]);
+    git(ws.path(), &["add", "src/lib.rs"]);
+    git(ws.path(), &["commit", "-q", "-m", "initial"]);
+
+    fs::copy(ws.path().join("lib_new.rs"), ws.path().join("src/lib.rs")).unwrap();
+    ws
+}
+
+#[test]
+fn read_source() {
+    let ws = setup();
+
+    let provider = GitSourceProvider::new(ws.path(), "HEAD").unwrap();
+    assert_eq!(provider.commit().len(), 40);
+
+    let expected = fs::read_to_string(ws.path().join("lib_old.rs")).unwrap();
+    assert_eq!(
+        provider.read_source(&ws.path().join("src/lib.rs")).unwrap(),
+        expected
+    );
+
+    let provider = GitSourceProvider::new(&ws.path().join("src"), "HEAD").unwrap();
+    assert_eq!(
+        provider.read_source(&ws.path().join("src/lib.rs")).unwrap(),
+        expected
+    );
+
+    assert_matches!(
+        provider
+            .read_source(&ws.path().join("src/main.rs"))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::SourceFileNotFound(_)
+    );
+    assert_matches!(
+        provider
+            .read_source(&ws.path().join("src"))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::SourceFileNotFound(_)
+    );
+
+    // the same process is used after the missing files
+    assert_eq!(
+        provider.read_source(&ws.path().join("src/lib.rs")).unwrap(),
+        expected
+    );
+
+    assert!(GitSourceProvider::new(ws.path(), "no-such-revision").is_err());
+}
+
+#[test]
+fn fix() {
+    let ws = setup();
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    let mut fixer = CoverageFixer::new();
+    fixer.set_source_provider(GitSourceProvider::new(ws.path(), "HEAD").unwrap());
+    fixer.fix(&mut coverage).unwrap();
+
+    let mut output = Vec::new();
+    parser.write(&coverage, &mut output).unwrap();
+    let expected_content = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), expected_content);
+}
+
+#[test]
+fn cli() {
+    let ws = setup();
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov_out.info");
+
+    let status = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--source-rev")
+        .arg("HEAD")
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov.info"))
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn cli_diff() {
+    use rust_covfix::TotalCoverage;
+
+    let ws = setup();
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    // the base is fixed with the committed source, not with the working tree
+    let parser = LcovParser::new(ws.path());
+    let fixed = parser
+        .read_from_file(&ws.path().join("lcov_fixed.info"))
+        .unwrap();
+    let expected = format!(
+        r#"{{"old":{{"line_executed":{},"line_total":{},"branch_executed":{},"branch_total":{}}},"#,
+        fixed.line_executed(),
+        fixed.line_total(),
+        fixed.branch_executed(),
+        fixed.branch_total()
+    );
+
+    let result = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--diff")
+        .arg(ws.path().join("lcov.info"))
+        .arg("--base-source-rev")
+        .arg("HEAD")
+        .arg("--diff-format")
+        .arg("json")
+        .arg(ws.path().join("lcov.info"))
+        .output()
+        .unwrap();
+
+    assert!(result.status.success());
+    let json = String::from_utf8(result.stdout).unwrap();
+    assert!(json.starts_with(&expected), "{}", json);
+}
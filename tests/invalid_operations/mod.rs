@@ -2,7 +2,9 @@ use super::WorkSpace;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
-use rust_covfix::{error::ErrorKind, parser::LcovParser, CoverageFixer, CoverageReader};
+use rust_covfix::{
+    error::ErrorKind, parser::LcovParser, CoverageFixer, CoverageReader, ErrorPolicy,
+};
 
 #[test]
 fn root_is_not_a_dir() {
@@ -60,6 +62,30 @@ fn source_file_not_found() {
     assert_matches!(result.unwrap_err().kind(), ErrorKind::SourceFileNotFound(_));
 }
 
+#[test]
+fn source_file_not_found_policy() {
+    let ws = WorkSpace::from_template("tests/invalid_operations");
+
+    let parser = LcovParser::new(ws.path());
+    let mut fixer = CoverageFixer::default();
+
+    fixer.set_error_policy(ErrorPolicy::SkipFile);
+    let mut cov = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+    let report = fixer.fix(&mut cov).unwrap();
+
+    assert_eq!(cov.file_coverages().len(), 1);
+    assert_eq!(report.warnings.len(), 1);
+    assert_eq!(report.warnings[0].path, ws.path().join("not_exist.rs"));
+    assert!(report.warnings[0].message.contains("not found"));
+
+    fixer.set_error_policy(ErrorPolicy::DropFile);
+    let mut cov = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+    let report = fixer.fix(&mut cov).unwrap();
+
+    assert!(cov.file_coverages().is_empty());
+    assert_eq!(report.warnings.len(), 1);
+}
+
 #[test]
 fn target_dir_not_found() {
     let ws = WorkSpace::from_template("tests/invalid_operations");
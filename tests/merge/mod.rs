@@ -0,0 +1,149 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_covfix::{
+    parser::LcovParser, BranchCoverage, CoverageReader, FileCoverage, LineCoverage, PackageCoverage,
+};
+
+#[test]
+fn merge() {
+    let ws = WorkSpace::from_template("tests/merge");
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser
+        .read_from_file(&ws.path().join("lcov1.info"))
+        .unwrap();
+    let other = parser
+        .read_from_file(&ws.path().join("lcov2.info"))
+        .unwrap();
+    coverage.merge(other);
+
+    let expected_coverage = PackageCoverage::with_test_name(
+        "merge",
+        vec![
+            FileCoverage::new(
+                ws.path().join("src/lib.rs"),
+                vec![
+                    LineCoverage {
+                        line_number: 2,
+                        count: Some(3),
+                    },
+                    LineCoverage {
+                        line_number: 3,
+                        count: Some(3),
+                    },
+                    LineCoverage {
+                        line_number: 4,
+                        count: Some(1),
+                    },
+                    LineCoverage {
+                        line_number: 6,
+                        count: Some(0),
+                    },
+                ],
+                vec![
+                    BranchCoverage {
+                        line_number: 3,
+                        block_number: Some(0),
+                        taken: Some(true),
+                    },
+                    BranchCoverage {
+                        line_number: 3,
+                        block_number: Some(0),
+                        taken: Some(true),
+                    },
+                ],
+            ),
+            FileCoverage::new(
+                ws.path().join("src/sub.rs"),
+                vec![LineCoverage {
+                    line_number: 1,
+                    count: Some(3),
+                }],
+                vec![],
+            ),
+        ],
+    );
+
+    assert_eq!(coverage, expected_coverage);
+}
+
+#[test]
+fn duplicate_entries() {
+    let ws = WorkSpace::from_template("tests/merge");
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser
+        .read_from_file(&ws.path().join("lcov1.info"))
+        .unwrap();
+    let other = parser
+        .read_from_file(&ws.path().join("lcov_duplicate.info"))
+        .unwrap();
+    coverage.merge(other);
+
+    let expected_coverage = PackageCoverage::new(vec![FileCoverage::new(
+        ws.path().join("src/lib.rs"),
+        vec![
+            LineCoverage {
+                line_number: 2,
+                count: Some(3),
+            },
+            LineCoverage {
+                line_number: 3,
+                count: Some(2),
+            },
+            LineCoverage {
+                line_number: 4,
+                count: Some(0),
+            },
+        ],
+        vec![
+            BranchCoverage {
+                line_number: 3,
+                block_number: Some(0),
+                taken: Some(true),
+            },
+            BranchCoverage {
+                line_number: 3,
+                block_number: Some(0),
+                taken: Some(true),
+            },
+        ],
+    )]);
+
+    assert_eq!(coverage, expected_coverage);
+}
+
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/merge");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov3.info");
+
+    let status = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--no-fix")
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov1.info"))
+        .arg(ws.path().join("lcov2.info"))
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_merged.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
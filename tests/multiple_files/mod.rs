@@ -32,7 +32,7 @@ fn all_rules() {
         String::from_utf8(result.stderr).unwrap(),
         r"Coverages are fixed successfully!
   line:   100.00% (10 of 10 lines)    => 100.00% (5 of 5 lines)
-  branch: NaN% (0 of 0 branches) => NaN% (0 of 0 branches)
+  branch: n/a (0 of 0 branches) => n/a (0 of 0 branches)
 
 "
     );
@@ -55,12 +55,23 @@ fn invalid() {
         exe.push("target/debug/rust-covfix");
     }
 
-    let result = Command::new(exe)
+    let result = Command::new(&exe)
         .current_dir(ws.path().join("src"))
         .arg(&lcov)
         .output()
         .unwrap();
 
+    assert!(result.status.success());
+    assert!(String::from_utf8(result.stderr)
+        .unwrap()
+        .starts_with("Warning: Failed to parse"));
+
+    let result = Command::new(&exe)
+        .current_dir(ws.path().join("src"))
+        .arg(ws.path().join("lcov_missing.info"))
+        .output()
+        .unwrap();
+
     assert!(!result.status.success());
     assert_eq!(
         String::from_utf8(result.stderr)
@@ -71,3 +82,39 @@ fn invalid() {
         "Error: Failed to fix coverage"
     );
 }
+
+#[test]
+fn invalid_with_policy() {
+    let ws = WorkSpace::from_template("tests/multiple_files");
+
+    let lcov = ws.path().join("lcov_missing.info");
+    let output = ws.path().join("lcov_out.info");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    for &(policy, kept) in &[("skip-file", true), ("drop-file", false)] {
+        let result = Command::new(&exe)
+            .current_dir(ws.path().join("src"))
+            .arg("--on-error")
+            .arg(policy)
+            .arg("-o")
+            .arg(&output)
+            .arg(&lcov)
+            .output()
+            .unwrap();
+
+        assert!(result.status.success());
+        assert!(String::from_utf8(result.stderr)
+            .unwrap()
+            .starts_with("Warning: Source file"));
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert!(content.contains("SF:src/lib.rs"));
+        assert_eq!(content.contains("SF:src/missing.rs"), kept);
+    }
+}
@@ -0,0 +1,117 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_covfix::{
+    parser::{LcovParser, OutsidePathStyle},
+    CoverageReader, CoverageWriter, FileCoverage, LineCoverage, PackageCoverage,
+};
+
+#[test]
+fn read() {
+    let ws = WorkSpace::from_template("tests/outside_root");
+    let root = ws.path().join("project");
+
+    let parser = LcovParser::new(&root);
+    let coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    assert_eq!(coverage.file_coverages()[0].path(), root.join("src/lib.rs"));
+    assert_eq!(
+        coverage.file_coverages()[1].path(),
+        ws.path().join("sibling/src/lib.rs")
+    );
+}
+
+#[test]
+fn write() {
+    let ws = WorkSpace::from_template("tests/outside_root");
+    let root = ws.path().join("project");
+    let sibling = ws.path().join("sibling/src/lib.rs");
+
+    let coverage = PackageCoverage::new(vec![FileCoverage::new(
+        &sibling,
+        vec![LineCoverage {
+            line_number: 1,
+            count: Some(1),
+        }],
+        vec![],
+    )]);
+
+    let mut parser = LcovParser::new(&root);
+
+    let mut buf = Vec::new();
+    parser.write(&coverage, &mut buf).unwrap();
+    let content = String::from_utf8(buf).unwrap();
+    assert_eq!(
+        content.lines().nth(1).unwrap(),
+        format!("SF:{}", sibling.display())
+    );
+
+    parser.set_outside_path_style(OutsidePathStyle::Relative);
+
+    let mut buf = Vec::new();
+    parser.write(&coverage, &mut buf).unwrap();
+    let content = String::from_utf8(buf).unwrap();
+    assert_eq!(content.lines().nth(1).unwrap(), "SF:../sibling/src/lib.rs");
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink() {
+    let ws = WorkSpace::from_template("tests/outside_root");
+    let link = ws.path().join("link");
+    std::os::unix::fs::symlink(ws.path().join("project"), &link).unwrap();
+
+    let coverage = PackageCoverage::new(vec![FileCoverage::new(
+        ws.path().join("project/src/lib.rs"),
+        vec![],
+        vec![],
+    )]);
+
+    let parser = LcovParser::new(&link);
+
+    let mut buf = Vec::new();
+    parser.write(&coverage, &mut buf).unwrap();
+    let content = String::from_utf8(buf).unwrap();
+    assert_eq!(content.lines().nth(1).unwrap(), "SF:src/lib.rs");
+
+    // the same file is also regarded as inside the root when reading
+    let content = format!(
+        "TN:\nSF:{}\nend_of_record\n",
+        ws.path().join("project/src/lib.rs").display()
+    );
+    let coverage = parser.read(&mut content.as_bytes()).unwrap();
+    assert_eq!(coverage.file_coverages()[0].path(), link.join("src/lib.rs"));
+}
+
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/outside_root");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov3.info");
+
+    let status = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path().join("project"))
+        .arg("--relative-outside-root")
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov.info"))
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov2.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
@@ -0,0 +1,226 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rust_covfix::rule::{self, Rule, RuleRegistry};
+use rust_covfix::{
+    parser::LcovParser, CoverageFixer, CoverageReader, CoverageWriter, FileCoverage, LineCoverage,
+    MemorySourceProvider, PackageCoverage, PatternRule, PatternScope,
+};
+
+fn pattern_rules() -> Vec<Box<dyn Rule>> {
+    let mut trace = PatternRule::new("trace");
+    trace.add_macro("tracing::trace");
+    trace.set_scope(PatternScope::Line);
+
+    let mut expect = PatternRule::new("expect");
+    expect.add_regex(r"\.expect\(").unwrap();
+    expect.set_scope(PatternScope::Branch);
+
+    let mut cold = PatternRule::new("cold");
+    cold.add_attribute("cold");
+
+    let mut debug = PatternRule::new("debug");
+    debug.add_function("debug_*");
+
+    vec![
+        Box::new(trace),
+        Box::new(expect),
+        Box::new(cold),
+        Box::new(debug),
+    ]
+}
+
+#[test]
+fn fix() {
+    let ws = WorkSpace::from_template("tests/pattern");
+
+    let mut rules = rule::default_rules();
+    rules.extend(pattern_rules());
+    let fixer = CoverageFixer::with_rules(rules);
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    let file = &report.files[0];
+    assert_eq!(file.rule("trace").unwrap().lines_removed, 1);
+    assert_eq!(file.rule("trace").unwrap().branches_removed, 0);
+    assert_eq!(file.rule("expect").unwrap().lines_removed, 0);
+    assert_eq!(file.rule("expect").unwrap().branches_removed, 2);
+
+    let mut output = Vec::new();
+    parser.write(&coverage, &mut output).unwrap();
+    let expected = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+}
+
+#[test]
+fn unparsable() {
+    let mut rule = PatternRule::new("expect");
+    rule.add_macro("todo");
+    assert!(rule.requires_ast());
+    rule.add_regex(r"\.expect\(").unwrap();
+    assert!(!rule.requires_ast());
+
+    let path = Path::new("/project/src/lib.rs");
+    let mut provider = MemorySourceProvider::new();
+    provider.insert(
+        path,
+        "fn broken( {\n    value.expect(\"x\");\n    todo!();\n}\n",
+    );
+
+    let mut fixer = CoverageFixer::with_rules(vec![Box::new(rule) as Box<dyn Rule>]);
+    fixer.set_source_provider(provider);
+
+    let mut coverage = PackageCoverage::new(vec![FileCoverage::new(
+        path,
+        vec![
+            LineCoverage {
+                line_number: 1,
+                count: Some(1),
+            },
+            LineCoverage {
+                line_number: 2,
+                count: Some(1),
+            },
+            LineCoverage {
+                line_number: 3,
+                count: Some(0),
+            },
+        ],
+        vec![],
+    )]);
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    // macros are not detected without the syntax tree
+    assert!(report.files[0].parse_error.is_some());
+    let lines: Vec<usize> = coverage.file_coverages()[0]
+        .line_coverages()
+        .iter()
+        .map(|v| v.line_number)
+        .collect();
+    assert_eq!(lines, vec![1, 3]);
+}
+
+#[test]
+fn options() {
+    let mut rules: Vec<Box<dyn Rule>> = vec![Box::new(PatternRule::new("custom"))];
+    let key = rules[0].cache_key().unwrap();
+
+    rule::configure(&mut rules, "custom.regex=^\\s*debug!\\(\"\\w{1,3}\"").unwrap();
+    rule::configure(&mut rules, "custom.regex=+unimplemented").unwrap();
+    rule::configure(&mut rules, "custom.macros=todo,unimplemented").unwrap();
+    rule::configure(&mut rules, "custom.scope=branch").unwrap();
+    assert_ne!(rules[0].cache_key().unwrap(), key);
+    assert!(!rules[0].requires_ast());
+
+    assert!(rule::configure(&mut rules, "custom.scope=all").is_err());
+    assert!(rule::configure(&mut rules, "custom.regex=(").is_err());
+    assert!(rule::configure(&mut rules, "custom.command=ls").is_err());
+
+    let mut rule = PatternRule::new("custom");
+    rule.add_regex(r"\btodo\b").unwrap();
+    assert_eq!(rule.regexes().collect::<Vec<_>>(), vec![r"\btodo\b"]);
+    assert!(!rule.requires_ast());
+    assert!(rule.add_regex("[").is_err());
+
+    let mut registry = RuleRegistry::new();
+    rule.register(&mut registry, false).unwrap();
+    assert!(!registry.get("custom").unwrap().is_default());
+    assert_eq!(
+        registry.parse("custom").unwrap()[0].cache_key(),
+        rules_key(r"\btodo\b")
+    );
+}
+
+fn rules_key(regex: &str) -> Option<String> {
+    let mut rule = PatternRule::new("custom");
+    rule.add_regex(regex).unwrap();
+    rule.cache_key()
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn config() {
+    let ws = WorkSpace::from_template("tests/pattern");
+    let config = rust_covfix::Config::discover(ws.path()).unwrap().unwrap();
+
+    // tables are sorted by their names
+    let names: Vec<&str> = config.patterns.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["cold", "debug", "expect", "trace"]);
+    assert_eq!(config.patterns[0].scope, "both");
+    assert_eq!(config.patterns[0].attributes, vec!["cold"]);
+    assert!(!config.patterns[1].default);
+    assert_eq!(config.patterns[2].regexes, vec![r"\.expect\("]);
+    assert_eq!(config.patterns[3].scope, "line");
+    assert_eq!(config.patterns[3].macros, vec!["tracing::trace"]);
+
+    let mut registry = RuleRegistry::new();
+    for pattern in &config.patterns {
+        pattern.register(&mut registry).unwrap();
+    }
+    let rules = config.create_rules(&registry).unwrap();
+    let names: Vec<&str> = rules.iter().map(|r| r.name()).collect();
+    assert!(names.ends_with(&["cold", "expect", "trace", "debug"]));
+    assert_eq!(rules[names.len() - 3].reason(), "panics on invalid input");
+
+    let from_toml = |content| rust_covfix::Config::from_toml(content, Path::new(""));
+    assert!(from_toml("[pattern.a]\nregex = 'a'\nscope = \"all\"").is_err());
+    assert!(from_toml("[pattern.a]\nscope = \"line\"").is_err());
+    let config = from_toml("[pattern.a]\nregex = '('").unwrap();
+    assert!(config.patterns[0].to_rule().is_err());
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/pattern");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov_out.info");
+
+    // rules from the configuration are also listed
+    let result = Command::new(&exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--list-rules")
+        .output()
+        .unwrap();
+    assert!(result.status.success());
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    let names: Vec<_> = stdout
+        .lines()
+        .map(|l| l.split_whitespace().next().unwrap())
+        .collect();
+    assert!(names.ends_with(&["all", "cold", "debug", "expect", "trace"]));
+    assert!(stdout
+        .lines()
+        .any(|l| l.starts_with("debug ") && l.ends_with("(disabled by default)")));
+
+    let result = Command::new(&exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--explain")
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov.info"))
+        .output()
+        .unwrap();
+
+    assert!(result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("expect: line 3 (panics on invalid input)"));
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
@@ -0,0 +1,24 @@
+pub fn process(values: &[u32]) -> u32 {
+    tracing::trace!("processing {} values", values.len());
+    let first = values.first().expect("values must not be empty");
+    let mut sum = *first;
+    for v in &values[1..] {
+        sum += v;
+    }
+    log_result(sum);
+    sum
+}
+
+#[inline(never)]
+#[cold]
+fn log_result(sum: u32) {
+    if sum > 100 {
+        println!("large sum");
+    }
+}
+
+pub fn debug_dump(values: &[u32]) {
+    for v in values {
+        println!("{}", v);
+    }
+}
@@ -0,0 +1,198 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rust_covfix::rule::{self, Rule};
+use rust_covfix::{
+    parser::LcovParser, CoverageFixer, CoverageReader, ErrorPolicy, PackageCoverage, PluginRule,
+    RuleScope,
+};
+
+/// create the plugin script which records the request and prints the response
+fn write_plugin(ws: &WorkSpace, response: &str) -> PathBuf {
+    let script = format!(
+        "#!/bin/sh\ncat > \"{dir}/request.json\"\necho run >> \"{dir}/runs.log\"\nprintf '%s' '{response}'\n",
+        dir = ws.path().display(),
+        response = response.replace("ROOT", &ws.path().display().to_string())
+    );
+
+    let path = ws.path().join("plugin.sh");
+    fs::write(&path, script).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+fn read_coverage(ws: &WorkSpace) -> PackageCoverage {
+    let parser = LcovParser::new(ws.path());
+    parser.read_from_file(&ws.path().join("lcov.info")).unwrap()
+}
+
+fn line_numbers(coverage: &PackageCoverage, idx: usize) -> Vec<usize> {
+    coverage.file_coverages()[idx]
+        .line_coverages()
+        .iter()
+        .map(|v| v.line_number)
+        .collect()
+}
+
+fn runs(ws: &WorkSpace) -> usize {
+    fs::read_to_string(ws.path().join("runs.log"))
+        .unwrap()
+        .lines()
+        .count()
+}
+
+const RESPONSE: &str = r#"{"files":[{"path":"ROOT/src/lib.rs","adjustments":[{"lines":[5,6],"reason":"internal helper"},{"lines":[7]}]}]}"#;
+
+#[test]
+fn per_file() {
+    let ws = WorkSpace::from_template("tests/plugin");
+    let plugin = PluginRule::new("internal", write_plugin(&ws, RESPONSE));
+
+    let mut fixer = CoverageFixer::with_rules(vec![Box::new(plugin) as Box<dyn Rule>]);
+    fixer.set_explain(true);
+    let mut coverage = read_coverage(&ws);
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(line_numbers(&coverage, 0), vec![1, 2, 3]);
+    assert_eq!(line_numbers(&coverage, 1), vec![1]);
+    assert_eq!(runs(&ws), 2);
+
+    let adjustments = &report.files[0].adjustments;
+    assert_eq!(adjustments.len(), 2);
+    assert_eq!(adjustments[0].reason, "internal helper");
+    assert_eq!(adjustments[0].lines, vec![5, 6]);
+    assert_eq!(adjustments[1].reason, "removed by the external plugin");
+    assert_eq!(adjustments[1].lines, vec![7]);
+
+    // request of the last file
+    let request: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(ws.path().join("request.json")).unwrap()).unwrap();
+    assert_eq!(request["version"], 1);
+    assert_eq!(request["rule"], "internal");
+    let file = &request["files"][0];
+    assert_eq!(
+        Path::new(file["path"].as_str().unwrap()),
+        ws.path().join("src/util.rs")
+    );
+    assert_eq!(file["source"], "pub fn util() {}\n");
+    assert_eq!(
+        file["lines"],
+        serde_json::json!([{"line_number": 1, "count": 0}])
+    );
+    assert_eq!(file["branches"], serde_json::json!([]));
+}
+
+#[test]
+fn batch() {
+    let ws = WorkSpace::from_template("tests/plugin");
+    let mut plugin = PluginRule::new("internal", write_plugin(&ws, RESPONSE));
+    plugin.set_batch(true);
+
+    let fixer = CoverageFixer::with_rules(vec![
+        rule::from_str("close").unwrap(),
+        Box::new(plugin) as Box<dyn Rule>,
+    ]);
+    let mut coverage = read_coverage(&ws);
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(line_numbers(&coverage, 0), vec![1, 2]);
+    assert_eq!(runs(&ws), 1);
+    assert_eq!(report.files[0].rule("close").unwrap().lines_removed, 2);
+    assert_eq!(report.files[0].rule("internal").unwrap().lines_removed, 2);
+
+    let request = fs::read_to_string(ws.path().join("request.json")).unwrap();
+    let request: serde_json::Value = serde_json::from_str(&request).unwrap();
+    assert_eq!(request["files"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn batch_scope() {
+    let ws = WorkSpace::from_template("tests/plugin");
+    let mut plugin = PluginRule::new("internal", write_plugin(&ws, RESPONSE));
+    plugin.set_batch(true);
+
+    let mut fixer = CoverageFixer::with_rules(vec![Box::new(plugin.clone()) as Box<dyn Rule>]);
+    fixer.set_root(ws.path());
+
+    // util.rs is fixed by the other rules
+    let mut scope = RuleScope::new(vec![rule::from_str("close").unwrap()]);
+    scope.add_pattern("src/util.rs");
+    fixer.add_scope(scope);
+
+    // the override does not match any file, so the plugin is not run for it
+    let mut scope = RuleScope::new(vec![Box::new(plugin) as Box<dyn Rule>]);
+    scope.add_pattern("tests/**");
+    fixer.add_scope(scope);
+
+    let mut coverage = read_coverage(&ws);
+    fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(line_numbers(&coverage, 0), vec![1, 2, 3]);
+    assert_eq!(runs(&ws), 1);
+
+    let request = fs::read_to_string(ws.path().join("request.json")).unwrap();
+    let request: serde_json::Value = serde_json::from_str(&request).unwrap();
+    let files = request["files"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(
+        Path::new(files[0]["path"].as_str().unwrap()),
+        ws.path().join("src/lib.rs")
+    );
+}
+
+#[test]
+fn failure() {
+    let ws = WorkSpace::from_template("tests/plugin");
+    let plugin = PluginRule::new("internal", write_plugin(&ws, "not json"));
+
+    let mut fixer = CoverageFixer::with_rules(vec![Box::new(plugin) as Box<dyn Rule>]);
+    let mut coverage = read_coverage(&ws);
+    assert!(fixer.fix(&mut coverage).is_err());
+
+    fixer.set_error_policy(ErrorPolicy::SkipFile);
+    let report = fixer.fix(&mut coverage).unwrap();
+    assert_eq!(report.warnings.len(), 2);
+    assert!(report.warnings[0].message.contains("Invalid response"));
+    assert_eq!(line_numbers(&coverage, 0), vec![1, 2, 3, 5, 6, 7]);
+
+    let plugin = PluginRule::new("missing", ws.path().join("missing.sh"));
+    let fixer = CoverageFixer::with_rules(vec![Box::new(plugin) as Box<dyn Rule>]);
+    assert!(fixer.fix(&mut coverage).is_err());
+}
+
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/plugin");
+    let plugin = write_plugin(&ws, RESPONSE);
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    exe.push("target/debug/rust-covfix");
+
+    let result = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--plugin")
+        .arg(format!("internal={}", plugin.display()))
+        .arg("--rule-option")
+        .arg("internal.batch=true")
+        .arg("--explain")
+        .arg("-o")
+        .arg(ws.path().join("lcov_out.info"))
+        .arg(ws.path().join("lcov.info"))
+        .output()
+        .unwrap();
+
+    assert!(result.status.success());
+    assert_eq!(runs(&ws), 1);
+
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("internal helper"));
+
+    let content = fs::read_to_string(ws.path().join("lcov_out.info")).unwrap();
+    assert!(content.contains("DA:2,1\n"));
+    assert!(!content.contains("DA:5,0\n"));
+}
@@ -0,0 +1,85 @@
+use pretty_assertions::assert_eq;
+
+use rust_covfix::{BranchCoverage, FileCoverage, LineCoverage};
+
+fn file_coverage() -> FileCoverage {
+    let counts = [
+        (1, Some(1)),
+        (2, Some(0)),
+        (3, None),
+        (4, Some(0)),
+        (6, Some(2)),
+        (7, Some(0)),
+        (9, Some(0)),
+    ];
+    let branches = [(2, false), (6, true), (6, false), (9, false)];
+
+    let mut file_cov = FileCoverage::new(
+        "src/lib.rs",
+        counts
+            .iter()
+            .rev()
+            .map(|&(line_number, count)| LineCoverage { line_number, count })
+            .collect(),
+        branches
+            .iter()
+            .map(|&(line_number, taken)| BranchCoverage {
+                line_number,
+                block_number: Some(0),
+                taken: Some(taken),
+            })
+            .collect(),
+    );
+    file_cov.sort();
+    file_cov
+}
+
+#[test]
+fn lookup() {
+    let file_cov = file_coverage();
+
+    assert_eq!(file_cov.line_count(1), Some(1));
+    assert_eq!(file_cov.line_count(3), None);
+    assert_eq!(file_cov.line_count(5), None);
+    assert_eq!(file_cov.line_coverage(4).unwrap().count, Some(0));
+    assert!(file_cov.line_coverage(100).is_none());
+
+    assert_eq!(file_cov.branches_at(6).len(), 2);
+    assert_eq!(file_cov.branches_at(7).len(), 0);
+    assert_eq!(file_cov.branches_at(9)[0].taken, Some(false));
+}
+
+#[test]
+fn range() {
+    let mut file_cov = file_coverage();
+
+    let lines: Vec<_> = file_cov
+        .line_range(2..=6)
+        .iter()
+        .map(|v| v.line_number)
+        .collect();
+    assert_eq!(lines, vec![2, 3, 4, 6]);
+
+    let lines: Vec<_> = file_cov
+        .line_range(5..)
+        .iter()
+        .map(|v| v.line_number)
+        .collect();
+    assert_eq!(lines, vec![6, 7, 9]);
+
+    assert!(file_cov.line_range(10..).is_empty());
+    assert_eq!(file_cov.branch_range(..6).len(), 1);
+
+    for branch in file_cov.branch_range_mut(6..=9) {
+        branch.taken = None;
+    }
+    assert_eq!(file_cov.branches_at(6)[0].taken, None);
+    assert_eq!(file_cov.branches_at(2)[0].taken, Some(false));
+}
+
+#[test]
+fn uncovered_ranges() {
+    let file_cov = file_coverage();
+
+    assert_eq!(file_cov.uncovered_ranges(), vec![(2, 4), (7, 9)]);
+}
@@ -0,0 +1,132 @@
+use pretty_assertions::assert_eq;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_covfix::error::ErrorKind;
+use rust_covfix::rule::{Rule, RuleRegistry, SourceCode};
+use rust_covfix::FileCoverage;
+
+struct CustomRule;
+
+impl Rule for CustomRule {
+    fn name(&self) -> &str {
+        "custom"
+    }
+
+    fn fix_file_coverage(&self, _source: &SourceCode, _file_cov: &mut FileCoverage) {}
+}
+
+fn names(rules: &[Box<dyn Rule>]) -> Vec<&str> {
+    rules.iter().map(|r| r.name()).collect()
+}
+
+#[test]
+fn select() {
+    let registry = RuleRegistry::new();
+
+    assert_eq!(
+        registry.select("default").unwrap(),
+        vec![
+            "close",
+            "test",
+            "loop",
+            "derive",
+            "unreachable",
+            "assert",
+            "comment"
+        ]
+    );
+    assert_eq!(
+        registry.select("-loop,-assert").unwrap(),
+        vec!["close", "test", "derive", "unreachable", "comment"]
+    );
+    assert_eq!(
+        registry.select("test,close,test").unwrap(),
+        vec!["test", "close"]
+    );
+    assert_eq!(
+        registry.select("close,+derive").unwrap(),
+        vec!["close", "derive"]
+    );
+    assert_eq!(registry.select("").unwrap(), Vec::<&str>::new());
+
+    assert_matches!(
+        registry.select("default,-forloop").unwrap_err().kind(),
+        ErrorKind::InvalidRuleName(_)
+    );
+}
+
+#[test]
+fn register() {
+    let mut registry = RuleRegistry::new();
+    registry
+        .register("custom", "custom rule", false, || Box::new(CustomRule))
+        .unwrap();
+
+    assert_matches!(
+        registry
+            .register("close", "duplicate", true, || Box::new(CustomRule))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::DuplicateRuleName(_)
+    );
+    assert!(registry
+        .register("default", "reserved", true, || Box::new(CustomRule))
+        .is_err());
+
+    let entry = registry.get("custom").unwrap();
+    assert_eq!(entry.description(), "custom rule");
+    assert!(!entry.is_default());
+
+    assert_eq!(registry.default_rules().len(), 7);
+    assert_eq!(
+        names(&registry.parse("default,-loop,+custom").unwrap()),
+        vec![
+            "close",
+            "test",
+            "derive",
+            "unreachable",
+            "assert",
+            "comment",
+            "custom"
+        ]
+    );
+    assert_eq!(names(&registry.parse("custom").unwrap()), vec!["custom"]);
+}
+
+#[test]
+fn cli() {
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = Command::new(&exe).arg("--list-rules").output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let names: Vec<_> = stdout
+        .lines()
+        .map(|l| l.split_whitespace().next().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "close",
+            "test",
+            "loop",
+            "derive",
+            "unreachable",
+            "assert",
+            "comment",
+            "all"
+        ]
+    );
+    assert!(stdout
+        .lines()
+        .last()
+        .unwrap()
+        .ends_with("(disabled by default)"));
+}
@@ -0,0 +1,94 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_covfix::{parser::LcovParser, CoverageReader, CoverageWriter};
+
+#[test]
+fn read_write() {
+    let ws = WorkSpace::from_template("tests/remap");
+
+    let mut parser = LcovParser::new(ws.path());
+    parser.add_remap("/build", ".");
+
+    let coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+    assert_eq!(
+        coverage.file_coverages()[0].path(),
+        ws.path().join("src/lib.rs")
+    );
+
+    let mut buf = Vec::new();
+    parser.write(&coverage, &mut buf).unwrap();
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov.info")).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), expected_content);
+}
+
+#[test]
+fn nested() {
+    let ws = WorkSpace::from_template("tests/remap");
+    let content = "TN:\nSF:/build/src/lib.rs\nDA:1,1\nend_of_record\n\
+                   TN:\nSF:/build/vendor/foo/lib.rs\nDA:1,1\nend_of_record\n";
+
+    // the longest prefix is used regardless of the order
+    for &reversed in &[false, true] {
+        let mut remaps = vec![("/build", "."), ("/build/vendor", "third_party")];
+        if reversed {
+            remaps.reverse();
+        }
+
+        let mut parser = LcovParser::new(ws.path());
+        for (from, to) in remaps {
+            parser.add_remap(from, to);
+        }
+
+        let coverage = parser.read(&mut content.as_bytes()).unwrap();
+        let paths: Vec<_> = coverage.file_coverages().iter().map(|f| f.path()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                ws.path().join("src/lib.rs"),
+                ws.path().join("third_party/foo/lib.rs")
+            ]
+        );
+
+        let mut buf = Vec::new();
+        parser.write(&coverage, &mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("SF:/build/src/lib.rs\n"));
+        assert!(output.contains("SF:/build/vendor/foo/lib.rs\n"));
+    }
+}
+
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/remap");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov3.info");
+
+    let status = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--remap")
+        .arg("/build=.")
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov.info"))
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov2.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
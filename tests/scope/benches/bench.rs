@@ -0,0 +1,3 @@
+pub fn bench() {
+    assert!(1 + 1 == 2);
+}
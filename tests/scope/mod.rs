@@ -0,0 +1,143 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rust_covfix::rule::{self, Rule, RuleRegistry};
+use rust_covfix::{parser::LcovParser, CoverageFixer, CoverageReader, CoverageWriter, RuleScope};
+
+fn names(rules: &[Box<dyn Rule>]) -> Vec<&str> {
+    rules.iter().map(|r| r.name()).collect()
+}
+
+#[test]
+fn rules_for() {
+    let mut fixer = CoverageFixer::with_rules(vec![rule::from_str("close").unwrap()]);
+
+    let mut scope = RuleScope::new(vec![rule::from_str("all").unwrap()]);
+    scope.add_pattern("src/generated");
+    fixer.add_scope(scope);
+
+    let mut scope = RuleScope::new(Vec::<Box<dyn Rule>>::new());
+    scope.add_pattern("src/**/raw.rs");
+    fixer.add_scope(scope);
+
+    assert_eq!(
+        names(fixer.rules_for(Path::new("src/lib.rs"))),
+        vec!["close"]
+    );
+    assert_eq!(
+        names(fixer.rules_for(Path::new("src/generated/mod.rs"))),
+        vec!["all"]
+    );
+
+    // scope added later takes precedence
+    assert!(fixer
+        .rules_for(Path::new("src/generated/raw.rs"))
+        .is_empty());
+
+    // paths are matched relative to the root directory
+    assert_eq!(
+        names(fixer.rules_for(Path::new("/project/src/generated/mod.rs"))),
+        vec!["close"]
+    );
+    fixer.set_root("/project");
+    assert_eq!(
+        names(fixer.rules_for(Path::new("/project/src/generated/mod.rs"))),
+        vec!["all"]
+    );
+}
+
+#[test]
+fn fix() {
+    let ws = WorkSpace::from_template("tests/scope");
+    let registry = RuleRegistry::new();
+
+    let mut fixer = CoverageFixer::with_rules(registry.parse("default,-loop").unwrap());
+    fixer.set_root(ws.path());
+    for (pattern, spec) in &[
+        ("src/generated", "all"),
+        ("src/ffi/**", "default,-loop,-close"),
+        ("benches", "default,-loop,-assert"),
+    ] {
+        let mut scope = RuleScope::new(registry.parse(spec).unwrap());
+        scope.add_pattern(*pattern);
+        fixer.add_scope(scope);
+    }
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+    let report = fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(report.files[1].rules.len(), 1);
+    assert_eq!(report.files[1].rule("all").unwrap().lines_removed, 3);
+    assert!(report.files[2].rule("close").is_none());
+    assert!(report.files[3].rule("assert").is_none());
+
+    let mut output = Vec::new();
+    parser.write(&coverage, &mut output).unwrap();
+    let expected = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn config() {
+    let ws = WorkSpace::from_template("tests/scope");
+    let config = rust_covfix::Config::discover(ws.path()).unwrap().unwrap();
+
+    assert_eq!(config.overrides.len(), 3);
+    assert_eq!(config.overrides[0].paths, vec!["src/generated"]);
+    assert_eq!(
+        config.overrides[0].rules_spec(Some("default,-loop")),
+        Some("all".to_owned())
+    );
+    assert_eq!(
+        config.overrides[1].rules_spec(Some("default,-loop")),
+        Some("default,-loop,-close".to_owned())
+    );
+    assert_eq!(
+        config.overrides[1].rules_spec(None),
+        Some("-close".to_owned())
+    );
+
+    let scopes = config.create_scopes(&RuleRegistry::new()).unwrap();
+    assert_eq!(
+        names(scopes[2].rules()),
+        vec!["close", "test", "derive", "unreachable", "comment"]
+    );
+
+    let content = "[[override]]\nrules = \"all\"\n";
+    assert!(rust_covfix::Config::from_toml(content, Path::new("")).is_err());
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/scope");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov_out.info");
+
+    let result = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov.info"))
+        .output()
+        .unwrap();
+
+    assert!(result.status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
@@ -0,0 +1,4 @@
+#[no_mangle]
+pub extern "C" fn ffi_add(a: i32, b: i32) -> i32 {
+    a + b
+}
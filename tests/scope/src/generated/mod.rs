@@ -0,0 +1,3 @@
+pub fn generated() -> i32 {
+    42
+}
@@ -0,0 +1,4 @@
+pub fn check(v: i32) -> i32 {
+    assert!(v >= 0);
+    v
+}
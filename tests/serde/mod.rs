@@ -0,0 +1,41 @@
+use pretty_assertions::assert_eq;
+
+use rust_covfix::{BranchCoverage, FileCoverage, LineCoverage, PackageCoverage};
+
+#[test]
+fn json_shape() {
+    let coverage = PackageCoverage::with_test_name(
+        "serde",
+        vec![FileCoverage::new(
+            "src/lib.rs",
+            vec![
+                LineCoverage {
+                    line_number: 1,
+                    count: Some(3),
+                },
+                LineCoverage {
+                    line_number: 2,
+                    count: None,
+                },
+            ],
+            vec![BranchCoverage {
+                line_number: 1,
+                block_number: Some(0),
+                taken: Some(true),
+            }],
+        )],
+    );
+
+    let json = serde_json::to_string(&coverage).unwrap();
+    assert_eq!(
+        json,
+        concat!(
+            r#"{"name":"serde","file_coverages":[{"path":"src/lib.rs","#,
+            r#""line_coverages":[{"line_number":1,"count":3},{"line_number":2,"count":null}],"#,
+            r#""branch_coverages":[{"line_number":1,"block_number":0,"taken":true}]}]}"#
+        )
+    );
+
+    let deserialized: PackageCoverage = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, coverage);
+}
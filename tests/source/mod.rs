@@ -0,0 +1,117 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+
+use rust_covfix::error::ErrorKind;
+use rust_covfix::{
+    parser::LcovParser, CoverageFixer, CoverageReader, CoverageWriter, MemorySourceProvider,
+    SourceProvider, TotalCoverage,
+};
+
+const SOURCE: &str = include_str!("../explain/src/lib.rs");
+
+#[test]
+fn memory() {
+    let ws = WorkSpace::from_template("tests/source");
+    assert!(!ws.path().join("src/lib.rs").exists());
+
+    let mut provider = MemorySourceProvider::new();
+    provider.insert(ws.path().join("./src/lib.rs"), SOURCE);
+    assert_eq!(
+        provider
+            .read_source(&ws.path().join("src/../src/lib.rs"))
+            .unwrap(),
+        SOURCE
+    );
+    assert_matches!(
+        provider
+            .read_source(&ws.path().join("src/main.rs"))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::SourceFileNotFound(_)
+    );
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    let mut fixer = CoverageFixer::new();
+    fixer.set_source_provider(provider);
+    fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(coverage.line_total(), 5);
+
+    let mut output = Vec::new();
+    parser.write(&coverage, &mut output).unwrap();
+    let expected_content = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), expected_content);
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn archive() {
+    use rust_covfix::ArchiveSourceProvider;
+
+    let ws = WorkSpace::from_template("tests/source");
+
+    let mut provider = ArchiveSourceProvider::new(ws.path());
+    provider
+        .load_file(&ws.path().join("sources.tar.gz"))
+        .unwrap();
+    assert_eq!(provider.len(), 1);
+    assert_eq!(
+        provider.read_source(&ws.path().join("src/lib.rs")).unwrap(),
+        SOURCE
+    );
+
+    let mut provider = ArchiveSourceProvider::new(ws.path());
+    provider.set_strip_components(1);
+    provider.load_file(&ws.path().join("sources.zip")).unwrap();
+    assert_eq!(
+        provider.read_source(&ws.path().join("src/lib.rs")).unwrap(),
+        SOURCE
+    );
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    let mut fixer = CoverageFixer::new();
+    fixer.set_source_provider(provider);
+    fixer.fix(&mut coverage).unwrap();
+
+    assert_eq!(coverage.line_total(), 5);
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn cli() {
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    let ws = WorkSpace::from_template("tests/source");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov_out.info");
+
+    let status = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--source-archive")
+        .arg(ws.path().join("sources.tar.gz"))
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov.info"))
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_fixed.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
@@ -0,0 +1,12 @@
+/// multiply two numbers
+pub fn mul(a: i32, b: i32) -> i32 {
+    a * b
+}
+
+pub fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+pub fn sub(a: i32, b: i32) -> i32 {
+    b - a
+}
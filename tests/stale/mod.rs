@@ -0,0 +1,182 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rust_covfix::{
+    detect_stale, parser::LcovParser, CoverageFixer, CoverageReader, FileCoverage,
+    FsSourceProvider, GitSourceProvider, LineMap, MemorySourceProvider, StaleFile, StaleReason,
+};
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// commit `lib_old.rs` as `src/lib.rs`, and then overwrite it with `lib_new.rs`
+fn setup() -> WorkSpace {
+    let ws = WorkSpace::from_template("tests/stale");
+
+    fs::create_dir(ws.path().join("src")).unwrap();
+    fs::copy(ws.path().join("lib_old.rs"), ws.path().join("src/lib.rs")).unwrap();
+
+    git(ws.path(), &["init", "-q"]);
+    git(ws.path(), &["add", "src/lib.rs"]);
+    git(ws.path(), &["commit", "-q", "-m", "initial"]);
+
+    fs::copy(ws.path().join("lib_new.rs"), ws.path().join("src/lib.rs")).unwrap();
+    ws
+}
+
+#[test]
+fn checksum_mismatch() {
+    let ws = WorkSpace::from_template("tests/stale");
+    let old = fs::read_to_string(ws.path().join("lib_old.rs")).unwrap();
+    let new = fs::read_to_string(ws.path().join("lib_new.rs")).unwrap();
+
+    let parser = LcovParser::new(ws.path());
+    let coverage = parser
+        .read_from_file(&ws.path().join("lcov_checksum.info"))
+        .unwrap();
+    let file_cov = &coverage.file_coverages()[0];
+
+    assert_eq!(detect_stale(file_cov, &old), vec![]);
+    assert_eq!(
+        detect_stale(file_cov, &new),
+        vec![StaleReason::ChecksumMismatch {
+            line_numbers: vec![1, 2, 3, 5, 6, 7]
+        }]
+    );
+
+    let mut coverage = coverage;
+    let mut provider = MemorySourceProvider::new();
+    provider.insert(ws.path().join("src/lib.rs"), new);
+
+    let mut fixer = CoverageFixer::new();
+    fixer.set_source_provider(provider);
+    let report = fixer.fix(&mut coverage).unwrap();
+    assert_eq!(report.files[0].stale.len(), 1);
+
+    // checksums at invalid line numbers are ignored
+    let mut file_cov = FileCoverage::new("src/lib.rs", vec![], vec![]);
+    file_cov.set_line_checksum(0, "invalid");
+    assert_eq!(detect_stale(&file_cov, &old), vec![]);
+}
+
+#[test]
+fn line_past_end() {
+    let ws = WorkSpace::from_template("tests/stale");
+
+    let parser = LcovParser::new(ws.path());
+    let coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+    let file_cov = &coverage.file_coverages()[0];
+
+    assert_eq!(
+        detect_stale(file_cov, "fn main() {}\n"),
+        vec![StaleReason::LinePastEnd {
+            line_number: 7,
+            line_count: 1
+        }]
+    );
+}
+
+#[test]
+fn remap_lines() {
+    let ws = WorkSpace::from_template("tests/stale");
+    let old = fs::read_to_string(ws.path().join("lib_old.rs")).unwrap();
+    let new = fs::read_to_string(ws.path().join("lib_new.rs")).unwrap();
+
+    let map = LineMap::new(&old, &new);
+    assert_eq!(map.map(1), Some(6));
+    assert_eq!(map.map(5), Some(10));
+    assert_eq!(map.map(6), None);
+    assert_eq!(map.map(7), Some(12));
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser
+        .read_from_file(&ws.path().join("lcov_checksum.info"))
+        .unwrap();
+    let file_cov = &mut coverage.file_coverages[0];
+
+    assert_eq!(file_cov.remap_lines(&map), vec![6]);
+    assert_eq!(file_cov.line_coverage(6).unwrap().count, Some(1));
+    assert_eq!(file_cov.line_coverage(12).unwrap().count, Some(0));
+    assert_eq!(detect_stale(file_cov, &new), vec![]);
+}
+
+#[test]
+fn check_stale_sources() {
+    let ws = setup();
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    let recorded = GitSourceProvider::new(ws.path(), "HEAD").unwrap();
+    assert_eq!(
+        coverage.check_stale_sources(&recorded, &recorded, true),
+        vec![]
+    );
+
+    // files which cannot be read are not checked
+    let current = MemorySourceProvider::new();
+    assert_eq!(
+        coverage.check_stale_sources(&recorded, &current, true),
+        vec![]
+    );
+
+    let path = coverage.file_coverages()[0].path().to_owned();
+    assert_eq!(
+        coverage.check_stale_sources(&recorded, &FsSourceProvider::new(), true),
+        vec![StaleFile {
+            path,
+            reasons: vec![StaleReason::ContentChanged],
+            dropped_lines: vec![6],
+        }]
+    );
+    assert_eq!(
+        coverage.file_coverages()[0].line_coverage(6).unwrap().count,
+        Some(1)
+    );
+}
+
+#[test]
+fn cli() {
+    let ws = setup();
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov_out.info");
+
+    let result = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--coverage-rev")
+        .arg("HEAD")
+        .arg("--remap-stale")
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov.info"))
+        .output()
+        .unwrap();
+
+    assert!(result.status.success());
+
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("has changed since the coverage was collected"));
+    assert!(stderr.contains("Dropped 1 lines"));
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_remapped.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
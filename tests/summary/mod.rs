@@ -0,0 +1,105 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+
+use std::fs;
+
+use rust_covfix::{
+    parser::LcovParser, summarize_by_directory, summarize_by_directory_with, summarize_by_module,
+    summarize_by_module_with, CoverageReader, CoverageSummary, MemorySourceProvider,
+};
+
+#[test]
+fn by_directory() {
+    let ws = WorkSpace::from_template("tests/summary");
+
+    let parser = LcovParser::new(ws.path());
+    let coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    let tree = summarize_by_directory(&coverage, ws.path());
+    assert_eq!(tree.function_total, 4);
+    assert_eq!(tree.function_executed, 2);
+
+    let net = tree.find("src/net").unwrap();
+    assert_eq!(
+        net.summary,
+        CoverageSummary {
+            line_executed: 3,
+            line_total: 6,
+            branch_executed: 1,
+            branch_total: 2,
+        }
+    );
+    assert_eq!(net.function_executed, 1);
+    assert_eq!(net.function_total, 2);
+
+    let http = tree.find("src/net/http.rs").unwrap();
+    assert_eq!(http.summary.line_executed, 3);
+    assert_eq!(http.summary.line_total, 4);
+}
+
+#[test]
+fn by_module() {
+    let ws = WorkSpace::from_template("tests/summary");
+
+    let parser = LcovParser::new(ws.path());
+    let coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    let tree = summarize_by_module(&coverage, &ws.path().join("src/lib.rs")).unwrap();
+    assert_eq!(tree.name, "crate");
+    assert_eq!(tree.summary.line_total, 10);
+    assert_eq!(tree.function_total, 4);
+
+    let names: Vec<_> = tree.children.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["util", "net"]);
+
+    let util = tree.find("crate::util").unwrap();
+    assert_eq!(util.summary.line_executed, 0);
+    assert_eq!(util.summary.line_total, 2);
+    assert_eq!(util.function_total, 1);
+    assert_eq!(util.function_executed, 0);
+
+    let net = tree.find("net").unwrap();
+    assert_eq!(net.summary.line_executed, 3);
+    assert_eq!(net.summary.line_total, 6);
+
+    let http = tree.find("crate::net::http").unwrap();
+    assert_eq!(
+        http.summary,
+        CoverageSummary {
+            line_executed: 3,
+            line_total: 4,
+            branch_executed: 1,
+            branch_total: 2,
+        }
+    );
+    assert_eq!(http.function_executed, 1);
+    assert_eq!(http.function_total, 1);
+}
+
+#[test]
+fn provider() {
+    let ws = WorkSpace::from_template("tests/summary");
+
+    let parser = LcovParser::new(ws.path());
+    let coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    // source files are read only from the provider
+    let mut provider = MemorySourceProvider::new();
+    for file in &["src/lib.rs", "src/net/mod.rs", "src/net/http.rs"] {
+        let path = ws.path().join(file);
+        provider.insert(&path, fs::read_to_string(&path).unwrap());
+    }
+    fs::remove_dir_all(ws.path().join("src")).unwrap();
+
+    let tree = summarize_by_directory_with(&coverage, ws.path(), &provider);
+    assert_eq!(tree.function_total, 4);
+    assert_eq!(tree.function_executed, 2);
+
+    let tree =
+        summarize_by_module_with(&coverage, &ws.path().join("src/lib.rs"), &provider).unwrap();
+    assert_eq!(tree.summary.line_total, 10);
+    assert_eq!(tree.function_total, 4);
+    let names: Vec<_> = tree.children.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["util", "net"]);
+    assert_eq!(tree.find("crate::net::http").unwrap().function_total, 1);
+}
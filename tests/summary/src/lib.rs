@@ -0,0 +1,11 @@
+pub mod net;
+
+pub fn hello() -> i32 {
+    1
+}
+
+mod util {
+    pub fn unused() -> i32 {
+        2
+    }
+}
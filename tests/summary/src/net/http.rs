@@ -0,0 +1,7 @@
+pub fn get(ok: bool) -> i32 {
+    if ok {
+        200
+    } else {
+        404
+    }
+}
@@ -63,10 +63,33 @@ fn copy_dir_contents(p1: &Path, p2: &Path) {
 }
 
 // test modules
+mod cache;
+#[cfg(feature = "config")]
+mod config;
+mod diff;
+mod explain;
+mod filter;
 mod fix;
+mod git_source;
 mod guess_game;
 mod invalid_operations;
+mod merge;
 mod multiple_files;
+mod outside_root;
+#[cfg(feature = "pattern")]
+mod pattern;
+#[cfg(all(unix, feature = "plugin"))]
+mod plugin;
+mod query;
 mod read_lcov;
+mod registry;
+mod remap;
+mod scope;
+#[cfg(feature = "serde")]
+mod serde;
+mod source;
+mod stale;
+mod summary;
+mod validate;
 mod workspace;
 mod write_lcov;
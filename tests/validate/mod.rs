@@ -0,0 +1,191 @@
+use super::WorkSpace;
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_covfix::{
+    parser::LcovParser, CoverageReader, TotalCoverage, ValidationFinding, ValidationIssue,
+};
+
+#[test]
+fn validate() {
+    let ws = WorkSpace::from_template("tests/validate");
+
+    let parser = LcovParser::new(ws.path());
+    let coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    let path = ws.path().join("src/lib.rs");
+    let finding = |issue| ValidationFinding {
+        path: path.clone(),
+        issue,
+    };
+
+    let expected = vec![
+        finding(ValidationIssue::DuplicateFile { records: 2 }),
+        finding(ValidationIssue::DuplicateLine {
+            line_number: 2,
+            entries: 2,
+        }),
+        finding(ValidationIssue::LineOutOfRange {
+            line_number: 12,
+            line_count: 7,
+        }),
+        finding(ValidationIssue::BranchTakenOnUnexecutedLine { line_number: 5 }),
+        finding(ValidationIssue::LineTotalMismatch {
+            declared: 4,
+            actual: 6,
+        }),
+        finding(ValidationIssue::LineExecutedMismatch {
+            declared: 3,
+            actual: 4,
+        }),
+    ];
+
+    assert_eq!(coverage.validate(), expected);
+}
+
+#[test]
+fn repair() {
+    let ws = WorkSpace::from_template("tests/validate");
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser.read_from_file(&ws.path().join("lcov.info")).unwrap();
+
+    let path = ws.path().join("src/lib.rs");
+    let finding = |issue| ValidationFinding {
+        path: path.clone(),
+        issue,
+    };
+
+    let expected = vec![
+        finding(ValidationIssue::DuplicateFile { records: 2 }),
+        finding(ValidationIssue::DuplicateLine {
+            line_number: 2,
+            entries: 2,
+        }),
+        finding(ValidationIssue::LineOutOfRange {
+            line_number: 12,
+            line_count: 7,
+        }),
+    ];
+
+    assert_eq!(coverage.repair(), expected);
+    assert_eq!(coverage.file_coverages().len(), 1);
+    assert_eq!(coverage.line_total(), 5);
+
+    let remaining: Vec<_> = coverage.validate().into_iter().map(|f| f.issue).collect();
+    assert_eq!(
+        remaining,
+        vec![ValidationIssue::BranchTakenOnUnexecutedLine { line_number: 5 }]
+    );
+}
+
+#[test]
+fn duplicate_branches() {
+    let ws = WorkSpace::from_template("tests/validate");
+
+    let parser = LcovParser::new(ws.path());
+    let mut coverage = parser
+        .read_from_file(&ws.path().join("lcov_branches.info"))
+        .unwrap();
+
+    let issues: Vec<_> = coverage.validate().into_iter().map(|f| f.issue).collect();
+    assert_eq!(
+        issues,
+        vec![
+            ValidationIssue::DuplicateLine {
+                line_number: 5,
+                entries: 2,
+            },
+            ValidationIssue::DuplicateBranch {
+                line_number: 5,
+                block_number: Some(0),
+                entries: 2,
+            },
+        ]
+    );
+
+    let repaired: Vec<_> = coverage.repair().into_iter().map(|f| f.issue).collect();
+    assert_eq!(repaired.len(), 2);
+    assert!(coverage.validate().is_empty());
+
+    // the branches of each copy are merged in order, and the other lines are kept
+    let taken: Vec<_> = coverage.file_coverages()[0]
+        .branch_coverages()
+        .iter()
+        .map(|v| (v.line_number, v.taken))
+        .collect();
+    assert_eq!(
+        taken,
+        vec![
+            (5, Some(true)),
+            (5, Some(true)),
+            (6, Some(true)),
+            (6, Some(false))
+        ]
+    );
+}
+
+#[test]
+fn cli() {
+    let ws = WorkSpace::from_template("tests/validate");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov_out.info");
+
+    let status = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--rules")
+        .arg("")
+        .arg("--repair")
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov.info"))
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_repaired.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
+
+#[test]
+fn cli_nofix() {
+    let ws = WorkSpace::from_template("tests/validate");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let output = ws.path().join("lcov_out.info");
+
+    let status = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--no-fix")
+        .arg("--repair")
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov.info"))
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_repaired.info")).unwrap();
+    let content = fs::read_to_string(output).unwrap();
+    assert_eq!(content, expected_content);
+}
@@ -2,7 +2,9 @@ use super::WorkSpace;
 use pretty_assertions::assert_eq;
 use std::fs;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, Stdio};
+
+use rust_covfix::{parser::LcovParser, CoverageReader, CoverageSummary, Workspace};
 
 #[test]
 fn all_rules() {
@@ -42,3 +44,147 @@ fn all_rules() {
 
     assert_eq!(content, expected_content);
 }
+
+#[test]
+fn group() {
+    let ws = WorkSpace::from_template("tests/workspace");
+
+    let workspace = Workspace::from_cargo_metadata(ws.path()).unwrap();
+    let names: Vec<_> = workspace.members().iter().map(|m| m.name()).collect();
+    assert_eq!(names, vec!["covfix-test1", "covfix-test2"]);
+
+    let parser = LcovParser::new(ws.path());
+    let coverage = parser
+        .read_from_file(&ws.path().join("lcov_split.info"))
+        .unwrap();
+    let grouped = workspace.group(coverage);
+
+    assert!(grouped.others().is_empty());
+    assert_eq!(
+        grouped.summaries(),
+        vec![
+            (
+                "covfix-test1",
+                CoverageSummary {
+                    line_executed: 2,
+                    line_total: 2,
+                    branch_executed: 0,
+                    branch_total: 0,
+                }
+            ),
+            (
+                "covfix-test2",
+                CoverageSummary {
+                    line_executed: 2,
+                    line_total: 3,
+                    branch_executed: 0,
+                    branch_total: 0,
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn split_by_crate() {
+    let ws = WorkSpace::from_template("tests/workspace");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let out_dir = ws.path().join("coverage");
+
+    let status = Command::new(&exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--split-by-crate")
+        .arg(&out_dir)
+        .arg(ws.path().join("lcov_split.info"))
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    assert!(out_dir.join("covfix-test1.info").is_file());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_split_test2.info")).unwrap();
+    let content = fs::read_to_string(out_dir.join("covfix-test2.info")).unwrap();
+    assert_eq!(content, expected_content);
+}
+
+#[test]
+fn split_by_crate_relative_root() {
+    let ws = WorkSpace::from_template("tests/workspace");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let mut content = fs::read_to_string(ws.path().join("lcov_split.info")).unwrap();
+    content.push_str("SF:build.rs\nDA:1,1\nend_of_record\n");
+    fs::write(ws.path().join("lcov_split.info"), content).unwrap();
+    fs::write(ws.path().join("build.rs"), "fn main() {}\n").unwrap();
+
+    let parent = ws.path().parent().unwrap();
+    let root = ws.path().file_name().unwrap();
+    let out_dir = ws.path().join("coverage");
+
+    let status = Command::new(exe)
+        .current_dir(parent)
+        .arg("--root")
+        .arg(root)
+        .arg("--split-by-crate")
+        .arg(&out_dir)
+        .arg(PathBuf::from(root).join("lcov_split.info"))
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let expected_content = fs::read_to_string(ws.path().join("lcov_split_test2.info")).unwrap();
+    let content = fs::read_to_string(out_dir.join("covfix-test2.info")).unwrap();
+    assert_eq!(content, expected_content);
+
+    // files outside the members are not dropped
+    let content = fs::read_to_string(out_dir.join("others.info")).unwrap();
+    assert!(content.contains("SF:build.rs\n"));
+}
+
+#[test]
+fn split_by_crate_with_output() {
+    let ws = WorkSpace::from_template("tests/workspace");
+
+    let mut exe = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    if cfg!(windows) {
+        exe.push("target\\debug\\rust-covfix");
+    } else {
+        exe.push("target/debug/rust-covfix");
+    }
+
+    let out_dir = ws.path().join("coverage");
+    let output = ws.path().join("lcov_out.info");
+
+    let status = Command::new(exe)
+        .arg("--root")
+        .arg(ws.path())
+        .arg("--split-by-crate")
+        .arg(&out_dir)
+        .arg("-o")
+        .arg(&output)
+        .arg(ws.path().join("lcov_split.info"))
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+
+    assert!(!status.success());
+    assert!(!out_dir.exists());
+    assert!(!output.exists());
+}